@@ -0,0 +1,189 @@
+//! Backs off checking feeds that have failed repeatedly, persisting each
+//! feed's consecutive failures in a sidecar file within its cache directory.
+
+use std::fs::OpenOptions;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{cache_failures_file_path, ensure_cache_dir, Error, ErrorKind};
+
+/// Configures how long a repeatedly failing feed goes unchecked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffPolicy {
+    /// consecutive failures tolerated before backing off.
+    pub grace_failures: u32,
+    /// the backoff following the first failure beyond the grace failures,
+    /// doubling with each further failure.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            grace_failures: 1,
+            base_delay: Duration::from_secs(15 * 60),
+            max_delay: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Returns how long a feed goes unchecked after `consecutive_failures`.
+    fn delay_for_failures(&self, consecutive_failures: u32) -> Duration {
+        match consecutive_failures.checked_sub(self.grace_failures + 1) {
+            None => Duration::ZERO,
+            Some(doublings) => self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(doublings))
+                .min(self.max_delay),
+        }
+    }
+}
+
+/// A feed's failures since it was last checked successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailureState {
+    pub consecutive_failures: u32,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+impl FailureState {
+    /// Returns when the feed may next be checked, if it is backing off.
+    fn backoff_until(&self, policy: &BackoffPolicy) -> Option<DateTime<Utc>> {
+        let delay = policy.delay_for_failures(self.consecutive_failures);
+
+        chrono::TimeDelta::from_std(delay)
+            .ok()
+            .filter(|delay| !delay.is_zero())
+            .and_then(|delay| self.last_failed_at.checked_add_signed(delay))
+    }
+}
+
+/// Loads a feed's failure state from `cache_path`, treating a missing or
+/// unreadable sidecar as the feed not having failed.
+fn load_failure_state(cache_path: &Path, feed_name: &str) -> Option<FailureState> {
+    let failures_file = OpenOptions::new()
+        .read(true)
+        .open(cache_failures_file_path(cache_path, feed_name))
+        .ok()?;
+
+    serde_json::from_reader(BufReader::new(failures_file))
+        .map_err(|err| {
+            log::debug!(
+                "ignoring invalid failure state for feed[{}]: {}",
+                feed_name,
+                err
+            )
+        })
+        .ok()
+}
+
+/// Returns whether a feed failed too recently to be checked as of `now`.
+pub(crate) fn is_backing_off(
+    cache_path: &Path,
+    feed_name: &str,
+    policy: &BackoffPolicy,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(state) = load_failure_state(cache_path, feed_name) else {
+        return false;
+    };
+
+    match state.backoff_until(policy) {
+        Some(backoff_until) if now < backoff_until => {
+            log::info!(
+                "feed[{}] failed {} times in a row, skipping until {}",
+                feed_name,
+                state.consecutive_failures,
+                backoff_until.to_rfc3339()
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Records the outcome of checking a feed, counting another failure or
+/// resetting its failures on success.
+pub(crate) fn record_check_outcome<T>(
+    cache_path: &Path,
+    feed_name: &str,
+    outcome: &Result<T, Error>,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let failures_file_path = cache_failures_file_path(cache_path, feed_name);
+    let io_error = |err| Error::from(err).with_data(format!("feed[{}]", feed_name));
+
+    if outcome.is_ok() {
+        return match std::fs::remove_file(&failures_file_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(io_error(err)),
+            _ => Ok(()),
+        };
+    }
+
+    let consecutive_failures = load_failure_state(cache_path, feed_name)
+        .map_or(0, |state| state.consecutive_failures)
+        .saturating_add(1);
+    // a feed failing before it was ever cached may lack its shard directory.
+    ensure_cache_dir(cache_path)?;
+    let failures_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&failures_file_path)
+        .map_err(io_error)?;
+
+    serde_json::to_writer(
+        failures_file,
+        &FailureState {
+            consecutive_failures,
+            last_failed_at: now,
+        },
+    )
+    .map_err(|err| Error::new(ErrorKind::JsonErr(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_double_the_backoff_with_each_failure_beyond_the_grace_failures() {
+        let policy = BackoffPolicy::default();
+
+        assert_eq!(policy.delay_for_failures(0), Duration::ZERO);
+        assert_eq!(policy.delay_for_failures(1), Duration::ZERO);
+        assert_eq!(policy.delay_for_failures(2), Duration::from_secs(15 * 60));
+        assert_eq!(policy.delay_for_failures(3), Duration::from_secs(30 * 60));
+        assert_eq!(policy.delay_for_failures(7), policy.max_delay);
+        assert_eq!(policy.delay_for_failures(u32::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn should_back_off_after_repeated_failures_until_a_success() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let policy = BackoffPolicy::default();
+        let now = Utc::now();
+        let failure: Result<(), Error> = Err(Error::new(ErrorKind::Timeout("test".to_string())));
+        let record =
+            |outcome| record_check_outcome(cache_dir.path(), "test", outcome, now).unwrap();
+        let is_backing_off_at =
+            |at: DateTime<Utc>| is_backing_off(cache_dir.path(), "test", &policy, at);
+
+        record(&failure);
+        assert!(!is_backing_off_at(now));
+
+        record(&failure);
+        assert!(is_backing_off_at(now));
+        assert!(!is_backing_off_at(now + chrono::TimeDelta::minutes(15)));
+
+        record(&Ok(()));
+        assert!(!is_backing_off_at(now));
+        assert!(!cache_failures_file_path(cache_dir.path(), "test").exists());
+    }
+}
@@ -0,0 +1,116 @@
+//! Parses the durations and cutoffs given to time based options.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// The seconds in each supported duration unit.
+const DURATION_UNITS: [(&str, u64); 5] = [
+    ("s", 1),
+    ("m", 60),
+    ("h", 60 * 60),
+    ("d", 24 * 60 * 60),
+    ("w", 7 * 24 * 60 * 60),
+];
+
+/// Parses a duration given as a whole number of seconds, minutes, hours, days
+/// or weeks, such as `30s`, `15m`, `2h` or `7d`. A bare number is taken as
+/// seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || format!("expected a duration like 30s, 15m, 2h or 7d: {}", duration);
+
+    let count_len = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (count, unit) = duration.split_at(count_len);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        "" => 1,
+        unit => DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, secs)| *secs)
+            .ok_or_else(invalid)?,
+    };
+
+    count
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
+}
+
+/// Parses a cutoff given either as a RFC3339 timestamp or as a duration
+/// before now, as accepted by [parse_duration].
+pub fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(since) = DateTime::parse_from_rfc3339(since) {
+        return Ok(since.with_timezone(&Utc));
+    }
+
+    let invalid = || {
+        format!(
+            "expected a RFC3339 timestamp or a relative age like 7d: {}",
+            since
+        )
+    };
+    let age = parse_duration(since).map_err(|_| invalid())?;
+
+    chrono::TimeDelta::from_std(age)
+        .ok()
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_durations_in_each_unit() {
+        for (duration, secs) in [
+            ("30s", 30),
+            ("15m", 15 * 60),
+            ("2h", 2 * 60 * 60),
+            ("7d", 7 * 24 * 60 * 60),
+            ("1w", 7 * 24 * 60 * 60),
+            ("300", 300),
+            ("0s", 0),
+        ] {
+            assert_eq!(parse_duration(duration), Ok(Duration::from_secs(secs)));
+        }
+    }
+
+    #[test]
+    fn should_reject_malformed_durations() {
+        for duration in [
+            "",
+            "s",
+            "-5s",
+            "1.5h",
+            "5x",
+            "5 m",
+            "m5",
+            "5ms",
+            "99999999999999999999w",
+        ] {
+            assert_eq!(
+                parse_duration(duration),
+                Err(format!(
+                    "expected a duration like 30s, 15m, 2h or 7d: {}",
+                    duration
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn should_parse_since_as_a_timestamp_or_relative_age() {
+        let timestamp = parse_since("2004-10-26T14:06:44+02:00").unwrap();
+        assert_eq!(timestamp.to_rfc3339(), "2004-10-26T12:06:44+00:00");
+
+        let since = parse_since("2h").unwrap();
+        let age = Utc::now() - since;
+        assert!(age >= chrono::TimeDelta::hours(2) && age < chrono::TimeDelta::hours(3));
+
+        assert!(parse_since("yesterday").is_err());
+    }
+}
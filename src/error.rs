@@ -2,65 +2,359 @@ use std::ffi::OsString;
 
 use url::ParseError;
 
+/// A [Send] representation of an [atom_syndication::Error], whose xml variant
+/// wraps a non-[Send] error. Io failures are preserved as an [std::io::Error]
+/// so they remain distinguishable from parse failures.
+#[derive(Debug)]
+pub enum AtomError {
+    Io(std::io::Error),
+    Xml(String),
+    InvalidStartTag,
+    Eof,
+    WrongDatetime(String),
+    WrongAttribute {
+        attribute: &'static str,
+        value: String,
+    },
+    /// any variant introduced by atom_syndication after this was written.
+    Other(String),
+}
+
+impl From<atom_syndication::Error> for AtomError {
+    fn from(err: atom_syndication::Error) -> Self {
+        use atom_syndication::Error as AtomSyndicationError;
+        use std::error::Error as _;
+
+        match err {
+            AtomSyndicationError::Xml(xml_err) => {
+                let mut source = xml_err.source();
+                while let Some(err) = source {
+                    if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                        return Self::Io(std::io::Error::new(io_err.kind(), io_err.to_string()));
+                    }
+                    source = err.source();
+                }
+
+                Self::Xml(xml_err.to_string())
+            }
+            AtomSyndicationError::InvalidStartTag => Self::InvalidStartTag,
+            AtomSyndicationError::Eof => Self::Eof,
+            AtomSyndicationError::WrongDatetime(datetime) => Self::WrongDatetime(datetime),
+            AtomSyndicationError::WrongAttribute { attribute, value } => {
+                Self::WrongAttribute { attribute, value }
+            }
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for AtomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // mirrors the display of the original atom_syndication::Error.
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Xml(err) | Self::Other(err) => write!(f, "{}", err),
+            Self::InvalidStartTag => write!(f, "input did not begin with an opening feed tag"),
+            Self::Eof => write!(f, "unexpected end of input"),
+            Self::WrongDatetime(datetime) => write!(
+                f,
+                "timestamps must be formatted by RFC3339, rather than {}",
+                datetime
+            ),
+            Self::WrongAttribute { attribute, value } => write!(
+                f,
+                "Unsupported value of attribute {}: '{}'.",
+                attribute, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ErrorKind {
-    FeedIsNeitherAtomOrRss(String),
+    /// a feed's body parsed as none of the supported formats.
+    FeedIsNeitherAtomOrRss {
+        feed_name: String,
+        /// the response's `Content-Type`, if it declared one.
+        content_type: Option<String>,
+        /// the start of the body, showing what was received in its place,
+        /// such as an html error or login page.
+        snippet: String,
+    },
     InvalidCache(String),
-    InvalidUrl { reason: ParseError, url: String },
+    /// the cache directory is locked by another run.
+    CacheLocked(String),
+    InvalidUrl {
+        reason: ParseError,
+        url: String,
+    },
+    InvalidConfig {
+        reason: Box<toml::de::Error>,
+        file_name: String,
+    },
+    InvalidLinkPattern {
+        reason: regex::Error,
+        pattern: String,
+    },
+    InvalidOpml(String),
+    /// a configured request header, identified by name alone as its value may
+    /// be sensitive.
+    InvalidHeader {
+        reason: String,
+        name: String,
+    },
     DuplicateFeed(String),
+    /// no feed matched any of the given feed name globs.
+    NoFeedsSelected(Vec<String>),
+    UnsafeFeedName(String),
+    Timeout(String),
+    TooManyRedirects(String),
+    BodyTooLarge(String),
+    /// a feed url with a scheme that is neither fetched nor read from disk.
+    UnsupportedScheme {
+        feed_name: String,
+        scheme: String,
+        /// the schemes a fetcher is registered for.
+        supported: Vec<&'static str>,
+    },
+    /// the environment variable a feed's bearer token is read from is unset.
+    MissingTokenEnv {
+        feed_name: String,
+        var: String,
+    },
+    /// a feed responded with an unsuccessful status code.
+    HttpStatus {
+        feed_name: String,
+        status: reqwest::StatusCode,
+    },
+    /// a gemini feed responded with a status other than success or redirect.
+    #[cfg(feature = "gemini")]
+    GeminiStatus {
+        feed_name: String,
+        status: u8,
+        meta: String,
+    },
     IoErr(std::io::Error),
     InvalidFilename(OsString),
     ReqwestErr(reqwest::Error),
+    #[cfg(feature = "gemini")]
+    TlsErr(native_tls::Error),
     RssErr(rss::Error),
-    // converted to a string for [Send]
-    AtomErr(String),
+    AtomErr(AtomError),
+    JsonErr(serde_json::Error),
+    ThreadPoolErr(rayon::ThreadPoolBuildError),
+    #[cfg(feature = "async")]
+    TaskErr(tokio::task::JoinError),
+    /// many errors encountered together, such as across configuration files.
+    Multiple(Vec<Error>),
+}
+
+impl ErrorKind {
+    /// A stable, machine readable identifier for each kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::FeedIsNeitherAtomOrRss { .. } => "unknown_feed_format",
+            Self::InvalidCache(_) => "invalid_cache",
+            Self::CacheLocked(_) => "cache_locked",
+            Self::InvalidUrl { .. } => "invalid_url",
+            Self::InvalidConfig { .. } => "invalid_config",
+            Self::InvalidLinkPattern { .. } => "invalid_link_pattern",
+            Self::InvalidOpml(_) => "invalid_opml",
+            Self::InvalidHeader { .. } => "invalid_header",
+            Self::DuplicateFeed(_) => "duplicate_feed",
+            Self::NoFeedsSelected(_) => "no_feeds_selected",
+            Self::UnsafeFeedName(_) => "unsafe_feed_name",
+            Self::Timeout(_) => "timeout",
+            Self::TooManyRedirects(_) => "too_many_redirects",
+            Self::BodyTooLarge(_) => "body_too_large",
+            Self::UnsupportedScheme { .. } => "unsupported_scheme",
+            Self::MissingTokenEnv { .. } => "missing_token_env",
+            Self::HttpStatus { .. } => "http_status",
+            #[cfg(feature = "gemini")]
+            Self::GeminiStatus { .. } => "gemini_status",
+            Self::IoErr(_) => "io",
+            Self::InvalidFilename(_) => "invalid_filename",
+            Self::ReqwestErr(_) => "reqwest",
+            #[cfg(feature = "gemini")]
+            Self::TlsErr(_) => "tls",
+            Self::RssErr(_) => "rss",
+            Self::AtomErr(_) => "atom",
+            Self::JsonErr(_) => "json",
+            Self::ThreadPoolErr(_) => "thread_pool",
+            #[cfg(feature = "async")]
+            Self::TaskErr(_) => "task",
+            Self::Multiple(_) => "multiple",
+        }
+    }
 }
 
 impl std::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::FeedIsNeitherAtomOrRss(feed_name) => {
+            Self::FeedIsNeitherAtomOrRss {
+                feed_name,
+                content_type,
+                snippet,
+            } => {
                 write!(
                     f,
-                    "feed {} is neither an atom feed or RSS2.0 channel",
+                    "feed {} is neither an atom feed, RSS2.0 channel or JSON feed",
                     feed_name
-                )
+                )?;
+                if let Some(content_type) = content_type {
+                    write!(f, ", served as {}", content_type)?;
+                }
+                write!(f, ", starting {:?}", snippet)
             }
             Self::InvalidCache(feed_name) => {
                 write!(f, "feed {} has an invalid cache file", feed_name)
             }
+            Self::CacheLocked(cache_path) => {
+                write!(f, "cache {} is locked by another run", cache_path)
+            }
             Self::DuplicateFeed(feed_name) => {
                 write!(f, "feed {} is defined more than once", feed_name)
             }
+            Self::NoFeedsSelected(globs) => {
+                write!(f, "no feeds match any of {}", globs.join(", "))
+            }
+            Self::UnsafeFeedName(feed_name) => {
+                write!(
+                    f,
+                    "feed name {:?} may not contain path separators or `..`",
+                    feed_name
+                )
+            }
+            Self::Timeout(feed_name) => write!(f, "feed {} timed out", feed_name),
+            Self::BodyTooLarge(feed_name) => {
+                write!(
+                    f,
+                    "feed {} exceeded the maximum response body size",
+                    feed_name
+                )
+            }
+            Self::UnsupportedScheme {
+                feed_name,
+                scheme,
+                supported,
+            } => write!(
+                f,
+                "feed {} has an unsupported url scheme {:?}, expected one of {}",
+                feed_name,
+                scheme,
+                supported.join(", ")
+            ),
+            Self::MissingTokenEnv { feed_name, var } => write!(
+                f,
+                "feed {} requires a bearer token but environment variable {} is not set",
+                feed_name, var
+            ),
+            Self::HttpStatus { feed_name, status } => {
+                write!(f, "feed {} responded with status {}", feed_name, status)
+            }
+            #[cfg(feature = "gemini")]
+            Self::GeminiStatus {
+                feed_name,
+                status,
+                meta,
+            } => write!(
+                f,
+                "feed {} responded with gemini status {} {}",
+                feed_name, status, meta
+            ),
+            Self::TooManyRedirects(feed_name) => {
+                write!(
+                    f,
+                    "feed {} exceeded the maximum number of redirects",
+                    feed_name
+                )
+            }
             Self::InvalidUrl { reason, url } => write!(f, "{} for {}", reason, url),
+            Self::InvalidConfig { reason, file_name } => {
+                write!(f, "invalid config {}: {}", file_name, reason)
+            }
+            Self::InvalidLinkPattern { reason, pattern } => {
+                write!(f, "invalid link pattern {}: {}", pattern, reason)
+            }
+            Self::InvalidOpml(reason) => write!(f, "invalid opml: {}", reason),
+            Self::InvalidHeader { reason, name } => {
+                write!(f, "invalid header {:?}: {}", name, reason)
+            }
             Self::IoErr(err) => write!(f, "{}", err),
             Self::InvalidFilename(repr) => {
                 write!(f, "filename must be representable as utf-8: {:?}", repr)
             }
             Self::ReqwestErr(err) => write!(f, "{}", err),
+            #[cfg(feature = "gemini")]
+            Self::TlsErr(err) => write!(f, "{}", err),
             Self::RssErr(err) => write!(f, "{}", err),
             Self::AtomErr(err) => write!(f, "{}", err),
+            Self::JsonErr(err) => write!(f, "{}", err),
+            Self::ThreadPoolErr(err) => write!(f, "{}", err),
+            #[cfg(feature = "async")]
+            Self::TaskErr(err) => write!(f, "{}", err),
+            Self::Multiple(errs) => {
+                let errs: Vec<_> = errs.iter().map(|err| err.to_string()).collect();
+                write!(f, "{}", errs.join("; "))
+            }
         }
     }
 }
 
+/// Wraps the errors of the underlying libraries, so that `?` converts them
+/// directly. Errors needing context can still add it via [Error::with_data].
+impl From<std::io::Error> for ErrorKind {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoErr(err)
+    }
+}
+
+impl From<reqwest::Error> for ErrorKind {
+    fn from(err: reqwest::Error) -> Self {
+        Self::ReqwestErr(err)
+    }
+}
+
+impl From<rss::Error> for ErrorKind {
+    fn from(err: rss::Error) -> Self {
+        Self::RssErr(err)
+    }
+}
+
+impl From<atom_syndication::Error> for ErrorKind {
+    fn from(err: atom_syndication::Error) -> Self {
+        Self::AtomErr(err.into())
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
     pub kind: ErrorKind,
-    pub data: Option<String>,
+    /// additional information added by each layer the error passed through,
+    /// in the order it was added.
+    pub data: Vec<String>,
 }
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Self {
-        Self { kind, data: None }
+        Self { kind, data: vec![] }
     }
 
     /// Allows a caller to enrich an error with a string signifying additional
-    /// information about the error.
+    /// information about the error, retaining any added before it.
     pub fn with_data_mut<S: AsRef<str>>(&mut self, data: S) {
         let data = data.as_ref().to_string();
 
-        self.data = Some(data)
+        self.data.push(data)
     }
 
     /// Allows a caller to enrich an error with a string signifying additional
@@ -72,11 +366,158 @@ impl Error {
     }
 }
 
+impl<E: Into<ErrorKind>> From<E> for Error {
+    fn from(err: E) -> Self {
+        Self::new(err.into())
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.data {
-            Some(ctx) => write!(f, "{}: {}", &self.kind, ctx),
-            None => write!(f, "{}", &self.kind),
+        write!(f, "{}", &self.kind)?;
+        for ctx in &self.data {
+            write!(f, ": {}", ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::InvalidUrl { reason, .. } => Some(reason),
+            ErrorKind::InvalidConfig { reason, .. } => Some(reason.as_ref()),
+            ErrorKind::InvalidLinkPattern { reason, .. } => Some(reason),
+            ErrorKind::IoErr(err) => Some(err),
+            ErrorKind::ReqwestErr(err) => Some(err),
+            #[cfg(feature = "gemini")]
+            ErrorKind::TlsErr(err) => Some(err),
+            ErrorKind::RssErr(err) => Some(err),
+            ErrorKind::AtomErr(err) => Some(err),
+            ErrorKind::JsonErr(err) => Some(err),
+            ErrorKind::ThreadPoolErr(err) => Some(err),
+            #[cfg(feature = "async")]
+            ErrorKind::TaskErr(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_expose_underlying_error_as_source() {
+        use std::error::Error as _;
+
+        let err = Error::new(ErrorKind::IoErr(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "missing",
+        )))
+        .with_data("feed[test]");
+
+        let source = err.source().unwrap();
+
+        assert_eq!(source.to_string(), "missing");
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+        assert_eq!(err.to_string(), "missing: feed[test]");
+    }
+
+    #[test]
+    fn should_retain_data_added_by_each_layer() {
+        let err = Error::new(ErrorKind::IoErr(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "permission denied",
+        )))
+        .with_data("feed[blog]")
+        .with_data("writing cache");
+
+        assert_eq!(err.data, vec!["feed[blog]", "writing cache"]);
+        assert_eq!(
+            err.to_string(),
+            "permission denied: feed[blog]: writing cache"
+        );
+    }
+
+    #[test]
+    fn should_preserve_atom_parse_failures() {
+        let err = atom_syndication::Feed::read_from(&b"<rss></rss>"[..]).unwrap_err();
+
+        let atom_err = AtomError::from(err);
+
+        assert!(matches!(atom_err, AtomError::InvalidStartTag));
+        assert_eq!(
+            atom_err.to_string(),
+            "input did not begin with an opening feed tag"
+        );
+    }
+
+    #[test]
+    fn should_identify_error_kinds_by_stable_code() {
+        assert_eq!(ErrorKind::Timeout("test".to_string()).code(), "timeout");
+        assert_eq!(
+            ErrorKind::InvalidCache("test".to_string()).code(),
+            "invalid_cache"
+        );
+    }
+
+    #[test]
+    fn should_include_status_code_in_http_status_errors() {
+        let err = Error::new(ErrorKind::HttpStatus {
+            feed_name: "test".to_string(),
+            status: reqwest::StatusCode::NOT_FOUND,
+        });
+
+        assert_eq!(
+            err.to_string(),
+            "feed test responded with status 404 Not Found"
+        );
+        assert_eq!(err.kind.code(), "http_status");
+    }
+
+    #[test]
+    fn should_convert_underlying_errors_with_the_question_mark_operator() {
+        fn read_missing_file() -> Result<String, Error> {
+            Ok(std::fs::read_to_string("/nonexistent/rss_checker")?)
+        }
+        fn parse_rss() -> Result<rss::Channel, Error> {
+            Ok(rss::Channel::read_from(&b"<feed></feed>"[..])?)
         }
+        fn parse_atom() -> Result<atom_syndication::Feed, Error> {
+            Ok(atom_syndication::Feed::read_from(&b"<rss></rss>"[..])?)
+        }
+
+        assert!(matches!(
+            read_missing_file().map_err(|err| err.with_data("feeds-from")),
+            Err(Error {
+                kind: ErrorKind::IoErr(_),
+                data,
+            }) if data == ["feeds-from"]
+        ));
+        assert!(matches!(
+            parse_rss(),
+            Err(Error {
+                kind: ErrorKind::RssErr(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_atom(),
+            Err(Error {
+                kind: ErrorKind::AtomErr(AtomError::InvalidStartTag),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_have_no_source_for_errors_originating_in_this_crate() {
+        use std::error::Error as _;
+
+        let err = Error::new(ErrorKind::DuplicateFeed("test".to_string()));
+
+        assert!(err.source().is_none());
     }
 }
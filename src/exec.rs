@@ -0,0 +1,169 @@
+//! Runs a user supplied shell command for each new link, such as to download
+//! or archive it. Commands are only ever run when explicitly passed with
+//! `--exec`.
+
+use std::num::NonZeroUsize;
+use std::process::{Command, Stdio};
+
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+
+use crate::output::NewItemsByFeed;
+use crate::{Error, ErrorKind, FeedItem};
+
+/// The default number of commands run at once.
+pub const DEFAULT_EXEC_CONCURRENCY: usize = 4;
+
+/// A shell command run once per new link, with `{link}`, `{feed}` and
+/// `{title}` substituted by the link's values. Values are substituted
+/// already shell quoted, so placeholders shouldn't be quoted in the command.
+#[derive(Debug, Clone)]
+pub struct ExecHook {
+    pub command: String,
+    /// the maximum number of commands run at once.
+    pub max_concurrency: NonZeroUsize,
+}
+
+/// Quotes a value as a single POSIX shell word, so that links and titles,
+/// which are controlled by the feed, are never interpreted by the shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+impl ExecHook {
+    /// Returns the command run for a feed's new item. Every placeholder is
+    /// substituted in a single pass, so substituted values are never
+    /// themselves substituted.
+    fn command_for(&self, feed_name: &str, item: &FeedItem) -> String {
+        let placeholders = Regex::new(r"\{(link|feed|title)\}").expect("placeholders are valid");
+
+        placeholders
+            .replace_all(&self.command, |captures: &Captures| {
+                let value = match &captures[1] {
+                    "link" => item.link.as_str(),
+                    "feed" => feed_name,
+                    _ => item.title.as_deref().unwrap_or_default(),
+                };
+                shell_quote(value)
+            })
+            .into_owned()
+    }
+
+    /// Runs the command for every new link, at most `max_concurrency` at a
+    /// time. A command that fails to start, or exits unsuccessfully, is only
+    /// logged, so that one link never prevents the rest from being run.
+    pub fn run_for_new_links(&self, new_links: &NewItemsByFeed) -> Result<(), Error> {
+        let commands: Vec<_> = new_links
+            .iter()
+            .flat_map(|(feed_name, items)| {
+                items
+                    .iter()
+                    .map(move |item| (feed_name, item, self.command_for(feed_name, item)))
+            })
+            .collect();
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.max_concurrency.get())
+            .build()
+            .map_err(|err| Error::new(ErrorKind::ThreadPoolErr(err)))?;
+        pool.install(|| {
+            commands.par_iter().for_each(|(feed_name, item, command)| {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .stdin(Stdio::null())
+                    .status();
+
+                match status {
+                    Ok(status) if status.success() => {
+                        log::debug!("exec[{}]: ran for {}", feed_name, item.link)
+                    }
+                    Ok(status) => {
+                        log::warn!("exec[{}]: {} for {}", feed_name, status, item.link)
+                    }
+                    Err(err) => log::warn!(
+                        "exec[{}]: failed to run for {}: {}",
+                        feed_name,
+                        item.link,
+                        err
+                    ),
+                }
+            })
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn item(link: &str, title: Option<&str>) -> FeedItem {
+        FeedItem {
+            link: link.to_string(),
+            title: title.map(|title| title.to_string()),
+            published: None,
+            categories: vec![],
+        }
+    }
+
+    #[test]
+    fn should_substitute_shell_quoted_placeholders() {
+        let hook = ExecHook {
+            command: "archive --feed {feed} {link} {title}".to_string(),
+            max_concurrency: NonZeroUsize::MIN,
+        };
+
+        assert_eq!(
+            hook.command_for(
+                "blog",
+                &item("http://example.com/1", Some("it's {link}; rm -rf ~"))
+            ),
+            r"archive --feed 'blog' 'http://example.com/1' 'it'\''s {link}; rm -rf ~'"
+        );
+        assert_eq!(
+            hook.command_for("blog", &item("http://example.com/1", None)),
+            "archive --feed 'blog' 'http://example.com/1' ''"
+        );
+    }
+
+    #[test]
+    fn should_run_the_command_for_every_new_link_despite_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let hook = ExecHook {
+            command: format!(
+                "test {{link}} != http://example.com/2 || exit 3; echo {{feed}} {{link}} >> {}",
+                shell_quote(out.to_str().unwrap())
+            ),
+            max_concurrency: NonZeroUsize::new(2).unwrap(),
+        };
+        let new_links = NewItemsByFeed::from([(
+            "blog".to_string(),
+            BTreeSet::from([
+                item("http://example.com/1", None),
+                item("http://example.com/2", None),
+                item("http://example.com/3", None),
+            ]),
+        )]);
+
+        hook.run_for_new_links(&new_links).unwrap();
+
+        let mut lines: Vec<_> = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec!["blog http://example.com/1", "blog http://example.com/3"]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use regex::Regex;
+
+/// Restricts the new links reported for a feed to those matching at least one
+/// include pattern, if any are configured, and no exclude patterns. Excludes
+/// take precedence over includes.
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl LinkFilter {
+    /// Compiles a filter from lists of include and exclude regex patterns.
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Result<Self, crate::Error> {
+        Ok(Self {
+            include: compile_patterns(include)?,
+            exclude: compile_patterns(exclude)?,
+        })
+    }
+
+    /// Returns true if a link should be reported.
+    pub fn is_match(&self, link: &str) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(link));
+        let excluded = self.exclude.iter().any(|pattern| pattern.is_match(link));
+
+        included && !excluded
+    }
+}
+
+impl PartialEq for LinkFilter {
+    fn eq(&self, other: &Self) -> bool {
+        fn as_strs(patterns: &[Regex]) -> Vec<&str> {
+            patterns.iter().map(Regex::as_str).collect()
+        }
+
+        as_strs(&self.include) == as_strs(&other.include)
+            && as_strs(&self.exclude) == as_strs(&other.exclude)
+    }
+}
+
+impl Eq for LinkFilter {}
+
+/// Restricts the new links reported for a feed to those of items in at least
+/// one included category, if any are configured, and no excluded categories.
+/// Categories are compared case-insensitively, and excludes take precedence
+/// over includes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryFilter {
+    include: BTreeSet<String>,
+    exclude: BTreeSet<String>,
+    /// whether items without any categories are reported, as they can't be
+    /// matched against either list.
+    include_uncategorized: bool,
+}
+
+impl Default for CategoryFilter {
+    fn default() -> Self {
+        Self::new::<&str>(&[], &[], true)
+    }
+}
+
+impl CategoryFilter {
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S], include_uncategorized: bool) -> Self {
+        let normalize = |categories: &[S]| {
+            categories
+                .iter()
+                .map(|category| normalize_category(category.as_ref()))
+                .collect()
+        };
+
+        Self {
+            include: normalize(include),
+            exclude: normalize(exclude),
+            include_uncategorized,
+        }
+    }
+
+    /// Returns true if the links of an item with the given categories should
+    /// be reported.
+    pub fn is_match<S: AsRef<str>>(&self, categories: &[S]) -> bool {
+        if categories.is_empty() {
+            return self.include_uncategorized;
+        }
+
+        let categories: Vec<_> = categories
+            .iter()
+            .map(|category| normalize_category(category.as_ref()))
+            .collect();
+        let included = self.include.is_empty()
+            || categories
+                .iter()
+                .any(|category| self.include.contains(category));
+        let excluded = categories
+            .iter()
+            .any(|category| self.exclude.contains(category));
+
+        included && !excluded
+    }
+}
+
+fn normalize_category(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+fn compile_patterns<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<Regex>, crate::Error> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.as_ref();
+            Regex::new(pattern).map_err(|err| {
+                crate::Error::new(crate::ErrorKind::InvalidLinkPattern {
+                    reason: err,
+                    pattern: pattern.to_string(),
+                })
+            })
+        })
+        .collect()
+}
+
+/// A feed name glob, where a `*` matches any run of characters and a `?` any
+/// single character.
+#[derive(Debug, Clone)]
+struct Glob {
+    glob: String,
+    regex: Regex,
+}
+
+impl Glob {
+    fn new(glob: &str) -> Self {
+        let pattern: String = glob
+            .chars()
+            .map(|c| match c {
+                '*' => ".*".to_string(),
+                '?' => ".".to_string(),
+                c => regex::escape(&c.to_string()),
+            })
+            .collect();
+
+        Self {
+            glob: glob.to_string(),
+            // every wildcard and escaped literal is valid regex syntax.
+            regex: Regex::new(&format!("^{}$", pattern)).expect("escaped glob is a valid regex"),
+        }
+    }
+
+    fn is_match(&self, feed_name: &str) -> bool {
+        self.regex.is_match(feed_name)
+    }
+}
+
+/// Selects which feeds are checked by name, restricting them to those matching
+/// at least one `only` glob, if any are given, and no `exclude` globs.
+#[derive(Debug, Clone, Default)]
+pub struct FeedNameFilter {
+    only: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl FeedNameFilter {
+    pub fn new<S: AsRef<str>>(only: &[S], exclude: &[S]) -> Self {
+        let compile = |globs: &[S]| globs.iter().map(|glob| Glob::new(glob.as_ref())).collect();
+
+        Self {
+            only: compile(only),
+            exclude: compile(exclude),
+        }
+    }
+
+    /// Returns true if a feed should be checked.
+    pub fn is_match(&self, feed_name: &str) -> bool {
+        let selected =
+            self.only.is_empty() || self.only.iter().any(|glob| glob.is_match(feed_name));
+        let excluded = self.exclude.iter().any(|glob| glob.is_match(feed_name));
+
+        selected && !excluded
+    }
+
+    /// Retains only the selected feeds, failing if `only` globs were given but
+    /// no feed was selected, as that is most likely a typo.
+    pub fn select<V>(
+        &self,
+        feeds: BTreeMap<String, V>,
+    ) -> Result<BTreeMap<String, V>, crate::Error> {
+        let selected: BTreeMap<_, _> = feeds
+            .into_iter()
+            .filter(|(feed_name, _)| self.is_match(feed_name))
+            .collect();
+
+        if selected.is_empty() && !self.only.is_empty() {
+            let globs = self.only.iter().map(|glob| glob.glob.clone()).collect();
+            return Err(crate::Error::new(crate::ErrorKind::NoFeedsSelected(globs)));
+        }
+
+        Ok(selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_all_links_by_default() {
+        let filter = LinkFilter::default();
+
+        assert!(filter.is_match("http://example.com/1"));
+    }
+
+    #[test]
+    fn should_only_match_included_links() {
+        let filter = LinkFilter::new(&["/security/"], &[]).unwrap();
+
+        assert!(filter.is_match("http://example.com/security/1"));
+        assert!(!filter.is_match("http://example.com/news/1"));
+    }
+
+    #[test]
+    fn should_prefer_excludes_over_includes() {
+        let filter = LinkFilter::new(&["/security/"], &["draft"]).unwrap();
+
+        assert!(filter.is_match("http://example.com/security/1"));
+        assert!(!filter.is_match("http://example.com/security/draft-1"));
+    }
+
+    #[test]
+    fn should_reject_invalid_patterns() {
+        let res = LinkFilter::new(&["("], &[]);
+
+        assert!(matches!(
+            res,
+            Err(crate::Error {
+                kind: crate::ErrorKind::InvalidLinkPattern { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_match_included_categories_case_insensitively() {
+        let filter = CategoryFilter::new(&["Security"], &[], true);
+
+        assert!(filter.is_match(&["news", "security "]));
+        assert!(!filter.is_match(&["news"]));
+        assert!(filter.is_match::<&str>(&[]));
+    }
+
+    #[test]
+    fn should_prefer_excluded_categories_over_included() {
+        let filter = CategoryFilter::new(&["security"], &["sponsored"], true);
+
+        assert!(filter.is_match(&["security"]));
+        assert!(!filter.is_match(&["security", "Sponsored"]));
+        assert!(!CategoryFilter::new(&[], &["sponsored"], true).is_match(&["sponsored"]));
+    }
+
+    #[test]
+    fn should_exclude_uncategorized_items_when_configured() {
+        let filter = CategoryFilter::new::<&str>(&[], &[], false);
+
+        assert!(!filter.is_match::<&str>(&[]));
+        assert!(filter.is_match(&["news"]));
+    }
+
+    #[test]
+    fn should_select_feeds_by_name_glob() {
+        let feeds = BTreeMap::from([
+            ("news-world".to_string(), ()),
+            ("news-tech".to_string(), ()),
+            ("blog".to_string(), ()),
+        ]);
+        let filter = FeedNameFilter::new(&["news-*", "b?og"], &["*-tech"]);
+
+        assert_eq!(
+            filter
+                .select(feeds.clone())
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>(),
+            vec!["blog", "news-world"]
+        );
+        assert_eq!(
+            FeedNameFilter::default().select(feeds.clone()).unwrap(),
+            feeds
+        );
+        assert!(matches!(
+            FeedNameFilter::new(&["new.*"], &[]).select(feeds),
+            Err(crate::Error {
+                kind: crate::ErrorKind::NoFeedsSelected(globs),
+                ..
+            }) if globs == ["new.*"]
+        ));
+    }
+}
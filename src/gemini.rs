@@ -0,0 +1,301 @@
+//! A fetcher for feeds served over the [Gemini](https://geminiprotocol.net/)
+//! protocol, registered for `gemini` urls when the `gemini` feature is
+//! enabled.
+//!
+//! Gemini servers overwhelmingly serve self-signed certificates rather than
+//! ones issued by a certificate authority, so certificate and hostname
+//! verification is disabled entirely. No trust-on-first-use pinning is done
+//! either, so a server's identity is never checked.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use reqwest::Url;
+
+use crate::{
+    body_read_error_for_feed, decode_feed_body, parse_fetched_feed, read_body_with_limit,
+    CacheMetadata, CachedFeed, Error, ErrorKind, FetchedFeed, HttpClientOptions,
+};
+
+/// The url scheme fetched by this module.
+pub const SCHEME: &str = "gemini";
+
+/// The port a gemini url without an explicit port is fetched from.
+pub const DEFAULT_PORT: u16 = 1965;
+
+/// The maximum length of a response header: a two digit status, a space, up
+/// to 1024 bytes of meta and the terminating CRLF.
+const MAX_HEADER_BYTES: usize = 1029;
+
+/// The parsed header line of a gemini response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub status: u8,
+    /// the mime type of a successful response, the target of a redirect, or a
+    /// server's description of a failure.
+    pub meta: String,
+}
+
+impl ResponseHeader {
+    /// Parses a header line of the form `<status> <meta>`, without its
+    /// terminating CRLF. Returns `None` for a malformed header.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (status, meta) = match line.split_once(' ') {
+            Some((status, meta)) => (status, meta),
+            None => (line, ""),
+        };
+        if status.len() != 2 || !status.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(Self {
+            status: status.parse().ok()?,
+            meta: meta.trim().to_string(),
+        })
+    }
+}
+
+/// Returns a fetcher requesting `gemini` urls, following redirects up to the
+/// options' `max_redirects`. Gemini carries no validators, so every fetched
+/// feed is treated as modified.
+pub(crate) fn get_feed_with_gemini_request(
+    options: &HttpClientOptions,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> {
+    let options = options.clone();
+
+    move |feed_name: &str, url: &Url, _: &CacheMetadata| {
+        let mut url = url.clone();
+
+        for _ in 0..=options.max_redirects {
+            let (header, stream) = request(feed_name, &url, &options)?;
+
+            match header.status / 10 {
+                2 => {
+                    let contents =
+                        read_body_with_limit(feed_name, stream, None, options.max_body_bytes)?;
+                    let contents = decode_feed_body(&contents, None);
+
+                    // a successful response's meta is its mime type.
+                    let content_type = Some(header.meta.as_str()).filter(|meta| !meta.is_empty());
+                    return parse_fetched_feed(feed_name, &contents, None, content_type).map(
+                        |feed| {
+                            let feed = feed.resolve_relative_links(&url);
+                            FetchedFeed::Modified(CachedFeed::new(feed, CacheMetadata::default()))
+                        },
+                    );
+                }
+                3 => {
+                    url = redirect_target(feed_name, &url, header)?;
+                    log::debug!("feed[{}]: redirected to {}", feed_name, url);
+                }
+                _ => return Err(status_error(feed_name, header)),
+            }
+        }
+
+        Err(Error::new(ErrorKind::TooManyRedirects(
+            feed_name.to_string(),
+        )))
+    }
+}
+
+fn status_error(feed_name: &str, header: ResponseHeader) -> Error {
+    Error::new(ErrorKind::GeminiStatus {
+        feed_name: feed_name.to_string(),
+        status: header.status,
+        meta: header.meta,
+    })
+}
+
+/// Resolves a redirect's target against the url redirected from. Redirects
+/// are only followed to other `gemini` urls, as a redirect to any other
+/// scheme would be requested as though it were gemini.
+fn redirect_target(feed_name: &str, url: &Url, header: ResponseHeader) -> Result<Url, Error> {
+    let target = match url.join(&header.meta) {
+        Ok(target) => target,
+        Err(_) => return Err(status_error(feed_name, header)),
+    };
+
+    if target.scheme() != SCHEME {
+        return Err(Error::new(ErrorKind::UnsupportedScheme {
+            feed_name: feed_name.to_string(),
+            scheme: target.scheme().to_string(),
+            supported: vec![SCHEME],
+        }));
+    }
+
+    Ok(target)
+}
+
+/// Maps a socket error to an [Error]. Sockets with a read timeout report it
+/// as `WouldBlock` on some platforms, so both are treated as timeouts.
+fn io_error_for_feed(feed_name: &str, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::WouldBlock => {
+            body_read_error_for_feed(feed_name, io::ErrorKind::TimedOut.into())
+        }
+        _ => body_read_error_for_feed(feed_name, err),
+    }
+}
+
+/// Sends a request for `url`, returning the response's header and the
+/// stream its body is read from.
+fn request(
+    feed_name: &str,
+    url: &Url,
+    options: &HttpClientOptions,
+) -> Result<(ResponseHeader, native_tls::TlsStream<TcpStream>), Error> {
+    let invalid_url = || {
+        Error::new(ErrorKind::IoErr(io::ErrorKind::InvalidInput.into())).with_data(format!(
+            "feed[{}]: gemini url {} has no host",
+            feed_name, url
+        ))
+    };
+    let host = url.host_str().ok_or_else(invalid_url)?;
+    let port = url.port().unwrap_or(DEFAULT_PORT);
+
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| io_error_for_feed(feed_name, err))?
+        .next()
+        .ok_or_else(invalid_url)?;
+    let stream = TcpStream::connect_timeout(&addr, options.connect_timeout)
+        .map_err(|err| io_error_for_feed(feed_name, err))?;
+    stream
+        .set_read_timeout(Some(options.request_timeout))
+        .and_then(|_| stream.set_write_timeout(Some(options.request_timeout)))
+        .map_err(|err| io_error_for_feed(feed_name, err))?;
+
+    let tls_error_for_feed =
+        |err| Error::new(ErrorKind::TlsErr(err)).with_data(format!("feed[{}]", feed_name));
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .map_err(tls_error_for_feed)?;
+    let mut stream = connector.connect(host, stream).map_err(|err| match err {
+        native_tls::HandshakeError::Failure(err) => tls_error_for_feed(err),
+        native_tls::HandshakeError::WouldBlock(_) => {
+            Error::new(ErrorKind::Timeout(feed_name.to_string()))
+        }
+    })?;
+
+    stream
+        .write_all(format!("{}\r\n", url).as_bytes())
+        .map_err(|err| io_error_for_feed(feed_name, err))?;
+
+    let header = read_header(feed_name, &mut stream)?;
+    Ok((header, stream))
+}
+
+/// Reads a response's header line a byte at a time, leaving the stream at
+/// the start of the body.
+fn read_header<R: Read>(feed_name: &str, stream: &mut R) -> Result<ResponseHeader, Error> {
+    let malformed = |line: &[u8]| {
+        Error::new(ErrorKind::GeminiStatus {
+            feed_name: feed_name.to_string(),
+            status: 0,
+            meta: format!(
+                "malformed response header {:?}",
+                String::from_utf8_lossy(line)
+            ),
+        })
+    };
+
+    let mut line = Vec::with_capacity(MAX_HEADER_BYTES);
+    let mut byte = [0u8];
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= MAX_HEADER_BYTES {
+            return Err(malformed(&line));
+        }
+        match stream.read(&mut byte) {
+            Ok(0) => return Err(malformed(&line)),
+            Ok(_) => line.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(io_error_for_feed(feed_name, err)),
+        }
+    }
+
+    std::str::from_utf8(&line[..line.len() - 2])
+        .ok()
+        .and_then(ResponseHeader::parse)
+        .ok_or_else(|| malformed(&line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_response_headers() {
+        assert_eq!(
+            ResponseHeader::parse("20 application/rss+xml; charset=utf-8"),
+            Some(ResponseHeader {
+                status: 20,
+                meta: "application/rss+xml; charset=utf-8".to_string(),
+            })
+        );
+        assert_eq!(
+            ResponseHeader::parse("51"),
+            Some(ResponseHeader {
+                status: 51,
+                meta: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_malformed_response_headers() {
+        assert_eq!(ResponseHeader::parse("2 text/gemini"), None);
+        assert_eq!(ResponseHeader::parse("ok text/gemini"), None);
+        assert_eq!(ResponseHeader::parse(""), None);
+    }
+
+    #[test]
+    fn should_only_follow_redirects_to_gemini_urls() {
+        let url = Url::parse("gemini://example.com/feeds/atom.xml").unwrap();
+        let redirect = |meta: &str| ResponseHeader {
+            status: 31,
+            meta: meta.to_string(),
+        };
+
+        assert_eq!(
+            redirect_target("test", &url, redirect("../rss.xml"))
+                .unwrap()
+                .as_str(),
+            "gemini://example.com/rss.xml"
+        );
+        assert!(matches!(
+            redirect_target("test", &url, redirect("https://example.com/rss.xml")),
+            Err(Error {
+                kind: ErrorKind::UnsupportedScheme { ref scheme, .. },
+                ..
+            }) if scheme == "https"
+        ));
+    }
+
+    #[test]
+    fn should_read_the_header_line_leaving_the_body() {
+        let mut stream = io::Cursor::new(b"20 text/xml\r\n<rss/>".to_vec());
+
+        let header = read_header("test", &mut stream).unwrap();
+        let mut body = String::new();
+        stream.read_to_string(&mut body).unwrap();
+
+        assert_eq!(header.status, 20);
+        assert_eq!(header.meta, "text/xml");
+        assert_eq!(body, "<rss/>");
+    }
+
+    #[test]
+    fn should_fail_unterminated_response_headers() {
+        let mut stream = io::Cursor::new(vec![b'2'; MAX_HEADER_BYTES + 1]);
+
+        assert!(matches!(
+            read_header("test", &mut stream),
+            Err(Error {
+                kind: ErrorKind::GeminiStatus { status: 0, .. },
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+//! A global configuration file providing defaults for command line options,
+//! distinct from the per-feed configuration directory. Options are resolved
+//! in order of precedence: command line flags, then environment variables,
+//! then this file, then built-in defaults.
+
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::duration::parse_duration;
+use crate::output::{OutputFormat, SortBy};
+use crate::{CacheLayout, CacheMode, DedupKey, Error, ErrorKind, LinkSource};
+
+/// Resolves the default global configuration file path,
+/// `$XDG_CONFIG_HOME/rss_checker/config.toml` falling back to
+/// `~/.config/rss_checker/config.toml`.
+pub fn default_config_file_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config_dir| config_dir.join("rss_checker").join("config.toml"))
+}
+
+/// Defaults for command line options. Every option is optional, falling
+/// back to the built-in default when unset.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlobalConfig {
+    pub cache_path: Option<PathBuf>,
+    pub cache_mode: Option<CacheMode>,
+    pub cache_layout: Option<CacheLayout>,
+    #[serde(default, with = "optional_duration")]
+    pub request_timeout: Option<Duration>,
+    #[serde(default, with = "optional_duration")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, with = "optional_duration")]
+    pub min_cache_age: Option<Duration>,
+    pub respect_cache_control: Option<bool>,
+    pub dedup_key: Option<DedupKey>,
+    pub link_source: Option<LinkSource>,
+    pub user_agent: Option<String>,
+    pub max_redirects: Option<usize>,
+    pub max_body_bytes: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub retry_base_delay_ms: Option<u64>,
+    pub fetch_jitter_ms: Option<u64>,
+    pub per_host_rps: Option<NonZeroU32>,
+    pub output_format: Option<OutputFormat>,
+    pub group_by_feed: Option<bool>,
+    pub show_empty: Option<bool>,
+    pub sort_by: Option<SortBy>,
+    pub cross_feed_dedup: Option<bool>,
+    pub max_concurrency: Option<NonZeroUsize>,
+}
+
+/// (De)serializes a duration given either as a whole number of seconds or as
+/// a string accepted by [parse_duration], such as `30s` or `2h`, matching the
+/// values accepted by the corresponding command line options.
+mod optional_duration {
+    use super::*;
+
+    struct DurationVisitor;
+
+    impl serde::de::Visitor<'_> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a number of seconds or a duration like 30s, 15m, 2h or 7d")
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, secs: u64) -> Result<Self::Value, E> {
+            Ok(Duration::from_secs(secs))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, secs: i64) -> Result<Self::Value, E> {
+            u64::try_from(secs)
+                .map(Duration::from_secs)
+                .map_err(|_| E::custom(format!("expected a positive duration: {}", secs)))
+        }
+
+        fn visit_str<E: serde::de::Error>(self, duration: &str) -> Result<Self::Value, E> {
+            parse_duration(duration).map_err(E::custom)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        deserializer.deserialize_any(DurationVisitor).map(Some)
+    }
+
+    /// Durations are serialized as whole seconds, such as `30s`.
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_str(&format!("{}s", duration.as_secs())),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+impl GlobalConfig {
+    /// Reads a global configuration file. A missing file is treated as empty
+    /// unless `required` is set.
+    pub fn from_file(path: &Path, required: bool) -> Result<Self, Error> {
+        let file_name = path.display().to_string();
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(err) => {
+                return Err(Error::from(err).with_data(format!("config-file[{}]", file_name)))
+            }
+        };
+
+        toml::from_str(&contents).map_err(|err| {
+            Error::new(ErrorKind::InvalidConfig {
+                reason: Box::new(err),
+                file_name,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_global_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "request_timeout = 5\nconnect_timeout = \"2m\"\nuser_agent = \"test\"\noutput_format = \"json\"\ndedup_key = \"guid\"\nmax_concurrency = 2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            GlobalConfig::from_file(&path, true).unwrap(),
+            GlobalConfig {
+                request_timeout: Some(Duration::from_secs(5)),
+                connect_timeout: Some(Duration::from_secs(120)),
+                user_agent: Some("test".to_string()),
+                output_format: Some(OutputFormat::Json),
+                dedup_key: Some(DedupKey::Guid),
+                max_concurrency: NonZeroUsize::new(2),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn should_only_require_an_existing_file_when_requested() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        assert_eq!(
+            GlobalConfig::from_file(&path, false).unwrap(),
+            GlobalConfig::default()
+        );
+        assert!(matches!(
+            GlobalConfig::from_file(&path, true),
+            Err(Error {
+                kind: ErrorKind::IoErr(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_reject_unknown_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "request_timout = 5\n").unwrap();
+
+        assert!(matches!(
+            GlobalConfig::from_file(&path, true),
+            Err(Error {
+                kind: ErrorKind::InvalidConfig { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_reject_invalid_durations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        for duration in ["-5", "\"5 minutes\"", "true"] {
+            std::fs::write(&path, format!("min_cache_age = {}\n", duration)).unwrap();
+
+            assert!(
+                matches!(
+                    GlobalConfig::from_file(&path, true),
+                    Err(Error {
+                        kind: ErrorKind::InvalidConfig { .. },
+                        ..
+                    })
+                ),
+                "{}",
+                duration
+            );
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! An append-only, JSON Lines, record of when each link was first seen. The
+//! history acts as a second layer of deduplication, so that links are never
+//! reported twice even if a feed's cache is lost.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::output::NewItemsByFeed;
+
+/// A single line of the history file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub feed: String,
+    pub link: String,
+    /// when the link was first seen, formatted per RFC3339.
+    pub first_seen: String,
+}
+
+fn io_error_for_history(path: &Path, err: io::Error) -> crate::Error {
+    crate::Error::from(err).with_data(format!("history[{}]", path.display()))
+}
+
+/// Loads the feed and link of every entry in a history file. A missing file
+/// is treated as an empty history and malformed lines are skipped.
+pub fn load_seen_links(path: &Path) -> Result<HashSet<(String, String)>, crate::Error> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(io_error_for_history(path, err)),
+    };
+
+    let mut seen_links = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|err| io_error_for_history(path, err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<HistoryEntry>(&line) {
+            Ok(entry) => {
+                seen_links.insert((entry.feed, entry.link));
+            }
+            Err(err) => log::warn!("skipping malformed history entry in {:?}: {}", path, err),
+        }
+    }
+
+    Ok(seen_links)
+}
+
+/// Removes any links already present in the history file from `new_links`,
+/// then appends the remaining links, stamped with `now`, to it. Nothing is
+/// appended under `dry_run`.
+pub fn filter_and_record_new_links(
+    path: &Path,
+    new_links: &mut NewItemsByFeed,
+    now: DateTime<Utc>,
+    dry_run: bool,
+) -> Result<(), crate::Error> {
+    let seen_links = load_seen_links(path)?;
+    for (feed, items) in new_links.iter_mut() {
+        items.retain(|item| !seen_links.contains(&(feed.clone(), item.link.clone())));
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let first_seen = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut writer = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(io::BufWriter::new)
+        .map_err(|err| io_error_for_history(path, err))?;
+
+    for (feed, items) in new_links.iter() {
+        for item in items {
+            let entry = HistoryEntry {
+                feed: feed.clone(),
+                link: item.link.clone(),
+                first_seen: first_seen.clone(),
+            };
+            serde_json::to_writer(&mut writer, &entry)
+                .map_err(|err| crate::Error::new(crate::ErrorKind::JsonErr(err)))?;
+            writeln!(writer).map_err(|err| io_error_for_history(path, err))?;
+        }
+    }
+
+    writer
+        .flush()
+        .map_err(|err| io_error_for_history(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_links_by_feed(feeds: &[(&str, &[&str])]) -> NewItemsByFeed {
+        feeds
+            .iter()
+            .map(|(feed, links)| {
+                (
+                    feed.to_string(),
+                    links
+                        .iter()
+                        .map(|link| crate::FeedItem {
+                            link: link.to_string(),
+                            title: None,
+                            published: None,
+                            categories: vec![],
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_append_new_links_to_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut new_links = new_links_by_feed(&[("a", &["http://example.com/1"])]);
+
+        filter_and_record_new_links(&path, &mut new_links, now, false).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"feed\":\"a\",\"link\":\"http://example.com/1\",\"first_seen\":\"2024-01-02T03:04:05Z\"}\n"
+        );
+    }
+
+    #[test]
+    fn should_not_report_or_reappend_links_already_in_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let now = Utc::now();
+        let mut first_links = new_links_by_feed(&[("a", &["http://example.com/1"])]);
+        filter_and_record_new_links(&path, &mut first_links, now, false).unwrap();
+
+        let mut new_links =
+            new_links_by_feed(&[("a", &["http://example.com/1", "http://example.com/2"])]);
+        filter_and_record_new_links(&path, &mut new_links, now, false).unwrap();
+
+        assert_eq!(
+            new_links,
+            new_links_by_feed(&[("a", &["http://example.com/2"])])
+        );
+        assert_eq!(load_seen_links(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn should_not_append_to_history_under_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let mut new_links = new_links_by_feed(&[("a", &["http://example.com/1"])]);
+
+        filter_and_record_new_links(&path, &mut new_links, Utc::now(), true).unwrap();
+
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,77 @@
+//! A minimal representation of a [JSON Feed](https://www.jsonfeed.org/version/1.1/)
+//! document. Only the fields needed for link extraction are modeled
+//! explicitly, all other fields are retained so that a feed can be written
+//! back to the cache without loss.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feed {
+    pub version: String,
+    pub title: String,
+    #[serde(default)]
+    pub items: Vec<Item>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_published: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Feed {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// Attempts to parse a JSON Feed from a reader.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Serializes the feed to a writer, returning the writer on success.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<W, serde_json::Error> {
+        serde_json::to_writer(&mut writer, self)?;
+        Ok(writer)
+    }
+}
+
+impl Item {
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn external_url(&self) -> Option<&str> {
+        self.external_url.as_deref()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The item's publication date, formatted per RFC3339.
+    pub fn date_published(&self) -> Option<&str> {
+        self.date_published.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+}
@@ -0,0 +1,5545 @@
+//! Checks RSS 2.0, Atom and JSON feeds for links that have been added since
+//! they were last cached. [check_feeds] checks every feed in a configuration
+//! directory, while the feed model and cache traits are exposed for embedding
+//! in other tools.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::OpenOptions;
+use std::io::{self, BufReader};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use atom_syndication::Feed;
+use chrono::{DateTime, FixedOffset, Utc};
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+use rayon::prelude::*;
+use reqwest::Url;
+use rss::Channel;
+use serde::{Deserialize, Serialize};
+
+pub mod backoff;
+use backoff::BackoffPolicy;
+
+mod error;
+pub use error::{AtomError, Error, ErrorKind};
+
+pub mod duration;
+pub mod exec;
+
+pub mod filter;
+use filter::{CategoryFilter, FeedNameFilter, LinkFilter};
+
+#[cfg(feature = "gemini")]
+pub mod gemini;
+
+pub mod global_config;
+
+pub mod history;
+
+pub mod jsonfeed;
+pub mod lock;
+pub mod metrics;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+pub mod normalize;
+use normalize::UrlNormalizer;
+
+pub mod notify;
+
+pub mod opml;
+
+pub mod ratelimit;
+use ratelimit::HostRateLimiter;
+
+pub mod output;
+
+pub mod selftest;
+
+pub mod validate;
+
+pub mod walker;
+pub mod webhook;
+
+#[allow(clippy::large_enum_variant)]
+pub enum RssOrAtomFeed {
+    Rss2(Channel),
+    Atom(Feed),
+    Json(jsonfeed::Feed),
+}
+
+/// Resolves a relative link against `base_url`, leaving absolute links, and
+/// those that fail to resolve, untouched.
+fn resolve_relative_link(base_url: &Url, link: &mut String) {
+    if Url::parse(link) != Err(url::ParseError::RelativeUrlWithoutBase) {
+        return;
+    }
+
+    if let Ok(resolved) = base_url.join(link) {
+        *link = resolved.to_string();
+    }
+}
+
+impl RssOrAtomFeed {
+    /// Resolves every relative item link against the url the feed was
+    /// fetched from, or an Atom feed's `xml:base` when given, so that items
+    /// with relative links aren't dropped for lacking a valid link.
+    fn resolve_relative_links(mut self, feed_url: &Url) -> Self {
+        match &mut self {
+            RssOrAtomFeed::Rss2(channel) => {
+                for link in channel
+                    .items
+                    .iter_mut()
+                    .filter_map(|item| item.link.as_mut())
+                {
+                    resolve_relative_link(feed_url, link);
+                }
+            }
+            RssOrAtomFeed::Atom(feed) => {
+                let base_url = feed
+                    .base
+                    .as_deref()
+                    .and_then(|base| feed_url.join(base).ok())
+                    .unwrap_or_else(|| feed_url.clone());
+                for link in feed
+                    .entries
+                    .iter_mut()
+                    .flat_map(|entry| entry.links.iter_mut())
+                {
+                    resolve_relative_link(&base_url, &mut link.href);
+                }
+            }
+            RssOrAtomFeed::Json(feed) => {
+                for link in feed
+                    .items
+                    .iter_mut()
+                    .flat_map(|item| [item.url.as_mut(), item.external_url.as_mut()])
+                    .flatten()
+                {
+                    resolve_relative_link(feed_url, link);
+                }
+            }
+        }
+
+        self
+    }
+}
+
+pub trait LinkProduceable {
+    /// Returns every valid link in the feed, in feed order, with any repeated
+    /// link retained only at its first occurrence.
+    fn get_links(&self) -> Vec<Url>;
+}
+
+/// Retains only the first of any values sharing a key, preserving order.
+fn first_occurrences<T, K: Eq + std::hash::Hash, I: IntoIterator<Item = T>>(
+    values: I,
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let mut seen_keys = HashSet::new();
+
+    values
+        .into_iter()
+        .filter(|value| seen_keys.insert(key(value)))
+        .collect()
+}
+
+impl LinkProduceable for rss::Channel {
+    fn get_links(&self) -> Vec<Url> {
+        let links = self
+            .items()
+            .iter()
+            .filter_map(|item| item.link())
+            .filter_map(|link| Url::parse(link).ok());
+
+        first_occurrences(links, Url::clone)
+    }
+}
+
+/// Link relations that never point to an entry's content, so are never
+/// considered its link.
+const ATOM_NON_CONTENT_RELS: [&str; 3] = ["self", "edit", "edit-media"];
+
+/// Returns the link to an Atom entry's content, its first valid `alternate`
+/// link, which links without a `rel` default to, falling back to its first
+/// valid link of any other relation pointing at content, such as an
+/// `enclosure`.
+fn atom_entry_link(entry: &atom_syndication::Entry) -> Option<Url> {
+    let valid_links = || {
+        entry
+            .links()
+            .iter()
+            .filter_map(|link| Url::parse(link.href()).ok().map(|url| (link.rel(), url)))
+    };
+
+    valid_links()
+        .find(|(rel, _)| *rel == "alternate")
+        .or_else(|| valid_links().find(|(rel, _)| !ATOM_NON_CONTENT_RELS.contains(rel)))
+        .map(|(_, url)| url)
+}
+
+impl LinkProduceable for atom_syndication::Feed {
+    fn get_links(&self) -> Vec<Url> {
+        let links = self.entries().iter().filter_map(atom_entry_link);
+
+        first_occurrences(links, Url::clone)
+    }
+}
+
+impl LinkProduceable for jsonfeed::Feed {
+    fn get_links(&self) -> Vec<Url> {
+        let links = self
+            .items()
+            .iter()
+            .filter_map(|item| item.url().or_else(|| item.external_url()))
+            .filter_map(|link| Url::parse(link).ok());
+
+        first_occurrences(links, Url::clone)
+    }
+}
+
+impl LinkProduceable for RssOrAtomFeed {
+    fn get_links(&self) -> Vec<Url> {
+        match self {
+            RssOrAtomFeed::Rss2(channel) => channel.get_links(),
+            RssOrAtomFeed::Atom(feed) => feed.get_links(),
+            RssOrAtomFeed::Json(feed) => feed.get_links(),
+        }
+    }
+}
+
+/// Counts of a feed's items by whether their link parses, for diagnosing
+/// feeds reporting fewer links than they have items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkCounts {
+    pub items: usize,
+    /// items with a link parsing as an absolute url.
+    pub valid_links: usize,
+    /// items with a link that fails to parse, even once resolved against the
+    /// feed's url. Items without any link are counted by neither.
+    pub unparseable_links: usize,
+}
+
+impl LinkCounts {
+    /// Counts items given whether each has a link, and if so whether it's
+    /// valid.
+    fn from_item_links(link_validity: impl Iterator<Item = Option<bool>>) -> Self {
+        link_validity.fold(Self::default(), |counts, is_valid| Self {
+            items: counts.items + 1,
+            valid_links: counts.valid_links + usize::from(is_valid == Some(true)),
+            unparseable_links: counts.unparseable_links + usize::from(is_valid == Some(false)),
+        })
+    }
+}
+
+impl std::fmt::Display for LinkCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} items, {} valid links, {} unparseable",
+            self.items, self.valid_links, self.unparseable_links
+        )
+    }
+}
+
+impl RssOrAtomFeed {
+    /// Counts the feed's items by whether their link parses, taking an Atom
+    /// entry's link as per [LinkProduceable].
+    pub fn link_counts(&self) -> LinkCounts {
+        let is_valid = |link: &str| Url::parse(link).is_ok();
+
+        match self {
+            RssOrAtomFeed::Rss2(channel) => LinkCounts::from_item_links(
+                channel.items().iter().map(|item| item.link().map(is_valid)),
+            ),
+            RssOrAtomFeed::Atom(feed) => {
+                LinkCounts::from_item_links(feed.entries().iter().map(|entry| {
+                    let has_content_link = entry
+                        .links()
+                        .iter()
+                        .any(|link| !ATOM_NON_CONTENT_RELS.contains(&link.rel()));
+
+                    has_content_link.then(|| atom_entry_link(entry).is_some())
+                }))
+            }
+            RssOrAtomFeed::Json(feed) => LinkCounts::from_item_links(
+                feed.items()
+                    .iter()
+                    .map(|item| item.url().or_else(|| item.external_url()).map(is_valid)),
+            ),
+        }
+    }
+}
+
+/// A single link produced by a feed item, alongside the item's title,
+/// publication date and categories when available.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct FeedItem {
+    pub link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// the publication date, serialized per RFC3339. Dates that fail to parse
+    /// are treated as absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<FixedOffset>>,
+    /// the item's RSS categories, Atom category terms or JSON Feed tags.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+}
+
+pub trait ItemsProduceable {
+    /// Returns a [FeedItem] for every valid link in the feed, in feed order.
+    /// An item repeating an earlier item's link is omitted, so each link is
+    /// always attributed to the first item producing it.
+    fn get_items(&self) -> Vec<FeedItem>;
+}
+
+fn rss_item_feed_items(item: &rss::Item) -> Vec<FeedItem> {
+    item.link()
+        .and_then(|link| Url::parse(link).ok())
+        .map(|link| FeedItem {
+            link: link.to_string(),
+            title: item.title().map(|title| title.to_string()),
+            published: item
+                .pub_date()
+                .and_then(|published| DateTime::parse_from_rfc2822(published).ok()),
+            categories: item
+                .categories()
+                .iter()
+                .map(|category| category.name().to_string())
+                .collect(),
+        })
+        .into_iter()
+        .collect()
+}
+
+fn atom_entry_feed_items(entry: &atom_syndication::Entry) -> Vec<FeedItem> {
+    atom_entry_link(entry)
+        .map(|link| FeedItem {
+            link: link.to_string(),
+            title: Some(entry.title().as_str())
+                .filter(|title| !title.is_empty())
+                .map(|title| title.to_string()),
+            published: entry.published().copied(),
+            categories: entry
+                .categories()
+                .iter()
+                .map(|category| category.term().to_string())
+                .collect(),
+        })
+        .into_iter()
+        .collect()
+}
+
+fn json_item_feed_items(item: &jsonfeed::Item) -> Vec<FeedItem> {
+    item.url()
+        .or_else(|| item.external_url())
+        .and_then(|link| Url::parse(link).ok())
+        .map(|link| FeedItem {
+            link: link.to_string(),
+            title: item.title().map(|title| title.to_string()),
+            published: item
+                .date_published()
+                .and_then(|published| DateTime::parse_from_rfc3339(published).ok()),
+            categories: item.tags().to_vec(),
+        })
+        .into_iter()
+        .collect()
+}
+
+impl ItemsProduceable for rss::Channel {
+    fn get_items(&self) -> Vec<FeedItem> {
+        first_occurrences(self.items().iter().flat_map(rss_item_feed_items), |item| {
+            item.link.clone()
+        })
+    }
+}
+
+impl ItemsProduceable for atom_syndication::Feed {
+    fn get_items(&self) -> Vec<FeedItem> {
+        first_occurrences(
+            self.entries().iter().flat_map(atom_entry_feed_items),
+            |item| item.link.clone(),
+        )
+    }
+}
+
+impl ItemsProduceable for jsonfeed::Feed {
+    fn get_items(&self) -> Vec<FeedItem> {
+        first_occurrences(self.items().iter().flat_map(json_item_feed_items), |item| {
+            item.link.clone()
+        })
+    }
+}
+
+impl ItemsProduceable for RssOrAtomFeed {
+    fn get_items(&self) -> Vec<FeedItem> {
+        match self {
+            RssOrAtomFeed::Rss2(channel) => channel.get_items(),
+            RssOrAtomFeed::Atom(feed) => feed.get_items(),
+            RssOrAtomFeed::Json(feed) => feed.get_items(),
+        }
+    }
+}
+
+/// An item's stable identity paired with any links it produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ItemIdentity {
+    key: String,
+    items: Vec<FeedItem>,
+    /// a digest of the item's title, description and content, changing
+    /// whenever the item is edited.
+    content_hash: String,
+}
+
+/// Returns the hex encoded SHA-256 digest of `bytes`.
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hashes the parts of an item's content, separated by `NUL`s, which can't
+/// appear in feed text, so that moving text between parts changes the hash.
+fn item_content_hash<'a, I: IntoIterator<Item = Option<&'a str>>>(parts: I) -> String {
+    let content: Vec<_> = parts.into_iter().map(Option::unwrap_or_default).collect();
+    sha256_hex(content.join("\0").as_bytes())
+}
+
+trait ItemIdentifiable {
+    /// Returns an identity for each item, keyed on its guid or id when
+    /// available and falling back to its link. Items with neither are omitted.
+    fn get_item_identities(&self) -> Vec<ItemIdentity>;
+}
+
+impl ItemIdentifiable for rss::Channel {
+    fn get_item_identities(&self) -> Vec<ItemIdentity> {
+        self.items()
+            .iter()
+            .filter_map(|item| {
+                let items = rss_item_feed_items(item);
+                let key = item
+                    .guid()
+                    .map(|guid| guid.value().to_string())
+                    .or_else(|| items.first().map(|item| item.link.clone()))?;
+                let content_hash =
+                    item_content_hash([item.title(), item.description(), item.content()]);
+
+                Some(ItemIdentity {
+                    key,
+                    items,
+                    content_hash,
+                })
+            })
+            .collect()
+    }
+}
+
+impl ItemIdentifiable for atom_syndication::Feed {
+    fn get_item_identities(&self) -> Vec<ItemIdentity> {
+        self.entries()
+            .iter()
+            .filter_map(|entry| {
+                let items = atom_entry_feed_items(entry);
+                let key = Some(entry.id())
+                    .filter(|id| !id.is_empty())
+                    .map(|id| id.to_string())
+                    .or_else(|| items.first().map(|item| item.link.clone()))?;
+                let content_hash = item_content_hash([
+                    Some(entry.title().as_str()),
+                    entry.summary().map(|summary| summary.as_str()),
+                    entry.content().and_then(|content| content.value()),
+                ]);
+
+                Some(ItemIdentity {
+                    key,
+                    items,
+                    content_hash,
+                })
+            })
+            .collect()
+    }
+}
+
+impl ItemIdentifiable for jsonfeed::Feed {
+    fn get_item_identities(&self) -> Vec<ItemIdentity> {
+        self.items()
+            .iter()
+            .filter_map(|item| {
+                let items = json_item_feed_items(item);
+                // ids should be strings but numeric ids are common in the wild.
+                let key = match &item.id {
+                    serde_json::Value::String(id) if !id.is_empty() => Some(id.clone()),
+                    serde_json::Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                }
+                .or_else(|| items.first().map(|item| item.link.clone()))?;
+                let extra = |field| item.extra.get(field).and_then(serde_json::Value::as_str);
+                let content_hash = item_content_hash([
+                    item.title(),
+                    extra("summary"),
+                    extra("content_html"),
+                    extra("content_text"),
+                ]);
+
+                Some(ItemIdentity {
+                    key,
+                    items,
+                    content_hash,
+                })
+            })
+            .collect()
+    }
+}
+
+impl ItemIdentifiable for RssOrAtomFeed {
+    fn get_item_identities(&self) -> Vec<ItemIdentity> {
+        match self {
+            RssOrAtomFeed::Rss2(channel) => channel.get_item_identities(),
+            RssOrAtomFeed::Atom(feed) => feed.get_item_identities(),
+            RssOrAtomFeed::Json(feed) => feed.get_item_identities(),
+        }
+    }
+}
+
+/// Metadata persisted alongside a cached feed, such as the http validators
+/// used to conditionally request it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// the freshness lifetime, in seconds, declared by the response's
+    /// `Cache-Control: max-age` directive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    /// when the response declaring `max_age` was received.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl CacheMetadata {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Updates cached metadata with that of a `304 Not Modified` response,
+    /// which only replaces the validators and freshness lifetime it declares.
+    /// A retained lifetime is restarted, as the cached response was just
+    /// revalidated.
+    fn revalidated_by(self, revalidated: CacheMetadata) -> Self {
+        let max_age = revalidated.max_age.or(self.max_age);
+
+        Self {
+            etag: revalidated.etag.or(self.etag),
+            last_modified: revalidated.last_modified.or(self.last_modified),
+            max_age,
+            fetched_at: max_age.map(|_| Utc::now()),
+        }
+    }
+
+    /// Returns true if the response is still within the freshness lifetime
+    /// its server declared, as of `now`.
+    fn is_fresh_per_server(&self, now: DateTime<Utc>) -> bool {
+        match (self.fetched_at, self.max_age) {
+            (Some(fetched_at), Some(max_age)) => i64::try_from(max_age)
+                .ok()
+                .and_then(chrono::TimeDelta::try_seconds)
+                .and_then(|max_age| fetched_at.checked_add_signed(max_age))
+                .is_some_and(|fresh_until| now < fresh_until),
+            _ => false,
+        }
+    }
+}
+
+/// Parses the `max-age` directive of a `Cache-Control` header, ignoring it if
+/// the response may not be reused without revalidation.
+fn cache_control_max_age(cache_control: &str) -> Option<u64> {
+    let directives: Vec<_> = cache_control
+        .split(',')
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .collect();
+
+    if directives
+        .iter()
+        .any(|directive| directive == "no-cache" || directive == "no-store")
+    {
+        return None;
+    }
+
+    directives.iter().find_map(|directive| {
+        directive
+            .strip_prefix("max-age=")
+            .and_then(|max_age| max_age.trim_matches('"').parse().ok())
+    })
+}
+
+/// The keys of every item in a feed, persisted in place of the feed itself
+/// when caching in [CacheMode::Keys]. Both links and identities are retained
+/// so that either [DedupKey] may be used against the cache.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedKeys {
+    pub links: BTreeSet<String>,
+    pub ids: BTreeSet<String>,
+    /// the content hash of each item, by identity key, to detect edits.
+    /// Absent from caches written before hashes were kept.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub content_hashes: BTreeMap<String, String>,
+}
+
+impl From<&RssOrAtomFeed> for CachedKeys {
+    fn from(feed: &RssOrAtomFeed) -> Self {
+        Self {
+            links: feed.get_items().into_iter().map(|item| item.link).collect(),
+            ids: feed
+                .get_item_identities()
+                .into_iter()
+                .map(|identity| identity.key)
+                .collect(),
+            content_hashes: feed.get_item_content_hashes(),
+        }
+    }
+}
+
+/// The contents of a feed's cache, either the feed itself or only its keys.
+#[allow(clippy::large_enum_variant)]
+pub enum CacheContents {
+    Feed(RssOrAtomFeed),
+    Keys(CachedKeys),
+}
+
+impl From<&CacheContents> for CachedKeys {
+    fn from(contents: &CacheContents) -> Self {
+        match contents {
+            CacheContents::Feed(feed) => feed.into(),
+            CacheContents::Keys(keys) => keys.clone(),
+        }
+    }
+}
+
+impl LinkProduceable for CacheContents {
+    fn get_links(&self) -> Vec<Url> {
+        match self {
+            CacheContents::Feed(feed) => feed.get_links(),
+            CacheContents::Keys(keys) => keys
+                .links
+                .iter()
+                .filter_map(|link| Url::parse(link).ok())
+                .collect(),
+        }
+    }
+}
+
+/// Produces the keys a cached feed's items are matched against.
+trait ItemKeysProduceable {
+    /// Returns the link of every item.
+    fn get_item_links(&self) -> Vec<String>;
+    /// Returns the identity key of every item, as per [ItemIdentifiable].
+    fn get_item_ids(&self) -> Vec<String>;
+    /// Returns the content hash of every item, by identity key.
+    fn get_item_content_hashes(&self) -> BTreeMap<String, String>;
+}
+
+impl ItemKeysProduceable for RssOrAtomFeed {
+    fn get_item_links(&self) -> Vec<String> {
+        self.get_items().into_iter().map(|item| item.link).collect()
+    }
+
+    fn get_item_ids(&self) -> Vec<String> {
+        self.get_item_identities()
+            .into_iter()
+            .map(|identity| identity.key)
+            .collect()
+    }
+
+    fn get_item_content_hashes(&self) -> BTreeMap<String, String> {
+        self.get_item_identities()
+            .into_iter()
+            .map(|identity| (identity.key, identity.content_hash))
+            .collect()
+    }
+}
+
+impl ItemKeysProduceable for CachedKeys {
+    fn get_item_links(&self) -> Vec<String> {
+        self.links.iter().cloned().collect()
+    }
+
+    fn get_item_ids(&self) -> Vec<String> {
+        self.ids.iter().cloned().collect()
+    }
+
+    fn get_item_content_hashes(&self) -> BTreeMap<String, String> {
+        self.content_hashes.clone()
+    }
+}
+
+impl ItemKeysProduceable for CacheContents {
+    fn get_item_links(&self) -> Vec<String> {
+        match self {
+            CacheContents::Feed(feed) => feed.get_item_links(),
+            CacheContents::Keys(keys) => keys.get_item_links(),
+        }
+    }
+
+    fn get_item_ids(&self) -> Vec<String> {
+        match self {
+            CacheContents::Feed(feed) => feed.get_item_ids(),
+            CacheContents::Keys(keys) => keys.get_item_ids(),
+        }
+    }
+
+    fn get_item_content_hashes(&self) -> BTreeMap<String, String> {
+        match self {
+            CacheContents::Feed(feed) => feed.get_item_content_hashes(),
+            CacheContents::Keys(keys) => keys.get_item_content_hashes(),
+        }
+    }
+}
+
+/// A feed paired with its cache metadata.
+pub struct CachedFeed {
+    pub contents: CacheContents,
+    pub metadata: CacheMetadata,
+    /// when the feed was last written to the cache, if it has been.
+    pub cached_at: Option<SystemTime>,
+}
+
+impl CachedFeed {
+    pub fn new(feed: RssOrAtomFeed, metadata: CacheMetadata) -> Self {
+        Self::from_contents(CacheContents::Feed(feed), metadata)
+    }
+
+    pub fn from_contents(contents: CacheContents, metadata: CacheMetadata) -> Self {
+        Self {
+            contents,
+            metadata,
+            cached_at: None,
+        }
+    }
+
+    /// Takes each RSS item's link from `link_source`, as per
+    /// [RssOrAtomFeed::with_link_source].
+    fn with_link_source(mut self, link_source: LinkSource) -> Self {
+        if let CacheContents::Feed(feed) = self.contents {
+            self.contents = CacheContents::Feed(feed.with_link_source(link_source));
+        }
+        self
+    }
+
+    pub fn with_cached_at(mut self, cached_at: SystemTime) -> Self {
+        self.cached_at = Some(cached_at);
+        self
+    }
+
+    /// Returns true if the feed was cached less than `min_age` ago.
+    pub fn is_fresh(&self, min_age: Duration) -> bool {
+        self.cached_at
+            .and_then(|cached_at| cached_at.elapsed().ok())
+            .is_some_and(|age| age < min_age)
+    }
+}
+
+impl LinkProduceable for CachedFeed {
+    fn get_links(&self) -> Vec<Url> {
+        self.contents.get_links()
+    }
+}
+
+/// The result of fetching a feed.
+#[allow(clippy::large_enum_variant)]
+pub enum FetchedFeed {
+    /// The feed is unchanged since it was cached, with the metadata of the
+    /// response saying so.
+    NotModified(CacheMetadata),
+    Modified(CachedFeed),
+}
+
+impl FetchedFeed {
+    fn fetch_status(&self) -> FetchStatus {
+        match self {
+            Self::NotModified(_) => FetchStatus::NotModified,
+            Self::Modified(_) => FetchStatus::Modified,
+        }
+    }
+}
+
+pub trait FeedCacheReadable {
+    fn read_cache(&self, feed_name: &str) -> Result<CachedFeed, Error>;
+
+    /// Reads only a feed's cache metadata and cache time, as a cached feed
+    /// without any items, for deciding whether, and how, to fetch it.
+    /// Defaults to reading the whole cache.
+    fn read_cache_validators(&self, feed_name: &str) -> Result<CachedFeed, Error> {
+        self.read_cache(feed_name)
+    }
+}
+
+impl<F> FeedCacheReadable for F
+where
+    F: Fn(&str) -> Result<CachedFeed, Error>,
+{
+    fn read_cache(&self, feed_name: &str) -> Result<CachedFeed, Error> {
+        (self)(feed_name)
+    }
+}
+
+pub trait FeedGettable {
+    fn get_feed(
+        &self,
+        feed_name: &str,
+        url: &Url,
+        metadata: &CacheMetadata,
+    ) -> Result<FetchedFeed, Error>;
+}
+
+impl<F> FeedGettable for F
+where
+    F: Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error>,
+{
+    fn get_feed(
+        &self,
+        feed_name: &str,
+        url: &Url,
+        metadata: &CacheMetadata,
+    ) -> Result<FetchedFeed, Error> {
+        (self)(feed_name, url, metadata)
+    }
+}
+
+pub trait FeedCacheWriteable {
+    fn write_cache(&self, feed_name: &str, feed: &CachedFeed) -> Result<(), Error>;
+
+    /// Records that a cached feed was found unchanged, persisting its
+    /// refreshed metadata without rewriting its items. Defaults to doing
+    /// nothing.
+    fn refresh_cache(&self, _feed_name: &str, _metadata: &CacheMetadata) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<F> FeedCacheWriteable for F
+where
+    F: Fn(&str, &CachedFeed) -> Result<(), Error>,
+{
+    fn write_cache(&self, feed_name: &str, feed: &CachedFeed) -> Result<(), Error> {
+        (self)(feed_name, feed)
+    }
+}
+
+/// The extension appended to a feed's name for its cache metadata sidecar file.
+const CACHE_METADATA_EXTENSION: &str = "meta";
+const CACHE_NAME_EXTENSION: &str = "name";
+const CACHE_KEYS_EXTENSION: &str = "keys";
+/// The extension of the sidecar file counting a feed's consecutive failures.
+const CACHE_FAILURES_EXTENSION: &str = "failures";
+
+/// The user agent sent with every feed request unless overridden.
+pub const DEFAULT_USER_AGENT: &str = concat!("rss_checker_redux/", env!("CARGO_PKG_VERSION"));
+
+/// The default maximum size of a feed response body, 16 MiB.
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Configures the http client shared across all feed requests.
+#[derive(Debug, Clone)]
+pub struct HttpClientOptions {
+    /// the maximum time to wait on a single feed request, including reading
+    /// its body.
+    pub request_timeout: Duration,
+    /// the maximum time to wait on connecting to a feed's host, so dead hosts
+    /// fail well within the request timeout.
+    pub connect_timeout: Duration,
+    /// the user agent sent with each feed request, unless overridden by a
+    /// feed's configuration.
+    pub user_agent: String,
+    /// routes all requests through a http or socks5 proxy. Without one, the
+    /// system proxy from the environment, such as `HTTPS_PROXY`, is used.
+    pub proxy: Option<reqwest::Proxy>,
+    /// the maximum number of redirects followed for a single feed request.
+    pub max_redirects: usize,
+    /// the maximum size, in bytes, of a feed response body.
+    pub max_body_bytes: u64,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            max_redirects: 10,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+}
+
+/// Builds the http client shared across all feed requests.
+pub(crate) fn build_http_client(
+    options: &HttpClientOptions,
+) -> reqwest::Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .timeout(options.request_timeout)
+        .connect_timeout(options.connect_timeout)
+        .user_agent(&options.user_agent)
+        .redirect(reqwest::redirect::Policy::limited(options.max_redirects));
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(proxy.clone());
+    }
+
+    builder.build()
+}
+
+/// Maps a reqwest error to an [Error], distinguishing timeouts and redirect
+/// loops from all other request failures.
+fn reqwest_error_for_feed(feed_name: &str, err: reqwest::Error) -> Error {
+    if err.is_timeout() {
+        Error::new(ErrorKind::Timeout(feed_name.to_string()))
+    } else if err.is_redirect() {
+        Error::new(ErrorKind::TooManyRedirects(feed_name.to_string()))
+    } else {
+        Error::from(err).with_data(format!("feed[{}]", feed_name))
+    }
+}
+
+/// Maps an unsuccessful response's error to an [ErrorKind::HttpStatus],
+/// retaining any error without a status as is.
+fn status_error_for_feed(feed_name: &str, err: reqwest::Error) -> Error {
+    match err.status() {
+        Some(status) => Error::new(ErrorKind::HttpStatus {
+            feed_name: feed_name.to_string(),
+            status,
+        }),
+        None => reqwest_error_for_feed(feed_name, err),
+    }
+}
+
+/// Captures the validators of a feed response for subsequent conditional
+/// requests.
+fn response_metadata(headers: &reqwest::header::HeaderMap) -> CacheMetadata {
+    use reqwest::header::{HeaderName, CACHE_CONTROL, ETAG, LAST_MODIFIED};
+
+    let header_value = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    let max_age = header_value(CACHE_CONTROL).and_then(|value| cache_control_max_age(&value));
+
+    CacheMetadata {
+        etag: header_value(ETAG),
+        last_modified: header_value(LAST_MODIFIED),
+        max_age,
+        fetched_at: max_age.map(|_| Utc::now()),
+    }
+}
+
+/// Maps an error reading a response body to an [Error], recovering the
+/// underlying reqwest error where possible so that timeouts are still
+/// distinguished.
+fn body_read_error_for_feed(feed_name: &str, err: io::Error) -> Error {
+    if err.kind() == io::ErrorKind::TimedOut {
+        return Error::new(ErrorKind::Timeout(feed_name.to_string()));
+    }
+
+    let io_error_for_feed =
+        |err: io::Error| Error::from(err).with_data(format!("feed[{}]", feed_name));
+
+    let kind = err.kind();
+    match err.into_inner() {
+        Some(inner) => match inner.downcast::<reqwest::Error>() {
+            Ok(reqwest_err) => reqwest_error_for_feed(feed_name, *reqwest_err),
+            Err(inner) => io_error_for_feed(io::Error::new(kind, inner)),
+        },
+        None => io_error_for_feed(io::Error::from(kind)),
+    }
+}
+
+/// Returns the capacity to preallocate for a response body of the declared
+/// `content_length`, bounded by `max_body_bytes`.
+fn body_capacity(content_length: Option<u64>, max_body_bytes: u64) -> usize {
+    content_length
+        .unwrap_or(0)
+        .min(max_body_bytes)
+        .try_into()
+        .unwrap_or(0)
+}
+
+/// Reads a response body, failing once it exceeds `max_body_bytes` rather than
+/// buffering it in full. The body is kept as raw bytes, leaving its decoding
+/// to the feed parsers.
+fn read_body_with_limit<R: io::Read>(
+    feed_name: &str,
+    body: R,
+    content_length: Option<u64>,
+    max_body_bytes: u64,
+) -> Result<Vec<u8>, Error> {
+    use std::io::Read;
+
+    let mut contents = Vec::with_capacity(body_capacity(content_length, max_body_bytes));
+    body.take(max_body_bytes.saturating_add(1))
+        .read_to_end(&mut contents)
+        .map_err(|err| body_read_error_for_feed(feed_name, err))?;
+
+    if contents.len() as u64 > max_body_bytes {
+        return Err(Error::new(ErrorKind::BodyTooLarge(feed_name.to_string())));
+    }
+
+    Ok(contents)
+}
+
+/// Reads a feed from the local path of a `file` url. Files carry no
+/// validators, so are always treated as modified.
+fn read_feed_from_file(
+    feed_name: &str,
+    url: &Url,
+    max_body_bytes: u64,
+) -> Result<FetchedFeed, Error> {
+    let io_error_for_feed = |err| Error::from(err).with_data(format!("feed[{}]", feed_name));
+
+    let path = url
+        .to_file_path()
+        .map_err(|_| io_error_for_feed(io::Error::from(io::ErrorKind::InvalidInput)))?;
+    let file = std::fs::File::open(&path).map_err(io_error_for_feed)?;
+    let file_len = file.metadata().map(|metadata| metadata.len()).ok();
+
+    let contents = read_body_with_limit(feed_name, file, file_len, max_body_bytes)?;
+    let contents = decode_feed_body(&contents, None);
+
+    parse_fetched_feed(feed_name, &contents, None, None).map(|feed| {
+        let feed = feed.resolve_relative_links(url);
+        FetchedFeed::Modified(CachedFeed::new(feed, CacheMetadata::default()))
+    })
+}
+
+/// Returns whether a feed url is fetched over http, rather than by the
+/// fetcher of another scheme.
+#[cfg(feature = "async")]
+fn is_http_url(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// Fetchers keyed by the url scheme they fetch.
+type SchemeFetchers<'a> = BTreeMap<&'static str, Box<dyn FeedGettable + 'a>>;
+
+/// Returns the fetchers of every supported scheme other than http and
+/// https, none of which share the http client: `file` urls, read from disk,
+/// and `gemini` urls when the `gemini` feature is enabled.
+fn non_http_scheme_fetchers(options: &HttpClientOptions) -> SchemeFetchers<'static> {
+    let max_body_bytes = options.max_body_bytes;
+    let mut fetchers: SchemeFetchers<'static> = BTreeMap::new();
+
+    fetchers.insert(
+        "file",
+        Box::new(move |feed_name: &str, url: &Url, _: &CacheMetadata| {
+            read_feed_from_file(feed_name, url, max_body_bytes)
+        }),
+    );
+    #[cfg(feature = "gemini")]
+    fetchers.insert(
+        gemini::SCHEME,
+        Box::new(gemini::get_feed_with_gemini_request(options)),
+    );
+
+    fetchers
+}
+
+/// Returns the fetchers of every supported scheme, fetching `http` and
+/// `https` urls with `http_fetcher`.
+fn default_scheme_fetchers<'a, F>(
+    http_fetcher: F,
+    options: &HttpClientOptions,
+) -> SchemeFetchers<'a>
+where
+    F: FeedGettable + Clone + 'a,
+{
+    let mut fetchers = non_http_scheme_fetchers(options);
+    fetchers.insert("http", Box::new(http_fetcher.clone()));
+    fetchers.insert("https", Box::new(http_fetcher));
+
+    fetchers
+}
+
+/// Returns a fetcher dispatching each fetch to the fetcher registered for its
+/// url's scheme, failing the feed for any other scheme.
+fn get_feed_by_scheme<'a>(
+    fetchers: SchemeFetchers<'a>,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> + 'a {
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| match fetchers.get(url.scheme()) {
+        Some(fetcher) => fetcher.get_feed(feed_name, url, metadata),
+        None => Err(Error::new(ErrorKind::UnsupportedScheme {
+            feed_name: feed_name.to_string(),
+            scheme: url.scheme().to_string(),
+            supported: fetchers.keys().copied().collect(),
+        })),
+    }
+}
+
+/// Reads a feed's bearer token from the environment variable `token_env`,
+/// as a sensitive `Authorization` header value so that it's never logged.
+fn bearer_token_header(
+    feed_name: &str,
+    token_env: &str,
+) -> Result<reqwest::header::HeaderValue, Error> {
+    let token = std::env::var_os(token_env)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| {
+            Error::new(ErrorKind::MissingTokenEnv {
+                feed_name: feed_name.to_string(),
+                var: token_env.to_string(),
+            })
+        })?;
+
+    // the token is deliberately omitted from the error, leaving only its
+    // source.
+    let mut header_value = token
+        .to_str()
+        .and_then(|token| reqwest::header::HeaderValue::try_from(format!("Bearer {}", token)).ok())
+        .ok_or_else(|| {
+            Error::new(ErrorKind::InvalidHeader {
+                reason: format!(
+                    "bearer token from {} is not a valid header value",
+                    token_env
+                ),
+                name: reqwest::header::AUTHORIZATION.to_string(),
+            })
+            .with_data(format!("feed[{}]", feed_name))
+        })?;
+    header_value.set_sensitive(true);
+
+    Ok(header_value)
+}
+
+/// Returns a fetcher requesting `http` and `https` urls, as registered in
+/// [default_scheme_fetchers].
+fn get_feed_with_blocking_http_request(
+    client: &reqwest::blocking::Client,
+    max_body_bytes: u64,
+    user_agent: Option<&str>,
+    credentials: Option<&walker::Credentials>,
+    token_env: Option<&str>,
+    headers: &reqwest::header::HeaderMap,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> + Clone {
+    use reqwest::header::{AUTHORIZATION, IF_MODIFIED_SINCE, IF_NONE_MATCH, USER_AGENT};
+
+    let client = client.clone();
+    let user_agent = user_agent.map(|user_agent| user_agent.to_string());
+    let credentials = credentials.cloned();
+    let token_env = token_env.map(|token_env| token_env.to_string());
+    let headers = headers.clone();
+
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+        let mut req = client.get(url.as_str());
+        // a per-feed user agent takes precedence over the client default.
+        if let Some(user_agent) = &user_agent {
+            req = req.header(USER_AGENT, user_agent);
+        }
+        if let Some(credentials) = &credentials {
+            req = req.basic_auth(&credentials.username, credentials.password.as_ref());
+        }
+        // read as each request is made, rather than when feeds are configured.
+        if let Some(token_env) = &token_env {
+            req = req.header(AUTHORIZATION, bearer_token_header(feed_name, token_env)?);
+        }
+        req = req.headers(headers.clone());
+        if let Some(etag) = &metadata.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &metadata.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let resp = req
+            .send()
+            .map_err(|err| reqwest_error_for_feed(feed_name, err))?;
+        resp.error_for_status_ref()
+            .map_err(|err| status_error_for_feed(feed_name, err))?;
+
+        if resp.url() != url {
+            log::debug!("feed[{}] redirected to {}", feed_name, resp.url());
+        }
+
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(FetchedFeed::NotModified(response_metadata(resp.headers())));
+        }
+
+        let metadata = response_metadata(resp.headers());
+        let declared_format = declared_feed_format(resp.headers());
+        let content_type =
+            response_content_type(resp.headers()).map(|content_type| content_type.to_string());
+        let charset = declared_charset(resp.headers());
+        // relative links resolve against the feed's url after any redirects.
+        let resolved_url = resp.url().clone();
+
+        let content_length = resp.content_length();
+        let contents = read_body_with_limit(feed_name, resp, content_length, max_body_bytes)?;
+        let contents = decode_feed_body(&contents, charset);
+
+        parse_fetched_feed(
+            feed_name,
+            &contents,
+            declared_format,
+            content_type.as_deref(),
+        )
+        .map(|feed| {
+            let feed = feed.resolve_relative_links(&resolved_url);
+            FetchedFeed::Modified(CachedFeed::new(feed, metadata))
+        })
+    }
+}
+
+/// Configures how many times, and how quickly, a failed feed fetch is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay preceding a retry, doubling with each attempt.
+    fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+/// Returns true if an error is transient and the fetch is worth retrying.
+/// Only network-level failures, 5xx responses and gemini's temporary
+/// failures qualify.
+fn is_retryable(err: &Error) -> bool {
+    match &err.kind {
+        ErrorKind::Timeout(_) => true,
+        ErrorKind::HttpStatus { status, .. } => status.is_server_error(),
+        #[cfg(feature = "gemini")]
+        ErrorKind::GeminiStatus { status, .. } => status / 10 == 4,
+        // only failures to connect or send a request may pass, unlike a body
+        // that failed to decode or a redirect limit that was exceeded.
+        ErrorKind::ReqwestErr(err) => match err.status() {
+            Some(status) => status.is_server_error(),
+            None => err.is_connect() || err.is_timeout() || err.is_request(),
+        },
+        _ => false,
+    }
+}
+
+/// Wraps a fetcher, retrying retryable failures with an exponential backoff.
+fn get_feed_with_retries<F: FeedGettable>(
+    fetch_feed: F,
+    policy: RetryPolicy,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> {
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+        let mut attempt = 0;
+
+        loop {
+            match fetch_feed.get_feed(feed_name, url, metadata) {
+                Err(err) if attempt < policy.max_retries && is_retryable(&err) => {
+                    attempt += 1;
+
+                    let delay = policy.delay_for_attempt(attempt);
+                    log::warn!(
+                        "feed[{}]: {}, retrying in {:?} (attempt {} of {})",
+                        feed_name,
+                        err,
+                        delay,
+                        attempt,
+                        policy.max_retries
+                    );
+                    std::thread::sleep(delay);
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Combines the errors of every url a feed was fetched from, keeping a lone
+/// error as is.
+fn error_for_feed_candidates(mut errors: Vec<Error>) -> Error {
+    match errors.len() {
+        1 => errors.remove(0),
+        _ => Error::new(ErrorKind::Multiple(errors)),
+    }
+}
+
+/// Wraps a fetcher, falling back to each of a feed's `mirrors` in turn when
+/// fetching from its url fails, so that the feed only fails once every
+/// candidate has.
+fn get_feed_with_mirrors<'a, F: FeedGettable + 'a>(
+    fetch_feed: F,
+    mirrors: &'a [Url],
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> + 'a {
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+        let mut errors = vec![];
+
+        for candidate in std::iter::once(url).chain(mirrors) {
+            match fetch_feed.get_feed(feed_name, candidate, metadata) {
+                Ok(fetched_feed) => {
+                    if !mirrors.is_empty() {
+                        log::debug!("feed[{}] fetched from {}", feed_name, candidate);
+                    }
+                    return Ok(fetched_feed);
+                }
+                Err(err) => {
+                    log::debug!("feed[{}]: {} from {}", feed_name, err, candidate);
+                    errors.push(err);
+                }
+            }
+        }
+
+        Err(error_for_feed_candidates(errors))
+    }
+}
+
+/// Wraps a fetcher, waiting out any delay imposed by the rate limit of the
+/// feed's host before each fetch.
+fn get_feed_with_rate_limit<'a, F: FeedGettable + 'a>(
+    fetch_feed: F,
+    rate_limiter: Option<&'a HostRateLimiter>,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> + 'a {
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+        let delay = rate_limiter.map_or(Duration::ZERO, |limiter| limiter.reserve(url));
+        if !delay.is_zero() {
+            log::debug!("feed[{}]: rate limited for {:?}", feed_name, delay);
+            std::thread::sleep(delay);
+        }
+
+        fetch_feed.get_feed(feed_name, url, metadata)
+    }
+}
+
+/// Returns a random delay of up to `max_jitter`, or no delay when unset.
+fn random_jitter(max_jitter: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    if max_jitter.is_zero() {
+        return Duration::ZERO;
+    }
+
+    // a freshly keyed std hasher is random enough to spread out requests,
+    // without depending on a dedicated rng.
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    let max_nanos = u64::try_from(max_jitter.as_nanos()).unwrap_or(u64::MAX);
+
+    Duration::from_nanos(random % max_nanos.saturating_add(1))
+}
+
+/// Wraps a fetcher, sleeping for a random delay of up to `max_jitter` before
+/// fetching so that feeds fetched in parallel don't all hit a host at once.
+fn get_feed_with_jitter<F: FeedGettable>(
+    fetch_feed: F,
+    max_jitter: Duration,
+) -> impl Fn(&str, &Url, &CacheMetadata) -> Result<FetchedFeed, Error> {
+    move |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+        let jitter = random_jitter(max_jitter);
+        if !jitter.is_zero() {
+            log::debug!("feed[{}]: delaying fetch by {:?}", feed_name, jitter);
+            std::thread::sleep(jitter);
+        }
+
+        fetch_feed.get_feed(feed_name, url, metadata)
+    }
+}
+
+/// Returns the local name of the root element of an xml document, skipping
+/// any leading declarations, processing instructions and comments.
+fn xml_root_element_name(contents: &[u8]) -> Option<&str> {
+    let mut remaining = contents;
+
+    loop {
+        let start = remaining.iter().position(|&b| b == b'<')?;
+        remaining = &remaining[start + 1..];
+
+        let terminator: &[u8] = if remaining.starts_with(b"?") {
+            b"?>"
+        } else if remaining.starts_with(b"!--") {
+            b"-->"
+        } else if remaining.starts_with(b"!") {
+            b">"
+        } else {
+            let end = remaining
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || *b == b'/' || *b == b'>')
+                .unwrap_or(remaining.len());
+            let name = std::str::from_utf8(&remaining[..end]).ok()?;
+
+            return Some(name.rsplit(':').next().unwrap_or(name));
+        };
+
+        let end = remaining
+            .windows(terminator.len())
+            .position(|window| window == terminator)?;
+        remaining = &remaining[end + terminator.len()..];
+    }
+}
+
+/// Picks between a document that successfully parsed as both an RSS channel
+/// and an Atom feed by inspecting its root element, preferring RSS unless the
+/// root is an Atom `feed`.
+fn disambiguate_feed(contents: &[u8], channel: Channel, feed: Feed) -> RssOrAtomFeed {
+    match xml_root_element_name(contents) {
+        Some("feed") => RssOrAtomFeed::Atom(feed),
+        _ => RssOrAtomFeed::Rss2(channel),
+    }
+}
+
+/// A feed format, as declared by a response's `Content-Type` or as parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl std::fmt::Display for FeedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rss => f.write_str("rss"),
+            Self::Atom => f.write_str("atom"),
+            Self::Json => f.write_str("json"),
+        }
+    }
+}
+
+impl RssOrAtomFeed {
+    /// The format the feed was parsed as.
+    pub fn format(&self) -> FeedFormat {
+        match self {
+            RssOrAtomFeed::Rss2(_) => FeedFormat::Rss,
+            RssOrAtomFeed::Atom(_) => FeedFormat::Atom,
+            RssOrAtomFeed::Json(_) => FeedFormat::Json,
+        }
+    }
+}
+
+/// Returns a response's `Content-Type` header, if it's valid text.
+fn response_content_type(headers: &reqwest::header::HeaderMap) -> Option<&str> {
+    headers.get(reqwest::header::CONTENT_TYPE)?.to_str().ok()
+}
+
+/// Returns the feed format a response's `Content-Type` declares, if it
+/// declares one unambiguously. Generic types, such as `text/xml`, declare
+/// none.
+fn declared_feed_format(headers: &reqwest::header::HeaderMap) -> Option<FeedFormat> {
+    let content_type = response_content_type(headers)?;
+    let mime_type = content_type.split(';').next()?.trim();
+
+    match mime_type.to_ascii_lowercase().as_str() {
+        "application/rss+xml" => Some(FeedFormat::Rss),
+        "application/atom+xml" => Some(FeedFormat::Atom),
+        "application/feed+json" => Some(FeedFormat::Json),
+        _ => None,
+    }
+}
+
+/// Returns the encoding declared by the `charset` parameter of a response's
+/// `Content-Type` header, if it names a known encoding.
+fn declared_charset(headers: &reqwest::header::HeaderMap) -> Option<&'static Encoding> {
+    let content_type = response_content_type(headers)?;
+
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+            .flatten()
+    })
+}
+
+/// Returns the byte range of the encoding named by a document's leading xml
+/// declaration, such as `ISO-8859-1` in `<?xml version="1.0"
+/// encoding="ISO-8859-1"?>`.
+fn xml_declared_encoding_range(contents: &[u8]) -> Option<std::ops::Range<usize>> {
+    let start = contents.iter().position(|b| !b.is_ascii_whitespace())?;
+    if !contents[start..].starts_with(b"<?xml") {
+        return None;
+    }
+
+    let declaration_len = contents[start..]
+        .windows(2)
+        .position(|window| window == b"?>")?;
+    let declaration = &contents[start..start + declaration_len];
+    let attribute_start = declaration
+        .windows(b"encoding".len())
+        .position(|window| window == b"encoding")?
+        + b"encoding".len();
+
+    let mut remaining = declaration[attribute_start..]
+        .iter()
+        .enumerate()
+        .skip_while(|(_, b)| b.is_ascii_whitespace() || **b == b'=');
+    let (quote_idx, &quote) = remaining
+        .next()
+        .filter(|(_, b)| matches!(b, b'"' | b'\''))?;
+    let (value_end, _) = remaining.find(|(_, b)| **b == quote)?;
+
+    let value_start = start + attribute_start + quote_idx + 1;
+    Some(value_start..start + attribute_start + value_end)
+}
+
+/// Transcodes a feed body to UTF-8, which every feed parser expects, stripping
+/// any leading byte order mark. The encoding is taken from the byte order
+/// mark, then the xml declaration, then the response's `charset`, as servers
+/// frequently label feeds with a default charset regardless of their
+/// contents. JSON Feed is always UTF-8, so a body sniffing as JSON ignores
+/// the `charset`. Transcoded xml has its declaration rewritten to match.
+fn decode_feed_body<'a>(contents: &'a [u8], charset: Option<&'static Encoding>) -> Cow<'a, [u8]> {
+    let sniffs_as_json = contents
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .is_some_and(|byte| *byte == b'{');
+
+    let (encoding, body) = match Encoding::for_bom(contents) {
+        Some((encoding, bom_len)) => (encoding, &contents[bom_len..]),
+        None if sniffs_as_json => (encoding_rs::UTF_8, contents),
+        None => {
+            let declared_encoding = xml_declared_encoding_range(contents)
+                .and_then(|range| Encoding::for_label(&contents[range]));
+            let encoding = declared_encoding.or(charset).unwrap_or(encoding_rs::UTF_8);
+
+            (encoding, contents)
+        }
+    };
+
+    if encoding == encoding_rs::UTF_8 {
+        return Cow::Borrowed(body);
+    }
+
+    let mut decoded = encoding.decode_without_bom_handling(body).0.into_owned();
+    if let Some(range) = xml_declared_encoding_range(decoded.as_bytes()) {
+        decoded.replace_range(range, "UTF-8");
+    }
+
+    Cow::Owned(decoded.into_bytes())
+}
+
+/// Parses a response body as only the declared feed format.
+fn parse_declared_feed(contents: &[u8], format: FeedFormat) -> Option<RssOrAtomFeed> {
+    match format {
+        FeedFormat::Rss => Channel::read_from(contents).ok().map(RssOrAtomFeed::Rss2),
+        FeedFormat::Atom => Feed::read_from(contents).ok().map(RssOrAtomFeed::Atom),
+        FeedFormat::Json => jsonfeed::Feed::read_from(contents)
+            .ok()
+            .map(RssOrAtomFeed::Json),
+    }
+}
+
+/// The maximum number of leading bytes of an unparseable body included in
+/// its error.
+const BODY_SNIPPET_MAX_BYTES: usize = 200;
+
+/// Returns up to the first [BODY_SNIPPET_MAX_BYTES] of a body as text,
+/// truncated to the last whole character.
+fn body_snippet(contents: &[u8]) -> String {
+    let mut snippet = &contents[..contents.len().min(BODY_SNIPPET_MAX_BYTES)];
+    if let Err(err) = std::str::from_utf8(snippet) {
+        // only a character cut short by the truncation is dropped, any
+        // other invalid bytes are replaced.
+        if err.error_len().is_none() {
+            snippet = &snippet[..err.valid_up_to()];
+        }
+    }
+
+    String::from_utf8_lossy(snippet).trim().to_string()
+}
+
+/// Attempts to parse a fetched response body as its declared feed format,
+/// falling back to trying each of the supported feed formats when the body
+/// isn't of the declared format, or none was declared. Each attempt reads
+/// from the same buffered body. A body of no supported format fails with its
+/// `content_type` and leading bytes.
+fn parse_fetched_feed(
+    feed_name: &str,
+    contents: &[u8],
+    declared_format: Option<FeedFormat>,
+    content_type: Option<&str>,
+) -> Result<RssOrAtomFeed, Error> {
+    if let Some(feed) = declared_format.and_then(|format| parse_declared_feed(contents, format)) {
+        return Ok(feed);
+    }
+
+    let maybe_channel = Channel::read_from(contents).map_err(Error::from);
+    let maybe_feed = Feed::read_from(contents).map_err(Error::from);
+
+    match (maybe_channel, maybe_feed) {
+        (Ok(channel), Ok(feed)) => Ok(disambiguate_feed(contents, channel, feed)),
+        (Ok(channel), Err(_)) => Ok(RssOrAtomFeed::Rss2(channel)),
+        (Err(_), Ok(feed)) => Ok(RssOrAtomFeed::Atom(feed)),
+        // fallback to a json feed if neither xml format matches.
+        (Err(_), Err(_)) => jsonfeed::Feed::read_from(contents)
+            .map(RssOrAtomFeed::Json)
+            .map_err(|_| {
+                Error::new(ErrorKind::FeedIsNeitherAtomOrRss {
+                    feed_name: feed_name.to_string(),
+                    content_type: content_type.map(|content_type| content_type.to_string()),
+                    snippet: body_snippet(contents),
+                })
+            }),
+    }
+}
+
+/// Returns the stable file name a feed is cached under, the hex encoded
+/// SHA-256 digest of its name. Hashing keeps feed names containing path
+/// separators, or characters invalid in file names, within the cache path.
+fn cache_file_name(feed_name: &str) -> String {
+    sha256_hex(feed_name.as_bytes())
+}
+
+/// The number of leading hex characters of a feed's hashed name that its
+/// shard directory is named by.
+const CACHE_SHARD_PREFIX_LEN: usize = 2;
+
+/// How cache files are arranged within the cache directory.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheLayout {
+    /// every cache file directly within the cache directory.
+    #[default]
+    Flat,
+    /// cache files grouped into subdirectories named by the first two hex
+    /// characters of their feed's hashed name, git-style, so that no single
+    /// directory grows unwieldy.
+    Sharded,
+}
+
+impl CacheLayout {
+    /// Returns the directory holding the cache files of the feed whose name
+    /// hashes to `hash`.
+    fn dir_for_hash(self, cache_path: &Path, hash: &str) -> PathBuf {
+        match self {
+            Self::Flat => cache_path.to_path_buf(),
+            Self::Sharded => cache_path.join(&hash[..CACHE_SHARD_PREFIX_LEN]),
+        }
+    }
+
+    /// Returns the directory holding a feed's cache files.
+    fn feed_dir(self, cache_path: &Path, feed_name: &str) -> PathBuf {
+        self.dir_for_hash(cache_path, &cache_file_name(feed_name))
+    }
+}
+
+/// Returns the path of the file caching a feed's contents.
+fn cache_file_path(cache_path: &Path, feed_name: &str) -> PathBuf {
+    cache_path.join(cache_file_name(feed_name))
+}
+
+/// Returns the path of the sidecar file storing a feed's cache metadata.
+fn cache_metadata_file_path(cache_path: &Path, feed_name: &str) -> PathBuf {
+    cache_path.join(format!(
+        "{}.{}",
+        cache_file_name(feed_name),
+        CACHE_METADATA_EXTENSION
+    ))
+}
+
+/// Returns the path of the file caching only a feed's item keys.
+fn cache_keys_file_path(cache_path: &Path, feed_name: &str) -> PathBuf {
+    cache_path.join(format!(
+        "{}.{}",
+        cache_file_name(feed_name),
+        CACHE_KEYS_EXTENSION
+    ))
+}
+
+/// Returns the path of the sidecar file recording the human readable name of
+/// the feed a cache file belongs to. Each feed has its own sidecar so that
+/// feeds may be cached concurrently.
+fn cache_name_file_path(cache_path: &Path, feed_name: &str) -> PathBuf {
+    cache_path.join(format!(
+        "{}.{}",
+        cache_file_name(feed_name),
+        CACHE_NAME_EXTENSION
+    ))
+}
+
+/// Returns the path of the sidecar file recording a feed's consecutive
+/// failures, as used to back off checking it.
+fn cache_failures_file_path(cache_path: &Path, feed_name: &str) -> PathBuf {
+    cache_path.join(format!(
+        "{}.{}",
+        cache_file_name(feed_name),
+        CACHE_FAILURES_EXTENSION
+    ))
+}
+
+/// Loads a feed's cache metadata, defaulting to empty metadata if the sidecar
+/// file is missing or unreadable.
+fn load_cache_metadata_from_disk(cache_path: &Path, feed_name: &str) -> CacheMetadata {
+    let metadata_file_path = cache_metadata_file_path(cache_path, feed_name);
+
+    let Ok(metadata_file) = OpenOptions::new().read(true).open(&metadata_file_path) else {
+        return CacheMetadata::default();
+    };
+
+    serde_json::from_reader(BufReader::new(metadata_file)).unwrap_or_else(|err| {
+        log::debug!(
+            "ignoring invalid cache metadata for feed[{}]: {}",
+            feed_name,
+            err
+        );
+        CacheMetadata::default()
+    })
+}
+
+/// Reads feeds cached on disk beneath a cache root, as laid out by `layout`.
+pub(crate) struct DiskCacheReader {
+    cache_root: PathBuf,
+    layout: CacheLayout,
+}
+
+fn load_cached_feed_from_disk(cache_path: &Path, layout: CacheLayout) -> DiskCacheReader {
+    DiskCacheReader {
+        cache_root: cache_path.to_owned(),
+        layout,
+    }
+}
+
+impl DiskCacheReader {
+    /// Moves a feed's cache from where it was kept before cache files were
+    /// named by hash, `<cache_root>/<feed_name>` and its `.meta` sidecar, to
+    /// its hashed path, so that upgrading doesn't report every item as new.
+    /// A feed already cached by hash is left as is.
+    fn migrate_legacy_cache(&self, feed_name: &str) -> Result<(), Error> {
+        let legacy_path = self.cache_root.join(feed_name);
+        if !legacy_path.is_file() {
+            return Ok(());
+        }
+
+        let cache_path = self.layout.feed_dir(&self.cache_root, feed_name);
+        let cache_file_path = cache_file_path(&cache_path, feed_name);
+        if cache_file_path.exists() || cache_keys_file_path(&cache_path, feed_name).exists() {
+            return Ok(());
+        }
+
+        let io_error_for_feed =
+            |err: io::Error| Error::from(err).with_data(format!("feed[{}]", feed_name));
+        ensure_cache_dir(&cache_path)?;
+        std::fs::rename(&legacy_path, &cache_file_path).map_err(io_error_for_feed)?;
+        let legacy_metadata_path = self
+            .cache_root
+            .join(format!("{}.{}", feed_name, CACHE_METADATA_EXTENSION));
+        match std::fs::rename(
+            legacy_metadata_path,
+            cache_metadata_file_path(&cache_path, feed_name),
+        ) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(io_error_for_feed(err))
+            }
+            _ => (),
+        }
+        log::info!(
+            "feed[{}]: migrated legacy cache file {} to {}",
+            feed_name,
+            legacy_path.display(),
+            cache_file_path.display()
+        );
+
+        cache_name_to_disk(&cache_path, feed_name)
+    }
+}
+
+impl FeedCacheReadable for DiskCacheReader {
+    fn read_cache(&self, feed_name: &str) -> Result<CachedFeed, Error> {
+        self.migrate_legacy_cache(feed_name)?;
+        let cache_path = self.layout.feed_dir(&self.cache_root, feed_name);
+
+        // a feed is cached in either mode, preferring its keys if both exist.
+        let (contents, cache_file_path) = match load_cached_keys_from_disk(&cache_path, feed_name) {
+            Ok(keys) => (
+                CacheContents::Keys(keys),
+                cache_keys_file_path(&cache_path, feed_name),
+            ),
+            Err(Error {
+                kind: ErrorKind::IoErr(err),
+                ..
+            }) if err.kind() == io::ErrorKind::NotFound => (
+                CacheContents::Feed(load_cached_feed_contents_from_disk(&cache_path, feed_name)?),
+                cache_file_path(&cache_path, feed_name),
+            ),
+            Err(err) => return Err(err),
+        };
+        let metadata = load_cache_metadata_from_disk(&cache_path, feed_name);
+        let cached_feed = CachedFeed::from_contents(contents, metadata);
+
+        let maybe_modified =
+            std::fs::metadata(cache_file_path).and_then(|file_metadata| file_metadata.modified());
+        match maybe_modified {
+            Ok(modified) => Ok(cached_feed.with_cached_at(modified)),
+            Err(_) => Ok(cached_feed),
+        }
+    }
+
+    fn read_cache_validators(&self, feed_name: &str) -> Result<CachedFeed, Error> {
+        self.migrate_legacy_cache(feed_name)?;
+        let cache_path = self.layout.feed_dir(&self.cache_root, feed_name);
+        let modified = |path: PathBuf| std::fs::metadata(path).and_then(|file| file.modified());
+
+        // either cache file's presence suffices, without reading it.
+        let cached_at = modified(cache_keys_file_path(&cache_path, feed_name))
+            .or_else(|_| modified(cache_file_path(&cache_path, feed_name)))
+            .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+        let metadata = load_cache_metadata_from_disk(&cache_path, feed_name);
+
+        Ok(
+            CachedFeed::from_contents(CacheContents::Keys(CachedKeys::default()), metadata)
+                .with_cached_at(cached_at),
+        )
+    }
+}
+
+fn load_cached_keys_from_disk(cache_path: &Path, feed_name: &str) -> Result<CachedKeys, Error> {
+    let keys_file = OpenOptions::new()
+        .read(true)
+        .open(cache_keys_file_path(cache_path, feed_name))
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+
+    serde_json::from_reader(BufReader::new(keys_file)).map_err(|err| {
+        Error::new(ErrorKind::InvalidCache(feed_name.to_string())).with_data(err.to_string())
+    })
+}
+
+/// Loads a cached feed document, reading the cache file once into a buffer
+/// that each format's parser is attempted against in turn.
+fn load_cached_feed_contents_from_disk(
+    cache_path: &Path,
+    feed_name: &str,
+) -> Result<RssOrAtomFeed, Error> {
+    let contents = std::fs::read(cache_file_path(cache_path, feed_name))
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+
+    let channel_load_result = Channel::read_from(contents.as_slice()).map_err(Error::from);
+    let feed_load_result = Feed::read_from(contents.as_slice()).map_err(Error::from);
+
+    match (channel_load_result, feed_load_result) {
+        (Ok(channel), Ok(feed)) => Ok(disambiguate_feed(&contents, channel, feed)),
+        (Ok(channel), Err(_)) => Ok(RssOrAtomFeed::Rss2(channel)),
+        (Err(_), Ok(feed)) => Ok(RssOrAtomFeed::Atom(feed)),
+        // retain why each format failed to load for diagnosing the cache.
+        (Err(rss_err), Err(atom_err)) => jsonfeed::Feed::read_from(contents.as_slice())
+            .map(RssOrAtomFeed::Json)
+            .map_err(|json_err| {
+                Error::new(ErrorKind::InvalidCache(feed_name.to_string())).with_data(format!(
+                    "rss: {}, atom: {}, json: {}",
+                    rss_err.kind, atom_err.kind, json_err
+                ))
+            }),
+    }
+}
+
+fn cache_feed_to_disk(
+    cache_path: &Path,
+    layout: CacheLayout,
+) -> impl Fn(&str, &CachedFeed) -> Result<(), Error> {
+    let cache_root = cache_path.to_owned();
+
+    move |feed_name: &str, cached_feed: &CachedFeed| {
+        let cache_path = layout.feed_dir(&cache_root, feed_name);
+        ensure_cache_dir(&cache_path)?;
+
+        match &cached_feed.contents {
+            CacheContents::Feed(feed) => {
+                cache_feed_contents_to_disk(&cache_path, feed_name, feed)?;
+                remove_stale_cache_file(feed_name, &cache_keys_file_path(&cache_path, feed_name))?;
+            }
+            // only keys remain to be cached, so fall back to caching them.
+            CacheContents::Keys(keys) => cache_keys_contents_to_disk(&cache_path, feed_name, keys)?,
+        }
+        cache_name_to_disk(&cache_path, feed_name)?;
+        cache_metadata_to_disk(&cache_path, feed_name, &cached_feed.metadata)
+    }
+}
+
+/// Caches only the keys of a feed's items, rather than the feed itself.
+fn cache_feed_keys_to_disk(
+    cache_path: &Path,
+    layout: CacheLayout,
+) -> impl Fn(&str, &CachedFeed) -> Result<(), Error> {
+    let cache_root = cache_path.to_owned();
+
+    move |feed_name: &str, cached_feed: &CachedFeed| {
+        let cache_path = layout.feed_dir(&cache_root, feed_name);
+        ensure_cache_dir(&cache_path)?;
+
+        let keys = CachedKeys::from(&cached_feed.contents);
+        cache_keys_contents_to_disk(&cache_path, feed_name, &keys)?;
+        cache_name_to_disk(&cache_path, feed_name)?;
+        cache_metadata_to_disk(&cache_path, feed_name, &cached_feed.metadata)
+    }
+}
+
+/// Writes feeds to the cache on disk beneath a cache root, as configured by
+/// `cache_mode` and laid out by `layout`.
+pub(crate) struct DiskCacheWriter {
+    cache_root: PathBuf,
+    cache_mode: CacheMode,
+    layout: CacheLayout,
+}
+
+/// Returns a cache writer persisting feeds as configured by `cache_mode`.
+fn cache_writer_for_mode(
+    cache_path: &Path,
+    cache_mode: CacheMode,
+    layout: CacheLayout,
+) -> DiskCacheWriter {
+    DiskCacheWriter {
+        cache_root: cache_path.to_owned(),
+        cache_mode,
+        layout,
+    }
+}
+
+impl FeedCacheWriteable for DiskCacheWriter {
+    fn write_cache(&self, feed_name: &str, cached_feed: &CachedFeed) -> Result<(), Error> {
+        match self.cache_mode {
+            CacheMode::Full => {
+                cache_feed_to_disk(&self.cache_root, self.layout)(feed_name, cached_feed)
+            }
+            CacheMode::Keys => {
+                cache_feed_keys_to_disk(&self.cache_root, self.layout)(feed_name, cached_feed)
+            }
+        }
+    }
+
+    /// Touches the feed's cache file, in whichever mode it was cached, so
+    /// that its cache time reflects this check, then persists its metadata.
+    fn refresh_cache(&self, feed_name: &str, metadata: &CacheMetadata) -> Result<(), Error> {
+        let cache_path = self.layout.feed_dir(&self.cache_root, feed_name);
+        let touch = |path: PathBuf| {
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+        };
+
+        touch(cache_keys_file_path(&cache_path, feed_name))
+            .or_else(|err| match err.kind() {
+                io::ErrorKind::NotFound => touch(cache_file_path(&cache_path, feed_name)),
+                _ => Err(err),
+            })
+            .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+        cache_metadata_to_disk(&cache_path, feed_name, metadata)
+    }
+}
+
+fn cache_keys_contents_to_disk(
+    cache_path: &Path,
+    feed_name: &str,
+    keys: &CachedKeys,
+) -> Result<(), Error> {
+    let keys_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(cache_keys_file_path(cache_path, feed_name))
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+
+    serde_json::to_writer(keys_file, keys).map_err(|err| Error::new(ErrorKind::JsonErr(err)))?;
+
+    // a full cache from a previous mode would otherwise go stale.
+    remove_stale_cache_file(feed_name, &cache_file_path(cache_path, feed_name))
+}
+
+/// Removes a cache file left behind by a different cache mode, if any.
+fn remove_stale_cache_file(feed_name: &str, path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            Err(Error::from(err).with_data(format!("feed[{}]", feed_name)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Records a feed's name in the sidecar alongside its hashed cache file.
+fn cache_name_to_disk(cache_path: &Path, feed_name: &str) -> Result<(), Error> {
+    std::fs::write(cache_name_file_path(cache_path, feed_name), feed_name)
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))
+}
+
+/// Writes a feed's cache metadata to its sidecar file, removing any stale
+/// sidecar if there is no metadata to persist.
+fn cache_metadata_to_disk(
+    cache_path: &Path,
+    feed_name: &str,
+    metadata: &CacheMetadata,
+) -> Result<(), Error> {
+    let metadata_file_path = cache_metadata_file_path(cache_path, feed_name);
+
+    if metadata.is_empty() {
+        return match std::fs::remove_file(&metadata_file_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(Error::from(err).with_data(format!("feed[{}]", feed_name)))
+            }
+            _ => Ok(()),
+        };
+    }
+
+    let metadata_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&metadata_file_path)
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+
+    serde_json::to_writer(metadata_file, metadata)
+        .map_err(|err| Error::new(ErrorKind::JsonErr(err)))
+}
+
+fn cache_feed_contents_to_disk(
+    cache_path: &Path,
+    feed_name: &str,
+    feed: &RssOrAtomFeed,
+) -> Result<(), Error> {
+    let cache_file_path = cache_file_path(cache_path, feed_name);
+    let cache_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&cache_file_path)
+        .map_err(|err| Error::from(err).with_data(format!("feed[{}]", feed_name)))?;
+
+    log::debug!(
+        "writing cache for feed[{}] to {}",
+        feed_name,
+        cache_file_path.display()
+    );
+
+    match feed {
+        RssOrAtomFeed::Rss2(channel) => channel
+            .write_to(cache_file)
+            .map(|_| ())
+            .map_err(Error::from),
+        RssOrAtomFeed::Atom(feed) => feed.write_to(cache_file).map(|_| ()).map_err(Error::from),
+        RssOrAtomFeed::Json(feed) => feed
+            .write_to(cache_file)
+            .map(|_| ())
+            .map_err(|err| Error::new(ErrorKind::JsonErr(err))),
+    }
+}
+
+/// A cache writer that never touches the cache when `dry_run` is set.
+struct DryRunCacheWriter<W> {
+    feed_writer: W,
+    dry_run: bool,
+}
+
+/// Wraps a cache writer, substituting a writer that never touches the cache
+/// when `dry_run` is set.
+fn cache_writer_with_dry_run<W: FeedCacheWriteable>(
+    feed_writer: W,
+    dry_run: bool,
+) -> impl FeedCacheWriteable {
+    DryRunCacheWriter {
+        feed_writer,
+        dry_run,
+    }
+}
+
+impl<W: FeedCacheWriteable> FeedCacheWriteable for DryRunCacheWriter<W> {
+    fn write_cache(&self, feed_name: &str, feed: &CachedFeed) -> Result<(), Error> {
+        if self.dry_run {
+            log::debug!("dry-run: skipping cache write for feed[{}]", feed_name);
+            Ok(())
+        } else {
+            self.feed_writer.write_cache(feed_name, feed)
+        }
+    }
+
+    fn refresh_cache(&self, feed_name: &str, metadata: &CacheMetadata) -> Result<(), Error> {
+        if self.dry_run {
+            log::debug!("dry-run: skipping cache refresh for feed[{}]", feed_name);
+            Ok(())
+        } else {
+            self.feed_writer.refresh_cache(feed_name, metadata)
+        }
+    }
+}
+
+/// What is persisted when caching a feed.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+    /// the feed document in full.
+    #[default]
+    Full,
+    /// only the links and identities of the feed's items, which is far
+    /// smaller and needn't be reparsed.
+    Keys,
+}
+
+/// The key new items are detected by.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DedupKey {
+    /// an item is new if its link has not been seen before.
+    #[default]
+    Link,
+    /// an item is new if its RSS guid or Atom id has not been seen before,
+    /// falling back to its link.
+    Guid,
+}
+
+/// The element of an RSS item its link is taken from.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkSource {
+    /// the item's `<link>`.
+    #[default]
+    Link,
+    /// the url of the item's `<enclosure>`, such as a podcast episode's
+    /// audio, skipping items without one.
+    Enclosure,
+    /// both the item's `<link>` and the url of its `<enclosure>`.
+    Both,
+}
+
+impl RssOrAtomFeed {
+    /// Rewrites each RSS item's link to be taken from `link_source`. When
+    /// taking both, an item with an enclosure is followed by a copy linking
+    /// to it, without a guid so the copy is identified by its link. Other
+    /// feed formats are left as is.
+    fn with_link_source(mut self, link_source: LinkSource) -> Self {
+        let RssOrAtomFeed::Rss2(channel) = &mut self else {
+            return self;
+        };
+        let enclosure_url = |item: &rss::Item| item.enclosure().map(|e| e.url().to_string());
+
+        match link_source {
+            LinkSource::Link => (),
+            LinkSource::Enclosure => {
+                for item in channel.items.iter_mut() {
+                    item.link = enclosure_url(item);
+                }
+            }
+            LinkSource::Both => {
+                channel.items = std::mem::take(&mut channel.items)
+                    .into_iter()
+                    .flat_map(|item| {
+                        let enclosure_item = enclosure_url(&item).map(|url| rss::Item {
+                            link: Some(url),
+                            guid: None,
+                            ..item.clone()
+                        });
+
+                        std::iter::once(item).chain(enclosure_item)
+                    })
+                    .collect();
+            }
+        }
+
+        self
+    }
+}
+
+/// Options controlling how an individual feed is checked.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FeedCheckOptions {
+    /// feeds cached more recently than this are not re-fetched.
+    pub min_cache_age: Duration,
+    pub dedup_key: DedupKey,
+    /// the element of each RSS item its link is taken from.
+    pub link_source: LinkSource,
+    /// only new links matching this filter are returned.
+    pub link_filter: LinkFilter,
+    /// only the links of items whose categories match this filter are
+    /// returned.
+    pub category_filter: CategoryFilter,
+    /// new items published before this are discarded.
+    pub since: Option<DateTime<Utc>>,
+    /// discard new items without a publication date when filtering by `since`.
+    pub skip_undated: bool,
+    /// links are compared in their normalized form, though new items retain
+    /// their original link.
+    pub url_normalizer: UrlNormalizer,
+    /// also report cached items whose links are no longer in the feed.
+    pub report_removed: bool,
+    /// also skip fetching feeds still within the freshness lifetime declared
+    /// by their last response's `Cache-Control: max-age`. A feed is skipped
+    /// while within either this or `min_cache_age`, so the longer applies.
+    pub respect_cache_control: bool,
+    /// neither read nor write the cache, reporting every item currently in
+    /// the feed as new.
+    pub no_cache: bool,
+    /// report every item currently in a feed that has never been cached as
+    /// new, rather than only seeding its cache.
+    pub report_on_first_seed: bool,
+    /// also report previously cached items whose content has since changed.
+    pub detect_updates: bool,
+    /// read only a feed's cached validators ahead of fetching it, deferring
+    /// reading, and parsing, its cached items until it's known to have
+    /// changed. Unchanged feeds are never parsed at all.
+    pub check_only_changed: bool,
+    /// bounds the new items reported for a single feed, such as when a feed
+    /// resets and every item appears new at once.
+    pub max_items_per_feed: Option<NonZeroUsize>,
+}
+
+impl FeedCheckOptions {
+    /// Returns whether an item's link and categories pass the feed's filters.
+    fn is_match(&self, item: &FeedItem) -> bool {
+        self.link_filter.is_match(&item.link) && self.category_filter.is_match(&item.categories)
+    }
+
+    /// Returns whether an item was published recently enough to be reported.
+    fn is_recent_enough(&self, item: &FeedItem) -> bool {
+        match (self.since, item.published) {
+            (None, _) => true,
+            (Some(since), Some(published)) => published >= since,
+            (Some(_), None) => !self.skip_undated,
+        }
+    }
+}
+
+/// Deduplicates items by normalized link, retaining the first item for each
+/// link.
+fn unique_items_by_link<I: IntoIterator<Item = FeedItem>>(
+    normalizer: &UrlNormalizer,
+    items: I,
+) -> Vec<FeedItem> {
+    first_occurrences(items, |item| normalizer.normalize(&item.link))
+}
+
+/// Returns the items found in `new_feed` whose normalized links aren't present
+/// in `cached_feed`.
+fn new_items_by_link<C: ItemKeysProduceable, I: ItemsProduceable>(
+    normalizer: &UrlNormalizer,
+    cached_feed: &C,
+    new_feed: &I,
+) -> Vec<FeedItem> {
+    let cached_item_links: HashSet<_> = cached_feed
+        .get_item_links()
+        .iter()
+        .map(|link| normalizer.normalize(link))
+        .collect();
+
+    unique_items_by_link(
+        normalizer,
+        new_feed
+            .get_items()
+            .into_iter()
+            .filter(|item| !cached_item_links.contains(&normalizer.normalize(&item.link))),
+    )
+}
+
+/// Returns the items in `new_feed` whose identity isn't present in
+/// `cached_feed`.
+fn new_items_by_identity<C: ItemKeysProduceable, I: ItemIdentifiable>(
+    normalizer: &UrlNormalizer,
+    cached_feed: &C,
+    new_feed: &I,
+) -> Vec<FeedItem> {
+    let cached_item_keys: HashSet<_> = cached_feed.get_item_ids().into_iter().collect();
+
+    unique_items_by_link(
+        normalizer,
+        new_feed
+            .get_item_identities()
+            .into_iter()
+            .filter(|identity| !cached_item_keys.contains(&identity.key))
+            .flat_map(|identity| identity.items),
+    )
+}
+
+/// Returns the items cached for a feed whose normalized links are no longer
+/// present in `new_feed`. Items cached as keys are known only by their link.
+fn removed_items_by_link<I: ItemsProduceable>(
+    normalizer: &UrlNormalizer,
+    cached_contents: &CacheContents,
+    new_feed: &I,
+) -> Vec<FeedItem> {
+    let new_item_links: HashSet<_> = new_feed
+        .get_items()
+        .iter()
+        .map(|item| normalizer.normalize(&item.link))
+        .collect();
+    let cached_items = match cached_contents {
+        CacheContents::Feed(cached_feed) => cached_feed.get_items(),
+        CacheContents::Keys(cached_keys) => cached_keys
+            .links
+            .iter()
+            .map(|link| FeedItem {
+                link: link.clone(),
+                title: None,
+                published: None,
+                categories: vec![],
+            })
+            .collect(),
+    };
+
+    unique_items_by_link(
+        normalizer,
+        cached_items
+            .into_iter()
+            .filter(|item| !new_item_links.contains(&normalizer.normalize(&item.link))),
+    )
+}
+
+/// Returns the items in `new_feed` that were cached under the same identity
+/// but whose content has since changed. Items only cached before content
+/// hashes were kept are never reported.
+fn updated_items_by_content<C: ItemKeysProduceable, I: ItemIdentifiable>(
+    normalizer: &UrlNormalizer,
+    cached_feed: &C,
+    new_feed: &I,
+) -> Vec<FeedItem> {
+    let cached_content_hashes = cached_feed.get_item_content_hashes();
+
+    unique_items_by_link(
+        normalizer,
+        new_feed
+            .get_item_identities()
+            .into_iter()
+            .filter(|identity| {
+                cached_content_hashes
+                    .get(&identity.key)
+                    .is_some_and(|cached_hash| cached_hash != &identity.content_hash)
+            })
+            .flat_map(|identity| identity.items),
+    )
+}
+
+/// Whether, and how, a feed was fetched while checking it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStatus {
+    /// the feed wasn't fetched, such as while its cache was fresh.
+    #[default]
+    Skipped,
+    /// the feed responded that it's unchanged since it was cached.
+    NotModified,
+    /// the feed was fetched in full.
+    Modified,
+}
+
+/// The items added to, updated in, and removed from, a feed since it was last
+/// cached.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FeedChanges {
+    pub fetch_status: FetchStatus,
+    pub new_items: Vec<FeedItem>,
+    /// only populated when detecting updates, never repeating a new item.
+    pub updated_items: Vec<FeedItem>,
+    /// only populated when reporting removed items.
+    pub removed_items: Vec<FeedItem>,
+}
+
+impl FeedChanges {
+    /// Returns whether the feed neither gained, changed nor lost any items.
+    pub fn is_empty(&self) -> bool {
+        self.new_items.is_empty() && self.updated_items.is_empty() && self.removed_items.is_empty()
+    }
+}
+
+/// How a feed's cache is updated once it's been checked.
+#[allow(clippy::large_enum_variant)]
+enum CacheUpdate {
+    /// the fetched feed replaces the cache.
+    Write(CachedFeed),
+    /// the feed is unchanged, so its cache is kept, only refreshing its
+    /// metadata and the time it was last checked.
+    Refresh(CacheMetadata),
+}
+
+impl CacheUpdate {
+    fn apply<W: FeedCacheWriteable>(&self, feed_name: &str, feed_writer: &W) -> Result<(), Error> {
+        match self {
+            Self::Write(new_feed) => feed_writer.write_cache(feed_name, new_feed),
+            Self::Refresh(metadata) => feed_writer.refresh_cache(feed_name, metadata),
+        }
+    }
+}
+
+/// The state of a feed's cache prior to fetching it.
+#[allow(clippy::large_enum_variant)]
+enum CacheLookup {
+    /// the feed was cached within the minimum cache age and needn't be fetched.
+    Fresh,
+    /// the feed was cached and should be fetched and diffed against the cache.
+    Stale(CachedFeed),
+    /// the feed has never been cached.
+    Missing,
+    /// the cache is bypassed, so every item in the fetched feed is reported
+    /// and nothing is cached.
+    Bypassed,
+}
+
+impl CacheLookup {
+    /// The validators to send with the feed's request.
+    fn metadata(&self) -> CacheMetadata {
+        match self {
+            Self::Stale(cached_feed) => cached_feed.metadata.clone(),
+            Self::Fresh | Self::Missing | Self::Bypassed => CacheMetadata::default(),
+        }
+    }
+}
+
+/// Classifies the result of reading a feed's cache, bubbling up any error
+/// other than a missing cache file.
+fn lookup_cache(
+    feed_name: &str,
+    maybe_cached_feed: Result<CachedFeed, Error>,
+    options: &FeedCheckOptions,
+) -> Result<CacheLookup, Error> {
+    match maybe_cached_feed {
+        Ok(cached_feed) => {
+            log::debug!("cache file found for {}", feed_name);
+
+            if cached_feed.is_fresh(options.min_cache_age) {
+                log::debug!(
+                    "feed[{}] cached within the last {:?}, skipping fetch",
+                    feed_name,
+                    options.min_cache_age
+                );
+                Ok(CacheLookup::Fresh)
+            } else if options.respect_cache_control
+                && cached_feed.metadata.is_fresh_per_server(Utc::now())
+            {
+                log::debug!(
+                    "feed[{}] within its cache-control max-age, skipping fetch",
+                    feed_name
+                );
+                Ok(CacheLookup::Fresh)
+            } else {
+                Ok(CacheLookup::Stale(cached_feed))
+            }
+        }
+
+        Err(Error {
+            kind: ErrorKind::IoErr(err),
+            ..
+        }) if err.kind() == io::ErrorKind::NotFound => {
+            log::debug!("cache file not found for {}", feed_name);
+            Ok(CacheLookup::Missing)
+        }
+
+        // any other Error should be bubbled up
+        Err(err) => Err(err),
+    }
+}
+
+/// Diffs a freshly fetched feed against its cache, returning the changes
+/// to report and how the cache is updated, if at all. A feed without a prior
+/// cache reports no changes.
+fn diff_fetched_feed(
+    feed_name: &str,
+    cache_lookup: CacheLookup,
+    fetched_feed: FetchedFeed,
+    options: &FeedCheckOptions,
+) -> (FeedChanges, Option<CacheUpdate>) {
+    let new_feed = match fetched_feed {
+        FetchedFeed::NotModified(metadata) => {
+            log::debug!("feed[{}] not modified since last cached", feed_name);
+            let cache_update = match cache_lookup {
+                CacheLookup::Stale(cached_feed) => Some(CacheUpdate::Refresh(
+                    cached_feed.metadata.revalidated_by(metadata),
+                )),
+                // there's no cache to refresh.
+                CacheLookup::Fresh | CacheLookup::Missing | CacheLookup::Bypassed => None,
+            };
+            return (FeedChanges::default(), cache_update);
+        }
+        FetchedFeed::Modified(new_feed) => new_feed.with_link_source(options.link_source),
+    };
+
+    // fetched feeds are always whole, only caches may be reduced to keys.
+    let CacheContents::Feed(new_feed_contents) = &new_feed.contents else {
+        return (FeedChanges::default(), Some(CacheUpdate::Write(new_feed)));
+    };
+    log::debug!("feed[{}]: {}", feed_name, new_feed_contents.link_counts());
+
+    let normalizer = &options.url_normalizer;
+    let all_current_items = || {
+        let mut new_items = unique_items_by_link(normalizer, new_feed_contents.get_items());
+        new_items.retain(|item| options.is_match(item) && options.is_recent_enough(item));
+
+        FeedChanges {
+            new_items,
+            ..Default::default()
+        }
+    };
+    let cached_feed = match cache_lookup {
+        CacheLookup::Stale(cached_feed) => cached_feed,
+        CacheLookup::Bypassed => return (all_current_items(), None),
+        // an existing cache is always diffed, even if it holds no items.
+        CacheLookup::Missing if options.report_on_first_seed => {
+            log::debug!("feed[{}] seeded, reporting every current item", feed_name);
+            return (all_current_items(), Some(CacheUpdate::Write(new_feed)));
+        }
+        CacheLookup::Fresh | CacheLookup::Missing => {
+            return (FeedChanges::default(), Some(CacheUpdate::Write(new_feed)))
+        }
+    };
+
+    let mut new_items = match options.dedup_key {
+        DedupKey::Link => new_items_by_link(normalizer, &cached_feed.contents, new_feed_contents),
+        DedupKey::Guid => {
+            new_items_by_identity(normalizer, &cached_feed.contents, new_feed_contents)
+        }
+    };
+    new_items.retain(|item| options.is_match(item) && options.is_recent_enough(item));
+
+    let updated_items = if options.detect_updates {
+        let new_links: HashSet<_> = new_items
+            .iter()
+            .map(|item| normalizer.normalize(&item.link))
+            .collect();
+        let mut updated_items =
+            updated_items_by_content(normalizer, &cached_feed.contents, new_feed_contents);
+        updated_items.retain(|item| {
+            options.is_match(item) && !new_links.contains(&normalizer.normalize(&item.link))
+        });
+        updated_items
+    } else {
+        vec![]
+    };
+
+    let removed_items = if options.report_removed {
+        let mut removed_items =
+            removed_items_by_link(normalizer, &cached_feed.contents, new_feed_contents);
+        removed_items.retain(|item| options.is_match(item));
+        removed_items
+    } else {
+        vec![]
+    };
+
+    (
+        FeedChanges {
+            new_items,
+            updated_items,
+            removed_items,
+            ..Default::default()
+        },
+        Some(CacheUpdate::Write(new_feed)),
+    )
+}
+
+/// Truncates a feed's new items to at most `max_items`, keeping the most
+/// recently published when every item is dated, otherwise the first in feed
+/// order. Kept items remain in feed order.
+fn truncate_new_items(
+    feed_name: &str,
+    new_items: &mut Vec<FeedItem>,
+    max_items: Option<NonZeroUsize>,
+) {
+    let Some(max_items) = max_items.map(NonZeroUsize::get) else {
+        return;
+    };
+    let found = new_items.len();
+    if found <= max_items {
+        return;
+    }
+
+    if new_items.iter().all(|item| item.published.is_some()) {
+        // the sort is stable, so equally recent items are kept in feed order.
+        let mut by_recency: Vec<_> = (0..found).collect();
+        by_recency.sort_by_key(|&index| std::cmp::Reverse(new_items[index].published));
+        let kept: HashSet<_> = by_recency.into_iter().take(max_items).collect();
+
+        *new_items = std::mem::take(new_items)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, item)| kept.contains(&index).then_some(item))
+            .collect();
+        log::warn!(
+            "feed[{}]: found {} new items, reporting only the {} most recent",
+            feed_name,
+            found,
+            max_items
+        );
+    } else {
+        new_items.truncate(max_items);
+        log::warn!(
+            "feed[{}]: found {} new items, reporting only the first {}",
+            feed_name,
+            found,
+            max_items
+        );
+    }
+}
+
+/// Handle the lookup of and caching of an individual feed.
+fn get_and_cache_new_items_from_feed<
+    R: FeedCacheReadable,
+    F: FeedGettable,
+    W: FeedCacheWriteable,
+>(
+    feed_name: &str,
+    feed_url: &Url,
+    feed_cache_readable: R,
+    fetch_feed: F,
+    feed_writer: W,
+    options: &FeedCheckOptions,
+) -> Result<FeedChanges, Error> {
+    let cache_lookup = if options.no_cache {
+        CacheLookup::Bypassed
+    } else if options.check_only_changed {
+        lookup_cache(
+            feed_name,
+            feed_cache_readable.read_cache_validators(feed_name),
+            options,
+        )?
+    } else {
+        lookup_cache(
+            feed_name,
+            feed_cache_readable.read_cache(feed_name),
+            options,
+        )?
+    };
+    if let CacheLookup::Fresh = cache_lookup {
+        return Ok(FeedChanges::default());
+    }
+
+    let fetched_feed = fetch_feed.get_feed(feed_name, feed_url, &cache_lookup.metadata())?;
+    let fetch_status = fetched_feed.fetch_status();
+    let cache_lookup = match cache_lookup {
+        // only a changed feed is diffed, so only its cached items are read.
+        CacheLookup::Stale(_)
+            if options.check_only_changed && fetch_status == FetchStatus::Modified =>
+        {
+            CacheLookup::Stale(feed_cache_readable.read_cache(feed_name)?)
+        }
+        cache_lookup => cache_lookup,
+    };
+    let (mut changes, maybe_cache_update) =
+        diff_fetched_feed(feed_name, cache_lookup, fetched_feed, options);
+    changes.fetch_status = fetch_status;
+    truncate_new_items(
+        feed_name,
+        &mut changes.new_items,
+        options.max_items_per_feed,
+    );
+
+    if let Some(cache_update) = maybe_cache_update {
+        cache_update
+            .apply(feed_name, &feed_writer)
+            .map_err(|err| err.with_data("writing cache"))?;
+    }
+
+    Ok(changes)
+}
+
+/// Configures a single check of every feed in a configuration directory.
+#[derive(Debug, Clone)]
+pub struct CheckConfig {
+    /// the directory path to source configuration files.
+    pub conf_path: PathBuf,
+    /// feeds checked in place of those configured in `conf_path`, such as
+    /// those parsed by [walker::parse_feed_list].
+    pub feeds: Option<BTreeMap<String, walker::FeedUrl>>,
+    /// restricts which of the feeds are checked by name.
+    pub feed_name_filter: FeedNameFilter,
+    /// the directory path to store all cache files.
+    pub cache_path: PathBuf,
+    pub cache_mode: CacheMode,
+    pub cache_layout: CacheLayout,
+    pub http_client_options: HttpClientOptions,
+    pub retry_policy: RetryPolicy,
+    /// skip feeds within the backoff of their consecutive failures, if set.
+    pub error_backoff: Option<BackoffPolicy>,
+    /// the upper bound of a random delay preceding each feed's fetch, spreading
+    /// out requests to the same host. Zero fetches immediately.
+    pub fetch_jitter: Duration,
+    /// the maximum number of requests per second made to any one host,
+    /// including retries. Unlimited when unset.
+    pub per_host_rps: Option<NonZeroU32>,
+    /// options applied to every feed, alongside its own configuration.
+    pub feed_check_options: FeedCheckOptions,
+    /// the maximum number of feeds fetched concurrently, defaulting to the
+    /// number of CPUs.
+    pub max_concurrency: Option<NonZeroUsize>,
+    /// report new links without writing any changes to the cache.
+    pub dry_run: bool,
+}
+
+impl CheckConfig {
+    /// Instantiates a check of the feeds in `conf_path` with all other
+    /// options defaulted.
+    pub fn new<P: Into<PathBuf>, C: Into<PathBuf>>(conf_path: P, cache_path: C) -> Self {
+        Self {
+            conf_path: conf_path.into(),
+            feeds: None,
+            feed_name_filter: FeedNameFilter::default(),
+            cache_path: cache_path.into(),
+            cache_mode: CacheMode::default(),
+            cache_layout: CacheLayout::default(),
+            http_client_options: HttpClientOptions::default(),
+            retry_policy: RetryPolicy {
+                max_retries: 3,
+                base_delay: Duration::from_millis(500),
+            },
+            error_backoff: None,
+            fetch_jitter: Duration::ZERO,
+            per_host_rps: None,
+            feed_check_options: FeedCheckOptions::default(),
+            max_concurrency: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// Returns the hash of the feed a file in the cache directory belongs to, or
+/// [None] if it isn't a cache file.
+fn cached_feed_hash(file_name: &str) -> Option<&str> {
+    let (hash, extension) = match file_name.split_once('.') {
+        Some((hash, extension)) => (hash, Some(extension)),
+        None => (file_name, None),
+    };
+
+    let is_hash = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_cache_extension = match extension {
+        None => true,
+        Some(extension) => [
+            CACHE_METADATA_EXTENSION,
+            CACHE_NAME_EXTENSION,
+            CACHE_KEYS_EXTENSION,
+            CACHE_FAILURES_EXTENSION,
+        ]
+        .contains(&extension),
+    };
+
+    (is_hash && is_cache_extension).then_some(hash)
+}
+
+/// Returns true if a directory within the cache directory is named as a shard
+/// of the [CacheLayout::Sharded] layout.
+fn is_cache_shard_dir(dir_name: &str) -> bool {
+    dir_name.len() == CACHE_SHARD_PREFIX_LEN && dir_name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Lists every file named as a cache file within `cache_path`, and its shard
+/// directories if `include_shards` is set, paired with the hash of the feed
+/// it belongs to.
+fn list_cache_files(cache_path: &Path, include_shards: bool) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut cache_files = vec![];
+
+    for entry in std::fs::read_dir(cache_path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        if include_shards && file_type.is_dir() && is_cache_shard_dir(&file_name) {
+            cache_files.extend(list_cache_files(&entry.path(), false)?);
+        } else if file_type.is_file() {
+            if let Some(hash) = cached_feed_hash(&file_name) {
+                cache_files.push((hash.to_string(), entry.path()));
+            }
+        }
+    }
+
+    Ok(cache_files)
+}
+
+/// Removes the cache files of every feed no longer configured in
+/// `conf_path`, of any feed last successfully checked more than `max_age`
+/// ago, even if answering `304 Not Modified`, and any left
+/// behind by a different `layout`, returning the paths removed. Both
+/// `cache_path` and every feed's overridden cache path are cleaned, each only
+/// of the feeds cached within it, so no cache path may be shared with another
+/// configuration. Only files named as cache files directly within a cache
+/// path or its shard directories are ever removed, and nothing is removed if
+/// any configuration file is invalid or under `dry_run`.
+pub fn clean_cache(
+    conf_path: &Path,
+    cache_path: &Path,
+    layout: CacheLayout,
+    max_age: Option<Duration>,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>, Error> {
+    // each feed is only active in the cache path it's cached in.
+    let mut active_hashes_by_cache_path = BTreeMap::from([(cache_path.to_owned(), HashSet::new())]);
+    let feeds = walker::walk_conf_dir(conf_path)
+        .map_err(|errors| Error::new(ErrorKind::Multiple(errors)))?;
+    for (feed_name, feed_url) in feeds {
+        let feed_cache_path = feed_url.cache_path.unwrap_or_else(|| cache_path.to_owned());
+        active_hashes_by_cache_path
+            .entry(feed_cache_path)
+            .or_default()
+            .insert(cache_file_name(&feed_name));
+    }
+
+    let mut stale_files = vec![];
+    for (cache_path, active_hashes) in &active_hashes_by_cache_path {
+        let cache_error =
+            |err| Error::from(err).with_data(format!("cache[{}]", cache_path.display()));
+        let stale_files_in_cache_path =
+            stale_cache_files(cache_path, active_hashes, layout, max_age).map_err(cache_error)?;
+
+        for path in &stale_files_in_cache_path {
+            if dry_run {
+                log::info!("dry-run: skipping removal of {}", path.display());
+            } else {
+                std::fs::remove_file(path).map_err(cache_error)?;
+            }
+        }
+        stale_files.extend(stale_files_in_cache_path);
+    }
+
+    Ok(stale_files)
+}
+
+/// Lists the stale cache files within a single cache path, as removed by
+/// [clean_cache]. A cache path that doesn't exist yet has none.
+fn stale_cache_files(
+    cache_path: &Path,
+    active_hashes: &HashSet<String>,
+    layout: CacheLayout,
+    max_age: Option<Duration>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut cache_files = match list_cache_files(cache_path, true) {
+        Ok(cache_files) => cache_files,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    cache_files.sort();
+
+    let is_expired = |path: &Path| {
+        max_age.is_some_and(|max_age| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > max_age)
+        })
+    };
+    // a feed expires with its cache file, in either cache mode, which is
+    // rewritten or touched by every successful check.
+    let expired_hashes: HashSet<_> = cache_files
+        .iter()
+        .filter(|(hash, path)| {
+            let is_contents = path.extension().is_none()
+                || path.extension() == Some(CACHE_KEYS_EXTENSION.as_ref());
+            is_contents && active_hashes.contains(hash) && is_expired(path)
+        })
+        .map(|(hash, _)| hash.clone())
+        .collect();
+
+    let is_misplaced = |hash: &str, path: &Path| {
+        path.parent() != Some(layout.dir_for_hash(cache_path, hash).as_path())
+    };
+    Ok(cache_files
+        .into_iter()
+        .filter(|(hash, path)| {
+            !active_hashes.contains(hash)
+                || expired_hashes.contains(hash)
+                || is_misplaced(hash, path)
+        })
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Returns every directory feeds are cached in, `config.cache_path` and each
+/// checked feed's overridden cache path. Overrides of feeds that can't be
+/// configured are omitted, as checking them fails regardless.
+pub fn cache_paths(config: &CheckConfig) -> BTreeSet<PathBuf> {
+    let overridden_cache_paths = feeds_to_check(config)
+        .into_iter()
+        .flat_map(|feeds| feeds.into_values())
+        .filter_map(|feed_url| feed_url.cache_path);
+
+    std::iter::once(config.cache_path.clone())
+        .chain(overridden_cache_paths)
+        .collect()
+}
+
+/// Creates the cache directory, if it doesn't already exist.
+fn ensure_cache_dir(cache_path: &Path) -> Result<(), Error> {
+    match std::fs::metadata(cache_path) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => Err(Error::new(ErrorKind::IoErr(io::Error::from(
+            io::ErrorKind::NotADirectory,
+        )))
+        .with_data(format!("cache[{}]", cache_path.display()))),
+
+        // Attempt to create the directory if it doesn't exist.
+        Err(_) => {
+            log::debug!("creating cache directory at {:?}", cache_path);
+            std::fs::create_dir_all(cache_path).map_err(Error::from)
+        }
+    }
+}
+
+/// The changes found in each feed, or the error encountered checking it,
+/// keyed by the feed's name. Results are ordered by name regardless of the
+/// order feeds finish being checked in, keeping output reproducible.
+pub type FeedCheckResults = BTreeMap<String, Result<FeedChanges, Error>>;
+
+/// Returns the feeds to check, those given by `config.feeds` or otherwise
+/// those configured in `config.conf_path`, as selected by
+/// `config.feed_name_filter`.
+fn feeds_to_check(config: &CheckConfig) -> Result<BTreeMap<String, walker::FeedUrl>, Error> {
+    let feeds = match &config.feeds {
+        Some(feeds) => feeds.clone(),
+        None => walker::walk_conf_dir(&config.conf_path)
+            .map_err(|errors| Error::new(ErrorKind::Multiple(errors)))?,
+    };
+
+    config.feed_name_filter.select(feeds)
+}
+
+/// Returns the directory a feed is cached in, its configured override or
+/// otherwise `config.cache_path`.
+fn feed_cache_path<'a>(config: &'a CheckConfig, feed_url: &'a walker::FeedUrl) -> &'a Path {
+    feed_url.cache_path.as_deref().unwrap_or(&config.cache_path)
+}
+
+/// Creates every overridden feed cache path at startup, so that an unusable
+/// override fails the run before any feed is fetched.
+fn ensure_feed_cache_dirs(
+    config: &CheckConfig,
+    feeds: &BTreeMap<String, walker::FeedUrl>,
+) -> Result<(), Error> {
+    if config.feed_check_options.no_cache {
+        return Ok(());
+    }
+
+    let cache_paths: BTreeSet<_> = feeds
+        .values()
+        .filter_map(|feed_url| feed_url.cache_path.as_deref())
+        .collect();
+    cache_paths.into_iter().try_for_each(ensure_cache_dir)
+}
+
+/// Returns the directory a feed's failures are recorded in, and the policy
+/// they're backed off by, unless backing off is disabled or the cache is
+/// bypassed.
+fn feed_error_backoff<'a>(
+    config: &'a CheckConfig,
+    feed_url: &walker::FeedUrl,
+) -> Option<(PathBuf, &'a BackoffPolicy)> {
+    let policy = config.error_backoff.as_ref()?;
+    let cache_dir = config
+        .cache_layout
+        .feed_dir(feed_cache_path(config, feed_url), &feed_url.name);
+
+    (!config.feed_check_options.no_cache).then_some((cache_dir, policy))
+}
+
+/// Returns whether a feed should be skipped, having failed too recently.
+fn is_feed_backing_off(config: &CheckConfig, feed_url: &walker::FeedUrl) -> bool {
+    feed_error_backoff(config, feed_url).is_some_and(|(cache_dir, policy)| {
+        backoff::is_backing_off(&cache_dir, &feed_url.name, policy, Utc::now())
+    })
+}
+
+/// Records whether a feed's check failed, for backing off future checks.
+/// Failing to record it is only logged, so as not to mask the outcome.
+fn record_feed_check_outcome(
+    config: &CheckConfig,
+    feed_url: &walker::FeedUrl,
+    outcome: &Result<FeedChanges, Error>,
+) {
+    let Some((cache_dir, _)) = feed_error_backoff(config, feed_url) else {
+        return;
+    };
+    if config.dry_run {
+        return;
+    }
+
+    if let Err(err) = backoff::record_check_outcome(&cache_dir, &feed_url.name, outcome, Utc::now())
+    {
+        log::warn!(
+            "feed[{}]: failed to record check outcome: {}",
+            feed_url.name,
+            err
+        );
+    }
+}
+
+/// Checks every feed configured in `config.conf_path`, or given by
+/// `config.feeds`, for new links, caching each feed's latest contents in its
+/// own cache path, if overridden, or otherwise `config.cache_path`.
+pub fn check_feeds(config: &CheckConfig) -> Result<FeedCheckResults, Error> {
+    if !config.feed_check_options.no_cache {
+        ensure_cache_dir(&config.cache_path)?;
+    }
+
+    let feed_mappings = feeds_to_check(config)?;
+    ensure_feed_cache_dirs(config, &feed_mappings)?;
+
+    // a single client is shared across all feed requests.
+    let client = build_http_client(&config.http_client_options)?;
+
+    // each worker performs a single blocking fetch at a time, so the size of
+    // the pool bounds the number of in-flight requests.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.max_concurrency.map(NonZeroUsize::get).unwrap_or(0))
+        .build()
+        .map_err(|err| Error::new(ErrorKind::ThreadPoolErr(err)))?;
+
+    // shared by every feed, so that the limit applies across the whole run.
+    let rate_limiter = config.per_host_rps.map(HostRateLimiter::new);
+
+    let fetch_feeds = pool.install(|| {
+        feed_mappings
+            .par_iter()
+            .map(|(feed_name, feed_url)| {
+                if is_feed_backing_off(config, feed_url) {
+                    return (feed_name.clone(), Ok(FeedChanges::default()));
+                }
+                let cache_dir_path = feed_cache_path(config, feed_url);
+
+                let outcome = get_and_cache_new_items_from_feed(
+                    feed_name,
+                    &feed_url.url,
+                    load_cached_feed_from_disk(cache_dir_path, config.cache_layout),
+                    get_feed_with_jitter(
+                        get_feed_with_mirrors(
+                            get_feed_with_retries(
+                                get_feed_with_rate_limit(
+                                    get_feed_by_scheme(default_scheme_fetchers(
+                                        get_feed_with_blocking_http_request(
+                                            &client,
+                                            config.http_client_options.max_body_bytes,
+                                            feed_url.user_agent.as_deref(),
+                                            feed_url.credentials.as_ref(),
+                                            feed_url.token_env.as_deref(),
+                                            &feed_url.headers,
+                                        ),
+                                        &config.http_client_options,
+                                    )),
+                                    rate_limiter.as_ref(),
+                                ),
+                                config.retry_policy,
+                            ),
+                            &feed_url.mirrors,
+                        ),
+                        config.fetch_jitter,
+                    ),
+                    cache_writer_with_dry_run(
+                        cache_writer_for_mode(
+                            cache_dir_path,
+                            config.cache_mode,
+                            config.cache_layout,
+                        ),
+                        config.dry_run,
+                    ),
+                    &FeedCheckOptions {
+                        link_filter: feed_url.link_filter.clone(),
+                        category_filter: feed_url.category_filter.clone(),
+                        ..config.feed_check_options.clone()
+                    },
+                );
+                record_feed_check_outcome(config, feed_url, &outcome);
+
+                (feed_name.clone(), outcome)
+            })
+            .collect()
+    });
+
+    Ok(fetch_feeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+    use url::Url;
+
+    use super::*;
+
+    /// Provides a rss 2.0 feed in xml format locally.
+    pub(crate) const MOCK_LOCAL_GOOD_FEED: &str = include_str!("../dev/nginx/www/feed.xml");
+
+    /// Provides a json feed 1.1 feed locally.
+    const MOCK_LOCAL_GOOD_JSON_FEED: &str = include_str!("../dev/nginx/www/feed.json");
+
+    /// Provides an atom feed with a single entry.
+    const MOCK_LOCAL_GOOD_ATOM_FEED: &str = "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>t</title><id>urn:feed</id><updated>2004-10-26T14:06:44Z</updated><entry><title>entry</title><id>urn:entry</id><updated>2004-10-26T14:06:44Z</updated><link href=\"http://example.com/entry\"/></entry></feed>";
+
+    #[allow(unused)]
+    struct MockFeedGetter<'data> {
+        contents: &'data str,
+    }
+
+    impl<'data> MockFeedGetter<'data> {
+        fn new(contents: &'data str) -> Self {
+            Self { contents }
+        }
+    }
+
+    impl FeedGettable for MockFeedGetter<'_> {
+        fn get_feed(
+            &self,
+            _feed_name: &str,
+            _url: &Url,
+            _metadata: &CacheMetadata,
+        ) -> Result<FetchedFeed, Error> {
+            Channel::read_from(self.contents.as_bytes())
+                .map_err(Error::from)
+                .map(|channel| {
+                    FetchedFeed::Modified(CachedFeed::new(
+                        RssOrAtomFeed::Rss2(channel),
+                        CacheMetadata::default(),
+                    ))
+                })
+        }
+    }
+
+    fn expect_modified(fetched: FetchedFeed) -> CachedFeed {
+        match fetched {
+            FetchedFeed::Modified(feed) => feed,
+            FetchedFeed::NotModified(_) => panic!("expected a modified feed"),
+        }
+    }
+
+    /// Reads a single request from the next connection to `listener`,
+    /// replying with `response` verbatim.
+    fn respond_once<R: AsRef<[u8]>>(listener: &std::net::TcpListener, response: R) -> String {
+        use std::io::{BufRead, Write};
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = io::BufReader::new(stream);
+
+        let mut request = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            request.push_str(&line);
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+        }
+
+        let mut stream = reader.into_inner();
+        stream.write_all(response.as_ref()).unwrap();
+        request
+    }
+
+    /// Serves a single raw http response on an ephemeral local port, returning
+    /// the url to request and a handle yielding the raw request received.
+    pub(crate) fn serve_once<R: AsRef<[u8]> + Send + 'static>(
+        response: R,
+    ) -> (Url, std::thread::JoinHandle<String>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || respond_once(&listener, response));
+
+        let url = Url::parse(&format!("http://{}/feed.xml", addr)).unwrap();
+        (url, handle)
+    }
+
+    /// Serves each response, in order, to a connection of its own, returning
+    /// every request received.
+    pub(crate) fn serve_sequence<R: AsRef<[u8]> + Send + 'static>(
+        responses: Vec<R>,
+    ) -> (Url, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| respond_once(&listener, response))
+                .collect()
+        });
+
+        let url = Url::parse(&format!("http://{}/feed.xml", addr)).unwrap();
+        (url, handle)
+    }
+
+    pub(crate) fn http_ok_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn should_send_configured_user_agent() {
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let feed = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        let request = handle.join().unwrap().to_lowercase();
+
+        assert_eq!(feed.get_links().len(), 2);
+        assert!(request.contains(&format!(
+            "user-agent: rss_checker_redux/{}\r\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+
+    #[test]
+    fn should_send_requests_through_configured_proxy() {
+        let (proxy_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            proxy: Some(reqwest::Proxy::all(proxy_url.as_str()).unwrap()),
+            ..Default::default()
+        })
+        .unwrap();
+        let feed_url = Url::parse("http://feeds.example.invalid/feed.xml").unwrap();
+
+        get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        let request = handle.join().unwrap();
+
+        // proxied requests carry the absolute url of the feed.
+        assert!(request.starts_with("GET http://feeds.example.invalid/feed.xml HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn should_fail_feeds_exceeding_max_redirects() {
+        let (feed_url, handle) = serve_once(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /feed.xml\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        );
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            max_redirects: 0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let res = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default());
+        handle.join().unwrap();
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::TooManyRedirects(feed_name),
+                ..
+            }) if feed_name == "test"
+        ));
+    }
+
+    #[test]
+    fn should_fail_feeds_exceeding_max_body_bytes() {
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let res =
+            get_feed_with_blocking_http_request(&client, 16, None, None, None, &HeaderMap::new())
+                .get_feed("test", &feed_url, &CacheMetadata::default());
+        handle.join().unwrap();
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::BodyTooLarge(feed_name),
+                ..
+            }) if feed_name == "test"
+        ));
+    }
+
+    #[test]
+    fn should_send_configured_basic_auth_credentials() {
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+        let credentials = walker::Credentials {
+            username: "user".to_string(),
+            password: Some("pass".to_string()),
+        };
+
+        get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            Some(&credentials),
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        let request = handle.join().unwrap().to_lowercase();
+
+        // base64 of "user:pass".
+        assert!(request.contains("authorization: basic dxnlcjpwyxnz\r\n"));
+    }
+
+    #[test]
+    fn should_send_bearer_token_from_the_configured_env_var() {
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+        std::env::set_var("RSS_CHECKER_TEST_BEARER_TOKEN", "s3cret");
+
+        get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            Some("RSS_CHECKER_TEST_BEARER_TOKEN"),
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        let request = handle.join().unwrap();
+
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer s3cret\r\n"));
+        assert!(!format!(
+            "{:?}",
+            bearer_token_header("test", "RSS_CHECKER_TEST_BEARER_TOKEN").unwrap()
+        )
+        .contains("s3cret"));
+    }
+
+    #[test]
+    fn should_fail_feeds_whose_bearer_token_env_var_is_unset() {
+        let client = build_http_client(&HttpClientOptions::default()).unwrap();
+        let feed_url = Url::parse("http://127.0.0.1:1/feed.xml").unwrap();
+
+        let res = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            Some("RSS_CHECKER_TEST_UNSET_BEARER_TOKEN"),
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default());
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::MissingTokenEnv { feed_name, var },
+                ..
+            }) if feed_name == "test" && var == "RSS_CHECKER_TEST_UNSET_BEARER_TOKEN"
+        ));
+    }
+
+    #[test]
+    fn should_send_configured_headers() {
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        headers.insert(
+            reqwest::header::ACCEPT,
+            "application/rss+xml".parse().unwrap(),
+        );
+
+        get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &headers,
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        let request = handle.join().unwrap().to_lowercase();
+
+        assert!(request.contains("x-api-key: secret\r\n"));
+        assert!(request.contains("accept: application/rss+xml\r\n"));
+        assert!(!request.contains("accept: */*"));
+    }
+
+    #[test]
+    fn should_surface_unsuccessful_response_status() {
+        let (feed_url, handle) = serve_once(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        );
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let res = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default());
+        handle.join().unwrap();
+
+        let err = res.err().unwrap();
+        assert!(matches!(
+            &err.kind,
+            ErrorKind::HttpStatus { feed_name, status }
+                if feed_name == "test" && status.as_u16() == 404
+        ));
+        assert!(!is_retryable(&err));
+    }
+
+    #[test]
+    fn should_read_bodies_as_raw_bytes_within_capacity_bounds() {
+        let body = b"<rss>\xe9</rss>";
+
+        assert_eq!(
+            read_body_with_limit("test", &body[..], Some(body.len() as u64), 64).unwrap(),
+            body
+        );
+        assert_eq!(body_capacity(Some(1 << 40), 64), 64);
+        assert_eq!(body_capacity(None, 64), 0);
+    }
+
+    #[test]
+    fn should_read_file_urls_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("feed.xml");
+        std::fs::write(&path, MOCK_LOCAL_GOOD_FEED).unwrap();
+        let fetch_feed =
+            get_feed_by_scheme(non_http_scheme_fetchers(&HttpClientOptions::default()));
+
+        let feed = fetch_feed
+            .get_feed(
+                "test",
+                &Url::from_file_path(&path).unwrap(),
+                &CacheMetadata::default(),
+            )
+            .map(expect_modified)
+            .unwrap();
+        let missing = fetch_feed.get_feed(
+            "test",
+            &Url::from_file_path(dir.path().join("missing.xml")).unwrap(),
+            &CacheMetadata::default(),
+        );
+
+        assert_eq!(feed.get_links().len(), 2);
+        assert!(matches!(
+            missing,
+            Err(Error {
+                kind: ErrorKind::IoErr(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_reject_unsupported_url_schemes() {
+        let client = build_http_client(&HttpClientOptions::default()).unwrap();
+        let http_fetcher = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        );
+
+        let res = get_feed_by_scheme(default_scheme_fetchers(
+            http_fetcher,
+            &HttpClientOptions::default(),
+        ))
+        .get_feed(
+            "test",
+            &Url::parse("ftp://example.com/feed.xml").unwrap(),
+            &CacheMetadata::default(),
+        );
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::UnsupportedScheme { scheme, supported, .. },
+                ..
+            }) if scheme == "ftp" && supported.contains(&"https") && supported.contains(&"file")
+        ));
+    }
+
+    #[test]
+    fn should_store_validators_from_response() {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nLast-Modified: Tue, 26 Oct 2004 14:06:44 GMT\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            MOCK_LOCAL_GOOD_FEED.len(),
+            MOCK_LOCAL_GOOD_FEED
+        );
+        let (feed_url, handle) = serve_once(response);
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let feed = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(
+            feed.metadata,
+            CacheMetadata {
+                etag: Some("\"abc\"".to_string()),
+                last_modified: Some("Tue, 26 Oct 2004 14:06:44 GMT".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn should_resolve_relative_item_links_against_the_feed_url() {
+        let atom_feed = "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:base=\"/blog/\"><title>t</title><id>urn:feed</id><updated>2004-10-26T14:06:44Z</updated><entry><title>relative</title><id>urn:1</id><updated>2004-10-26T14:06:44Z</updated><link rel=\"alternate\" href=\"posts/1\"/></entry><entry><title>absolute</title><id>urn:2</id><updated>2004-10-26T14:06:44Z</updated><link href=\"http://example.com/2\"/></entry></feed>";
+        let (feed_url, handle) = serve_once(http_ok_response(atom_feed));
+        let client = build_http_client(&HttpClientOptions::default()).unwrap();
+
+        let feed = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(
+            feed.get_links(),
+            vec![
+                feed_url.join("/blog/posts/1").unwrap(),
+                Url::parse("http://example.com/2").unwrap()
+            ]
+        );
+
+        let channel = RssOrAtomFeed::Rss2(
+            Channel::read_from(
+                "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><link>../posts/3</link></item></channel></rss>".as_bytes(),
+            )
+            .unwrap(),
+        )
+        .resolve_relative_links(&Url::parse("http://example.com/feeds/rss.xml").unwrap());
+        assert_eq!(
+            channel.get_links(),
+            vec![Url::parse("http://example.com/posts/3").unwrap()]
+        );
+    }
+
+    #[test]
+    fn should_send_validators_and_handle_not_modified() {
+        let (feed_url, handle) = serve_once(
+            "HTTP/1.1 304 Not Modified\r\nETag: \"def\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        );
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+        let metadata = CacheMetadata {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Tue, 26 Oct 2004 14:06:44 GMT".to_string()),
+            ..Default::default()
+        };
+
+        let fetched = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &metadata)
+        .unwrap();
+        let request = handle.join().unwrap().to_lowercase();
+
+        assert!(
+            matches!(fetched, FetchedFeed::NotModified(metadata) if metadata.etag.as_deref() == Some("\"def\""))
+        );
+        assert!(request.contains("if-none-match: \"abc\"\r\n"));
+        assert!(request.contains("if-modified-since: tue, 26 oct 2004 14:06:44 gmt\r\n"));
+    }
+
+    #[test]
+    fn should_skip_diffing_and_writing_unmodified_feeds() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_reader = |_: &str| {
+            Ok(CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::default()),
+                CacheMetadata {
+                    etag: Some("\"abc\"".to_string()),
+                    last_modified: None,
+                    ..Default::default()
+                },
+            ))
+        };
+        let not_modified_getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::NotModified(CacheMetadata::default()))
+        };
+        let writer_invoked = AtomicBool::new(false);
+        let mock_writer = |_: &str, _: &CachedFeed| {
+            writer_invoked.store(true, Ordering::SeqCst);
+            Ok(())
+        };
+
+        let new_links = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            cache_reader,
+            not_modified_getter,
+            mock_writer,
+            &FeedCheckOptions::default(),
+        )
+        .unwrap();
+
+        assert!(new_links.new_items.is_empty());
+        assert!(!writer_invoked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn should_refresh_the_cache_of_unmodified_feeds() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let feed =
+            RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap());
+        let cached_metadata = CacheMetadata {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Tue, 26 Oct 2004 14:06:44 GMT".to_string()),
+            ..Default::default()
+        };
+        cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat)
+            .write_cache("test", &CachedFeed::new(feed, cached_metadata))
+            .unwrap();
+        let last_checked = SystemTime::now() - Duration::from_secs(60 * 60);
+        std::fs::File::options()
+            .write(true)
+            .open(cache_file_path(cache_dir.path(), "test"))
+            .unwrap()
+            .set_modified(last_checked)
+            .unwrap();
+
+        let not_modified_getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::NotModified(CacheMetadata {
+                etag: Some("\"def\"".to_string()),
+                max_age: Some(300),
+                fetched_at: Some(Utc::now()),
+                ..Default::default()
+            }))
+        };
+        let changes = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat),
+            not_modified_getter,
+            cache_writer_for_mode(cache_dir.path(), CacheMode::Full, CacheLayout::Flat),
+            &FeedCheckOptions::default(),
+        )
+        .unwrap();
+        let cached_feed = load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat)
+            .read_cache("test")
+            .unwrap();
+
+        assert_eq!(changes.fetch_status, FetchStatus::NotModified);
+        // the 304's validators replace those cached, keeping any it omits.
+        assert_eq!(cached_feed.metadata.etag.as_deref(), Some("\"def\""));
+        assert_eq!(
+            cached_feed.metadata.last_modified.as_deref(),
+            Some("Tue, 26 Oct 2004 14:06:44 GMT")
+        );
+        assert_eq!(cached_feed.metadata.max_age, Some(300));
+        assert!(cached_feed.cached_at.unwrap() > last_checked);
+        assert_eq!(cached_feed.contents.get_item_links().len(), 2);
+    }
+
+    #[test]
+    fn should_not_fetch_feeds_cached_within_min_cache_age() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let feed =
+            RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap());
+        cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat)
+            .write_cache("test", &CachedFeed::new(feed, CacheMetadata::default()))
+            .unwrap();
+
+        let fetch_invoked = AtomicBool::new(false);
+        let mock_getter = |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+            fetch_invoked.store(true, Ordering::SeqCst);
+            MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED).get_feed(feed_name, url, metadata)
+        };
+        let options = FeedCheckOptions {
+            min_cache_age: Duration::from_secs(3600),
+            ..Default::default()
+        };
+
+        let new_links = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat),
+            mock_getter,
+            cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat),
+            &options,
+        )
+        .unwrap();
+
+        assert!(new_links.new_items.is_empty());
+        assert!(!fetch_invoked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn should_parse_cache_control_max_age() {
+        assert_eq!(cache_control_max_age("public, max-age=300"), Some(300));
+        assert_eq!(cache_control_max_age("Max-Age=\"60\""), Some(60));
+        assert_eq!(cache_control_max_age("max-age=300, no-cache"), None);
+        assert_eq!(cache_control_max_age("private"), None);
+    }
+
+    #[test]
+    fn should_only_skip_fetches_within_cache_control_max_age_when_respected() {
+        let now = Utc::now();
+        let cached_feed = |fetched_at: DateTime<Utc>| {
+            Ok(CachedFeed::new(
+                rss_feed_with_items(&[]),
+                CacheMetadata {
+                    max_age: Some(3600),
+                    fetched_at: Some(fetched_at),
+                    ..Default::default()
+                },
+            ))
+        };
+        let respected = FeedCheckOptions {
+            respect_cache_control: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            lookup_cache("test", cached_feed(now), &respected),
+            Ok(CacheLookup::Fresh)
+        ));
+        assert!(matches!(
+            lookup_cache("test", cached_feed(now), &FeedCheckOptions::default()),
+            Ok(CacheLookup::Stale(_))
+        ));
+        assert!(matches!(
+            lookup_cache(
+                "test",
+                cached_feed(now - chrono::TimeDelta::hours(2)),
+                &respected
+            ),
+            Ok(CacheLookup::Stale(_))
+        ));
+    }
+
+    #[test]
+    fn should_store_cache_control_max_age_from_response() {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nCache-Control: public, max-age=300\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            MOCK_LOCAL_GOOD_FEED.len(),
+            MOCK_LOCAL_GOOD_FEED
+        );
+        let (feed_url, handle) = serve_once(response);
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let cached_feed = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(cached_feed.metadata.max_age, Some(300));
+        assert!(cached_feed.metadata.is_fresh_per_server(Utc::now()));
+    }
+
+    #[test]
+    fn should_round_trip_cache_metadata_through_disk() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let metadata = CacheMetadata {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            ..Default::default()
+        };
+        let feed =
+            RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap());
+
+        cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat)
+            .write_cache("test", &CachedFeed::new(feed, metadata.clone()))
+            .unwrap();
+        let cached_feed = load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat)
+            .read_cache("test")
+            .unwrap();
+
+        assert_eq!(cached_feed.metadata, metadata);
+        assert_eq!(cached_feed.get_links().len(), 2);
+    }
+
+    #[test]
+    fn should_diff_new_items_against_cached_keys() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let keys_writer =
+            cache_writer_for_mode(cache_dir.path(), CacheMode::Keys, CacheLayout::Flat);
+        keys_writer
+            .write_cache(
+                "test",
+                &CachedFeed::new(
+                    rss_feed_with_items(&[("1", "http://example.com/1")]),
+                    CacheMetadata::default(),
+                ),
+            )
+            .unwrap();
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(CachedFeed::new(
+                rss_feed_with_items(&[
+                    ("1", "http://example.com/1"),
+                    ("2", "http://example.com/2"),
+                ]),
+                CacheMetadata::default(),
+            )))
+        };
+
+        assert!(cache_keys_file_path(cache_dir.path(), "test").exists());
+        assert!(!cache_file_path(cache_dir.path(), "test").exists());
+        for dedup_key in [DedupKey::Link, DedupKey::Guid] {
+            let new_links = get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat),
+                getter,
+                |_: &str, _: &CachedFeed| Ok(()),
+                &FeedCheckOptions {
+                    dedup_key,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            assert_eq!(links_of(new_links.new_items), vec!["http://example.com/2"]);
+        }
+    }
+
+    #[test]
+    fn should_report_items_removed_since_last_cached() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cached_feed = || {
+            CachedFeed::new(
+                rss_feed_with_items(&[
+                    ("1", "http://example.com/1"),
+                    ("2", "http://example.com/2"),
+                ]),
+                CacheMetadata::default(),
+            )
+        };
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(CachedFeed::new(
+                rss_feed_with_items(&[
+                    ("2", "http://example.com/2"),
+                    ("3", "http://example.com/3"),
+                ]),
+                CacheMetadata::default(),
+            )))
+        };
+        let keys_cache_reader = |_: &str| {
+            let cached_feed = cached_feed();
+            Ok(CachedFeed::from_contents(
+                CacheContents::Keys(CachedKeys::from(&cached_feed.contents)),
+                cached_feed.metadata,
+            ))
+        };
+        let check = |cache_reader: &dyn Fn(&str) -> Result<CachedFeed, Error>, report_removed| {
+            get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                cache_reader,
+                getter,
+                |_: &str, _: &CachedFeed| Ok(()),
+                &FeedCheckOptions {
+                    report_removed,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let unreported = check(&|_: &str| Ok(cached_feed()), false);
+        let reported = check(&|_: &str| Ok(cached_feed()), true);
+        let reported_from_keys = check(&keys_cache_reader, true);
+
+        assert!(unreported.removed_items.is_empty());
+        assert_eq!(links_of(reported.new_items), vec!["http://example.com/3"]);
+        assert_eq!(
+            links_of(reported.removed_items),
+            vec!["http://example.com/1"]
+        );
+        assert_eq!(
+            links_of(reported_from_keys.removed_items),
+            vec!["http://example.com/1"]
+        );
+    }
+
+    #[test]
+    fn should_report_cached_items_whose_content_changed_as_updated() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let feed_with_titles = |items: &[(&str, &str)]| {
+            let items: String = items
+                .iter()
+                .map(|(guid, title)| {
+                    format!(
+                        "<item><guid>{0}</guid><link>http://example.com/{0}</link><title>{1}</title></item>",
+                        guid, title
+                    )
+                })
+                .collect();
+            let xml = format!(
+                "<rss version=\"2.0\"><channel><title>test</title><link>http://example.com</link><description>test</description>{}</channel></rss>",
+                items
+            );
+
+            CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::read_from(xml.as_bytes()).unwrap()),
+                CacheMetadata::default(),
+            )
+        };
+        let cached_feed = || feed_with_titles(&[("1", "first"), ("2", "second")]);
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(feed_with_titles(&[
+                ("1", "first"),
+                ("2", "second, edited"),
+                ("3", "third"),
+            ])))
+        };
+        let keys_cache_reader = |_: &str| {
+            let cached_feed = cached_feed();
+            Ok(CachedFeed::from_contents(
+                CacheContents::Keys(CachedKeys::from(&cached_feed.contents)),
+                cached_feed.metadata,
+            ))
+        };
+        let legacy_keys_cache_reader = |_: &str| {
+            let cached_feed = cached_feed();
+            Ok(CachedFeed::from_contents(
+                CacheContents::Keys(CachedKeys {
+                    content_hashes: BTreeMap::new(),
+                    ..CachedKeys::from(&cached_feed.contents)
+                }),
+                cached_feed.metadata,
+            ))
+        };
+        let check = |cache_reader: &dyn Fn(&str) -> Result<CachedFeed, Error>, detect_updates| {
+            get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                cache_reader,
+                getter,
+                |_: &str, _: &CachedFeed| Ok(()),
+                &FeedCheckOptions {
+                    detect_updates,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let undetected = check(&|_: &str| Ok(cached_feed()), false);
+        let detected = check(&|_: &str| Ok(cached_feed()), true);
+        let detected_from_keys = check(&keys_cache_reader, true);
+        let detected_from_legacy_keys = check(&legacy_keys_cache_reader, true);
+
+        assert!(undetected.updated_items.is_empty());
+        assert_eq!(links_of(detected.new_items), vec!["http://example.com/3"]);
+        assert_eq!(
+            links_of(detected.updated_items),
+            vec!["http://example.com/2"]
+        );
+        assert_eq!(
+            links_of(detected_from_keys.updated_items),
+            vec!["http://example.com/2"]
+        );
+        assert!(detected_from_legacy_keys.updated_items.is_empty());
+    }
+
+    #[test]
+    fn should_remove_cache_files_left_by_another_cache_mode() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cached_feed = CachedFeed::new(
+            rss_feed_with_items(&[("1", "http://example.com/1")]),
+            CacheMetadata::default(),
+        );
+
+        cache_writer_for_mode(cache_dir.path(), CacheMode::Keys, CacheLayout::Flat)
+            .write_cache("test", &cached_feed)
+            .unwrap();
+        cache_writer_for_mode(cache_dir.path(), CacheMode::Full, CacheLayout::Flat)
+            .write_cache("test", &cached_feed)
+            .unwrap();
+
+        assert!(!cache_keys_file_path(cache_dir.path(), "test").exists());
+        assert!(matches!(
+            load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat).read_cache("test"),
+            Ok(CachedFeed {
+                contents: CacheContents::Feed(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_cache_feeds_named_with_path_separators_within_cache_path() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let cache_path = root_dir.path().join("cache");
+        std::fs::create_dir(&cache_path).unwrap();
+        let feed_name = "../blogs/test";
+        let feed =
+            RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap());
+
+        cache_feed_to_disk(&cache_path, CacheLayout::Flat)
+            .write_cache(feed_name, &CachedFeed::new(feed, CacheMetadata::default()))
+            .unwrap();
+        let cached_feed = load_cached_feed_from_disk(&cache_path, CacheLayout::Flat)
+            .read_cache(feed_name)
+            .unwrap();
+
+        assert_eq!(cached_feed.get_links().len(), 2);
+        assert!(!root_dir.path().join("blogs").exists());
+        assert_eq!(
+            std::fs::read_to_string(cache_name_file_path(&cache_path, feed_name)).unwrap(),
+            feed_name
+        );
+    }
+
+    #[test]
+    fn should_load_cached_feeds_of_each_format_from_a_single_read() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let feeds = [
+            (
+                "rss",
+                RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap()),
+            ),
+            (
+                "atom",
+                RssOrAtomFeed::Atom(Feed::read_from(MOCK_LOCAL_GOOD_ATOM_FEED.as_bytes()).unwrap()),
+            ),
+            (
+                "json",
+                RssOrAtomFeed::Json(
+                    jsonfeed::Feed::read_from(MOCK_LOCAL_GOOD_JSON_FEED.as_bytes()).unwrap(),
+                ),
+            ),
+        ];
+
+        for (feed_name, feed) in feeds {
+            let expected_links = feed.get_links();
+            cache_feed_contents_to_disk(cache_dir.path(), feed_name, &feed).unwrap();
+
+            assert_eq!(
+                load_cached_feed_contents_from_disk(cache_dir.path(), feed_name)
+                    .unwrap()
+                    .get_links(),
+                expected_links
+            );
+        }
+        assert!(matches!(
+            load_cached_feed_contents_from_disk(cache_dir.path(), "missing"),
+            Err(Error {
+                kind: ErrorKind::IoErr(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_parse_valid_feed() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let feed_name = "test";
+        let feed_getter = MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED);
+
+        let channel = feed_getter
+            .get_feed(feed_name, &feed_url, &CacheMetadata::default())
+            .map(expect_modified)
+            .unwrap();
+        let channel_items = channel.get_links();
+
+        assert_eq!(channel_items.len(), 2);
+    }
+
+    #[test]
+    fn should_never_write_cache_under_dry_run() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_reader = |_: &str| {
+            Ok(CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::default()),
+                CacheMetadata::default(),
+            ))
+        };
+        let writer_invoked = AtomicBool::new(false);
+        let mock_writer = |_: &str, _: &CachedFeed| {
+            writer_invoked.store(true, Ordering::SeqCst);
+            Ok(())
+        };
+
+        let new_links = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            cache_reader,
+            MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED),
+            cache_writer_with_dry_run(mock_writer, true),
+            &FeedCheckOptions::default(),
+        )
+        .unwrap();
+
+        // the diff is still computed against the cache.
+        assert_eq!(new_links.new_items.len(), 2);
+        assert!(!writer_invoked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn should_report_every_item_without_touching_the_cache_under_no_cache() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_invoked = AtomicBool::new(false);
+        let cache_reader = |_: &str| {
+            cache_invoked.store(true, Ordering::SeqCst);
+            Err(Error::new(ErrorKind::InvalidCache("test".to_string())))
+        };
+        let mock_writer = |_: &str, _: &CachedFeed| {
+            cache_invoked.store(true, Ordering::SeqCst);
+            Ok(())
+        };
+
+        let changes = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            cache_reader,
+            MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED),
+            mock_writer,
+            &FeedCheckOptions {
+                no_cache: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(changes.new_items.len(), 2);
+        assert!(!cache_invoked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn should_report_every_item_of_never_cached_feeds_on_first_seed() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let missing_cache =
+            |_: &str| Err(Error::new(ErrorKind::IoErr(io::ErrorKind::NotFound.into())));
+        let unchanged_cache = |_: &str| {
+            Ok(CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::read_from(MOCK_LOCAL_GOOD_FEED.as_bytes()).unwrap()),
+                CacheMetadata::default(),
+            ))
+        };
+        let check = |cache_reader: &dyn Fn(&str) -> Result<CachedFeed, Error>,
+                     report_on_first_seed: bool| {
+            get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                cache_reader,
+                MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED),
+                |_: &str, _: &CachedFeed| Ok(()),
+                &FeedCheckOptions {
+                    report_on_first_seed,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .new_items
+            .len()
+        };
+
+        assert_eq!(check(&missing_cache, false), 0);
+        assert_eq!(check(&missing_cache, true), 2);
+        assert_eq!(check(&unchanged_cache, true), 0);
+    }
+
+    #[test]
+    fn should_retry_transient_failures_until_success() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let attempts = AtomicU32::new(0);
+        let flaky_getter = |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(Error::new(ErrorKind::Timeout(feed_name.to_string())))
+            } else {
+                MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED).get_feed(feed_name, url, metadata)
+            }
+        };
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::ZERO,
+        };
+
+        let res = get_feed_with_retries(flaky_getter, policy).get_feed(
+            "test",
+            &feed_url,
+            &CacheMetadata::default(),
+        );
+
+        assert!(res.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn should_not_retry_non_transient_failures() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let non_transient_errors: [fn(&str) -> Error; 2] = [
+            |feed_name| {
+                Error::new(ErrorKind::FeedIsNeitherAtomOrRss {
+                    feed_name: feed_name.to_string(),
+                    content_type: None,
+                    snippet: String::new(),
+                })
+            },
+            // a request that can't be built fails identically every time.
+            |_| {
+                Error::from(
+                    reqwest::blocking::Client::new()
+                        .get("not a url")
+                        .send()
+                        .unwrap_err(),
+                )
+            },
+        ];
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::ZERO,
+        };
+
+        for non_transient_error in non_transient_errors {
+            let attempts = AtomicU32::new(0);
+            let failing_getter = |feed_name: &str, _: &Url, _: &CacheMetadata| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(non_transient_error(feed_name))
+            };
+
+            let res = get_feed_with_retries(failing_getter, policy).get_feed(
+                "test",
+                &feed_url,
+                &CacheMetadata::default(),
+            );
+
+            assert!(res.is_err());
+            assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn should_fall_back_to_mirrors_until_one_succeeds() {
+        use std::sync::Mutex;
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let mirrors = [
+            Url::parse("http://down.example.com/feed.xml").unwrap(),
+            Url::parse("http://mirror.example.com/feed.xml").unwrap(),
+        ];
+        let requested = Mutex::new(vec![]);
+        let getter = |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+            requested.lock().unwrap().push(url.to_string());
+            match url.host_str() {
+                Some("mirror.example.com") => {
+                    MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED).get_feed(feed_name, url, metadata)
+                }
+                _ => Err(Error::new(ErrorKind::Timeout(feed_name.to_string()))),
+            }
+        };
+
+        let res = get_feed_with_mirrors(getter, &mirrors).get_feed(
+            "test",
+            &feed_url,
+            &CacheMetadata::default(),
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            *requested.lock().unwrap(),
+            vec![
+                "http://example.com/feed.xml",
+                "http://down.example.com/feed.xml",
+                "http://mirror.example.com/feed.xml"
+            ]
+        );
+
+        let res = get_feed_with_mirrors(getter, &mirrors[..1]).get_feed(
+            "test",
+            &feed_url,
+            &CacheMetadata::default(),
+        );
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::Multiple(errors),
+                ..
+            }) if errors.len() == 2
+        ));
+    }
+
+    #[test]
+    fn should_space_rate_limited_requests_to_the_same_host() {
+        use std::sync::Mutex;
+        use std::time::Instant;
+
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let requested_at = Mutex::new(vec![]);
+        let recording_getter = |feed_name: &str, url: &Url, metadata: &CacheMetadata| {
+            requested_at.lock().unwrap().push(Instant::now());
+            MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED).get_feed(feed_name, url, metadata)
+        };
+        let rate_limiter = HostRateLimiter::new(NonZeroU32::new(20).unwrap());
+        let fetch_feed = get_feed_with_rate_limit(recording_getter, Some(&rate_limiter));
+
+        for _ in 0..2 {
+            fetch_feed
+                .get_feed("test", &feed_url, &CacheMetadata::default())
+                .unwrap();
+        }
+
+        let requested_at = requested_at.lock().unwrap();
+        assert!(requested_at[1] - requested_at[0] >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn should_bound_random_jitter_by_its_maximum() {
+        let max_jitter = Duration::from_millis(10);
+
+        assert_eq!(random_jitter(Duration::ZERO), Duration::ZERO);
+        assert!((0..100).all(|_| random_jitter(max_jitter) <= max_jitter));
+    }
+
+    fn rss_feed_with_items(items: &[(&str, &str)]) -> RssOrAtomFeed {
+        let items: String = items
+            .iter()
+            .map(|(guid, link)| format!("<item><guid>{}</guid><link>{}</link></item>", guid, link))
+            .collect();
+        let xml = format!(
+            "<rss version=\"2.0\"><channel><title>test</title><link>http://example.com</link><description>test</description>{}</channel></rss>",
+            items
+        );
+
+        RssOrAtomFeed::Rss2(Channel::read_from(xml.as_bytes()).unwrap())
+    }
+
+    fn links_of(items: Vec<FeedItem>) -> Vec<String> {
+        items.into_iter().map(|item| item.link).collect()
+    }
+
+    #[test]
+    fn should_count_items_with_valid_and_unparseable_links() {
+        let channel = RssOrAtomFeed::Rss2(
+            Channel::read_from(
+                "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><link>http://example.com/1</link></item><item><link>http://example.com/1</link></item><item><link>http://[bad</link></item><item><title>unlinked</title></item></channel></rss>".as_bytes(),
+            )
+            .unwrap(),
+        );
+        let counts = channel.link_counts();
+
+        assert_eq!(
+            counts,
+            LinkCounts {
+                items: 4,
+                valid_links: 2,
+                unparseable_links: 1,
+            }
+        );
+        assert_eq!(counts.to_string(), "4 items, 2 valid links, 1 unparseable");
+
+        let atom_feed = RssOrAtomFeed::Atom(
+            Feed::read_from(
+                "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>t</title><id>urn:feed</id><updated>2004-10-26T14:06:44Z</updated><entry><title>a</title><id>urn:1</id><updated>2004-10-26T14:06:44Z</updated><link href=\"http://[bad\"/><link rel=\"enclosure\" href=\"http://example.com/1.mp3\"/></entry><entry><title>b</title><id>urn:2</id><updated>2004-10-26T14:06:44Z</updated><link href=\"http://[bad\"/><link rel=\"self\" href=\"http://example.com/2\"/></entry></feed>".as_bytes(),
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            atom_feed.link_counts(),
+            LinkCounts {
+                items: 2,
+                valid_links: 1,
+                unparseable_links: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn should_detect_reused_links_as_new_items_by_guid() {
+        let cached_feed = rss_feed_with_items(&[("1", "http://example.com/post")]);
+        let new_feed = rss_feed_with_items(&[
+            ("1", "http://example.com/post"),
+            ("2", "http://example.com/post"),
+        ]);
+
+        assert!(new_items_by_link(&UrlNormalizer::default(), &cached_feed, &new_feed).is_empty());
+        assert_eq!(
+            links_of(new_items_by_identity(
+                &UrlNormalizer::default(),
+                &cached_feed,
+                &new_feed
+            )),
+            vec!["http://example.com/post"]
+        );
+    }
+
+    #[test]
+    fn should_not_report_relinked_items_by_guid() {
+        let cached_feed = rss_feed_with_items(&[("1", "http://example.com/old")]);
+        let new_feed = rss_feed_with_items(&[("1", "http://example.com/new")]);
+
+        assert_eq!(
+            links_of(new_items_by_link(
+                &UrlNormalizer::default(),
+                &cached_feed,
+                &new_feed
+            )),
+            vec!["http://example.com/new"]
+        );
+        assert!(
+            new_items_by_identity(&UrlNormalizer::default(), &cached_feed, &new_feed).is_empty()
+        );
+    }
+
+    #[test]
+    fn should_filter_new_links_by_feed_link_filter() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_reader = |_: &str| {
+            Ok(CachedFeed::new(
+                rss_feed_with_items(&[("1", "http://example.com/security/old")]),
+                CacheMetadata::default(),
+            ))
+        };
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(CachedFeed::new(
+                rss_feed_with_items(&[
+                    ("1", "http://example.com/security/old"),
+                    ("2", "http://example.com/security/new"),
+                    ("3", "http://example.com/security/draft"),
+                    ("4", "http://example.com/news/new"),
+                ]),
+                CacheMetadata::default(),
+            )))
+        };
+        let options = FeedCheckOptions {
+            link_filter: LinkFilter::new(&["/security/"], &["draft"]).unwrap(),
+            ..Default::default()
+        };
+
+        let new_links = get_and_cache_new_items_from_feed(
+            "test",
+            &feed_url,
+            cache_reader,
+            getter,
+            |_: &str, _: &CachedFeed| Ok(()),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            links_of(new_links.new_items),
+            vec!["http://example.com/security/new"]
+        );
+    }
+
+    #[test]
+    fn should_filter_new_links_by_feed_category_filter() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_reader = |_: &str| {
+            Ok(CachedFeed::new(
+                rss_feed_with_items(&[]),
+                CacheMetadata::default(),
+            ))
+        };
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::read_from(
+                    "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><link>http://example.com/1</link><category>Security</category></item><item><link>http://example.com/2</link><category>security</category><category>sponsored</category></item><item><link>http://example.com/3</link><category>news</category></item><item><link>http://example.com/4</link></item></channel></rss>".as_bytes(),
+                )
+                .unwrap()),
+                CacheMetadata::default(),
+            )))
+        };
+        let new_links = |category_filter| {
+            let options = FeedCheckOptions {
+                category_filter,
+                ..Default::default()
+            };
+
+            get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                cache_reader,
+                getter,
+                |_: &str, _: &CachedFeed| Ok(()),
+                &options,
+            )
+            .map(|changes| links_of(changes.new_items))
+            .unwrap()
+        };
+
+        assert_eq!(
+            new_links(CategoryFilter::new(&["security"], &["sponsored"], true)),
+            vec!["http://example.com/1", "http://example.com/4"]
+        );
+        assert_eq!(
+            new_links(CategoryFilter::new(&[], &["sponsored"], false)),
+            vec!["http://example.com/1", "http://example.com/3"]
+        );
+    }
+
+    #[test]
+    fn should_extract_atom_and_json_feed_categories() {
+        let atom = RssOrAtomFeed::Atom(
+            Feed::read_from(
+                r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>t</title><id>t</id><updated>2004-10-26T14:06:44Z</updated><entry><title>e</title><id>1</id><updated>2004-10-26T14:06:44Z</updated><link href="http://example.com/1"/><category term="security"/><category term="rust"/></entry></feed>"#.as_bytes(),
+            )
+            .unwrap(),
+        );
+        let json = RssOrAtomFeed::Json(
+            jsonfeed::Feed::read_from(
+                r#"{"version":"https://jsonfeed.org/version/1.1","title":"t","items":[{"id":"1","url":"http://example.com/1","tags":["security"]}]}"#.as_bytes(),
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(atom.get_items()[0].categories, vec!["security", "rust"]);
+        assert_eq!(json.get_items()[0].categories, vec!["security"]);
+    }
+
+    #[test]
+    fn should_discard_new_items_published_before_since() {
+        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
+        let cache_reader = |_: &str| {
+            Ok(CachedFeed::new(
+                rss_feed_with_items(&[]),
+                CacheMetadata::default(),
+            ))
+        };
+        let getter = |_: &str, _: &Url, _: &CacheMetadata| {
+            Ok(FetchedFeed::Modified(CachedFeed::new(
+                RssOrAtomFeed::Rss2(Channel::read_from(
+                    "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><link>http://example.com/before</link><pubDate>Mon, 25 Oct 2004 23:59:59 GMT</pubDate></item><item><link>http://example.com/at</link><pubDate>Tue, 26 Oct 2004 00:00:00 GMT</pubDate></item><item><link>http://example.com/after</link><pubDate>Tue, 26 Oct 2004 02:00:00 +0100</pubDate></item><item><link>http://example.com/undated</link></item></channel></rss>"
+                        .as_bytes(),
+                )
+                .unwrap()),
+                CacheMetadata::default(),
+            )))
+        };
+        let options = FeedCheckOptions {
+            since: DateTime::parse_from_rfc3339("2004-10-26T00:00:00Z")
+                .ok()
+                .map(|since| since.with_timezone(&Utc)),
+            ..Default::default()
+        };
+        let get_new_links = |options: &FeedCheckOptions| {
+            get_and_cache_new_items_from_feed(
+                "test",
+                &feed_url,
+                cache_reader,
+                getter,
+                |_: &str, _: &CachedFeed| Ok(()),
+                options,
+            )
+            .map(|changes| links_of(changes.new_items))
+            .unwrap()
+        };
+
+        assert_eq!(
+            get_new_links(&options),
+            vec![
+                "http://example.com/at",
+                "http://example.com/after",
+                "http://example.com/undated"
+            ]
+        );
+        assert_eq!(
+            get_new_links(&FeedCheckOptions {
+                skip_undated: true,
+                ..options.clone()
+            }),
+            vec!["http://example.com/at", "http://example.com/after"]
+        );
+    }
+
+    #[test]
+    fn should_truncate_new_items_to_the_most_recent_or_first_in_feed_order() {
+        let dated = |link: &str, published: &str| FeedItem {
+            link: link.to_string(),
+            title: None,
+            published: DateTime::parse_from_rfc3339(published).ok(),
+            categories: vec![],
+        };
+        let max_items = NonZeroUsize::new(2);
+
+        let mut new_items = vec![
+            dated("http://example.com/1", "2004-10-26T00:00:00Z"),
+            dated("http://example.com/2", "2004-10-28T00:00:00Z"),
+            dated("http://example.com/3", "2004-10-25T00:00:00Z"),
+            dated("http://example.com/4", "2004-10-27T00:00:00Z"),
+        ];
+        truncate_new_items("test", &mut new_items, max_items);
+        assert_eq!(
+            links_of(new_items),
+            vec!["http://example.com/2", "http://example.com/4"]
+        );
+
+        let mut new_items = vec![
+            dated("http://example.com/1", "2004-10-26T00:00:00Z"),
+            dated("http://example.com/2", "not a date"),
+            dated("http://example.com/3", "2004-10-28T00:00:00Z"),
+        ];
+        truncate_new_items("test", &mut new_items, max_items);
+        assert_eq!(
+            links_of(new_items),
+            vec!["http://example.com/1", "http://example.com/2"]
+        );
+
+        let mut new_items = vec![dated("http://example.com/1", "2004-10-26T00:00:00Z")];
+        truncate_new_items("test", &mut new_items, None);
+        assert_eq!(links_of(new_items), vec!["http://example.com/1"]);
+    }
+
+    #[test]
+    fn should_match_normalized_links_while_returning_original_links() {
+        let cached_feed = rss_feed_with_items(&[("1", "http://example.com/post/")]);
+        let new_feed = rss_feed_with_items(&[
+            ("1", "https://example.com/post?utm_source=rss"),
+            ("2", "https://example.com/new?utm_source=rss"),
+        ]);
+        let normalizer = UrlNormalizer {
+            strip_query_params: vec!["utm_source".to_string()],
+            normalize_urls: true,
+        };
+
+        assert_eq!(
+            links_of(new_items_by_link(&normalizer, &cached_feed, &new_feed)),
+            vec!["https://example.com/new?utm_source=rss"]
+        );
+    }
+
+    #[test]
+    fn should_produce_item_titles_and_publication_dates() {
+        let channel = Channel::read_from(
+            "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><title>post</title><link>http://example.com/post</link><pubDate>Tue, 26 Oct 2004 14:06:44 GMT</pubDate></item></channel></rss>"
+                .as_bytes(),
+        )
+        .unwrap();
+        let feed = Feed::read_from(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>t</title><id>urn:feed</id><updated>2004-10-26T14:06:44Z</updated><entry><title>entry</title><id>urn:entry</id><updated>2004-10-26T14:06:44Z</updated><published>2004-10-26T14:06:44Z</published><link href=\"http://example.com/entry\"/></entry></feed>"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            channel.get_items(),
+            vec![FeedItem {
+                link: "http://example.com/post".to_string(),
+                title: Some("post".to_string()),
+                published: DateTime::parse_from_rfc3339("2004-10-26T14:06:44Z").ok(),
+                categories: vec![],
+            }]
+        );
+        assert_eq!(
+            feed.get_items(),
+            vec![FeedItem {
+                link: "http://example.com/entry".to_string(),
+                title: Some("entry".to_string()),
+                published: DateTime::parse_from_rfc3339("2004-10-26T14:06:44Z").ok(),
+                categories: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn should_prefer_atom_alternate_links_over_other_relations() {
+        let feed = Feed::read_from(
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\"><title>t</title><id>urn:feed</id><updated>2004-10-26T14:06:44Z</updated>\
+<entry><title>a</title><id>urn:1</id><updated>2004-10-26T14:06:44Z</updated><link rel=\"edit\" href=\"http://example.com/edit/1\"/><link rel=\"enclosure\" href=\"http://example.com/1.mp3\"/><link rel=\"alternate\" href=\"http://example.com/1\"/></entry>\
+<entry><title>b</title><id>urn:2</id><updated>2004-10-26T14:06:44Z</updated><link rel=\"self\" href=\"http://example.com/self/2\"/><link href=\"http://example.com/2\"/></entry>\
+<entry><title>c</title><id>urn:3</id><updated>2004-10-26T14:06:44Z</updated><link rel=\"self\" href=\"http://example.com/self/3\"/><link rel=\"enclosure\" href=\"http://example.com/3.mp3\"/></entry>\
+</feed>"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            links_of(feed.get_items()),
+            vec![
+                "http://example.com/1",
+                "http://example.com/2",
+                "http://example.com/3.mp3"
+            ]
+        );
+        assert_eq!(feed.get_links().len(), 3);
+    }
+
+    #[test]
+    fn should_take_rss_item_links_from_the_configured_link_source() {
+        let podcast = "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description>\
+<item><guid>1</guid><link>http://example.com/episodes/1</link><enclosure url=\"http://example.com/1.mp3\" length=\"1\" type=\"audio/mpeg\"/></item>\
+<item><guid>2</guid><link>http://example.com/posts/2</link></item>\
+</channel></rss>";
+        let links_from = |link_source| {
+            let feed = RssOrAtomFeed::Rss2(Channel::read_from(podcast.as_bytes()).unwrap());
+            links_of(feed.with_link_source(link_source).get_items())
+        };
+
+        assert_eq!(
+            links_from(LinkSource::Link),
+            vec![
+                "http://example.com/episodes/1",
+                "http://example.com/posts/2"
+            ]
+        );
+        assert_eq!(
+            links_from(LinkSource::Enclosure),
+            vec!["http://example.com/1.mp3"]
+        );
+        assert_eq!(
+            links_from(LinkSource::Both),
+            vec![
+                "http://example.com/episodes/1",
+                "http://example.com/1.mp3",
+                "http://example.com/posts/2"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_attribute_repeated_links_to_their_first_item() {
+        let channel = Channel::read_from(
+            "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description><item><title>original</title><link>http://example.com/post</link></item><item><title>updated</title><link>http://example.com/post</link></item><item><title>other</title><link>http://example.com/other</link></item></channel></rss>"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            channel
+                .get_items()
+                .into_iter()
+                .map(|item| (item.link, item.title))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "http://example.com/post".to_string(),
+                    Some("original".to_string())
+                ),
+                (
+                    "http://example.com/other".to_string(),
+                    Some("other".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            channel
+                .get_links()
+                .iter()
+                .map(Url::as_str)
+                .collect::<Vec<_>>(),
+            vec!["http://example.com/post", "http://example.com/other"]
+        );
+    }
+
+    #[test]
+    fn should_check_all_configured_feeds() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        std::fs::write(conf_dir.path().join("test"), feed_url.as_str()).unwrap();
+
+        let results = check_feeds(&CheckConfig::new(conf_dir.path(), cache_dir.path())).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results.get("test"), Some(Ok(new_links)) if new_links.is_empty()));
+        assert!(cache_file_path(cache_dir.path(), "test").exists());
+    }
+
+    #[test]
+    fn should_check_feeds_across_runs_against_a_live_server() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let updated_feed = MOCK_LOCAL_GOOD_FEED.replace(
+            "</channel>",
+            "<item><title>new</title><link>http://example.com/new</link></item></channel>",
+        );
+        let with_etag = |etag: &str, body: &str| {
+            format!(
+                "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                etag,
+                body.len(),
+                body
+            )
+        };
+        let (feed_url, handle) = serve_sequence(vec![
+            with_etag("\"v1\"", MOCK_LOCAL_GOOD_FEED),
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+            with_etag("\"v2\"", &updated_feed),
+        ]);
+        std::fs::write(conf_dir.path().join("test"), feed_url.as_str()).unwrap();
+        let config = CheckConfig::new(conf_dir.path(), cache_dir.path());
+
+        let new_links_by_run: Vec<Vec<String>> = (0..3)
+            .map(|_| match check_feeds(&config).unwrap().pop_first() {
+                Some((_, Ok(changes))) => changes
+                    .new_items
+                    .into_iter()
+                    .map(|item| item.link)
+                    .collect(),
+                res => panic!("unexpected check result: {:?}", res),
+            })
+            .collect();
+        let requests = handle.join().unwrap();
+
+        assert_eq!(
+            new_links_by_run,
+            vec![vec![], vec![], vec!["http://example.com/new".to_string()]]
+        );
+        assert!(requests[1]
+            .to_lowercase()
+            .contains("if-none-match: \"v1\"\r\n"));
+        assert!(requests[2]
+            .to_lowercase()
+            .contains("if-none-match: \"v1\"\r\n"));
+        assert_eq!(
+            load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Flat)
+                .read_cache("test")
+                .unwrap()
+                .metadata
+                .etag
+                .as_deref(),
+            Some("\"v2\"")
+        );
+    }
+
+    #[test]
+    fn should_only_read_the_cached_items_of_changed_feeds_when_checking_only_changed() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let updated_feed = MOCK_LOCAL_GOOD_FEED.replace(
+            "</channel>",
+            "<item><title>new</title><link>http://example.com/new</link></item></channel>",
+        );
+        let with_etag = |etag: &str, body: &str| {
+            format!(
+                "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                etag,
+                body.len(),
+                body
+            )
+        };
+        let (feed_url, handle) = serve_sequence(vec![
+            with_etag("\"v1\"", MOCK_LOCAL_GOOD_FEED),
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+            with_etag("\"v2\"", &updated_feed),
+        ]);
+        std::fs::write(conf_dir.path().join("test"), feed_url.as_str()).unwrap();
+        let mut config = CheckConfig::new(conf_dir.path(), cache_dir.path());
+        config.feed_check_options.check_only_changed = true;
+        let check = || match check_feeds(&config).unwrap().pop_first() {
+            Some((_, Ok(changes))) => changes,
+            res => panic!("unexpected check result: {:?}", res),
+        };
+
+        assert_eq!(check().fetch_status, FetchStatus::Modified);
+
+        // an unchanged feed's cached items are never read, so never parsed.
+        let cache_path = cache_file_path(cache_dir.path(), "test");
+        let cached_contents = std::fs::read(&cache_path).unwrap();
+        std::fs::write(&cache_path, "unparseable").unwrap();
+        assert_eq!(check().fetch_status, FetchStatus::NotModified);
+
+        std::fs::write(&cache_path, cached_contents).unwrap();
+        let changes = check();
+        let requests = handle.join().unwrap();
+
+        assert_eq!(changes.fetch_status, FetchStatus::Modified);
+        assert_eq!(links_of(changes.new_items), vec!["http://example.com/new"]);
+        assert!(requests[2]
+            .to_lowercase()
+            .contains("if-none-match: \"v1\"\r\n"));
+    }
+
+    #[test]
+    fn should_time_out_unresponsive_feeds() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let feed_url = Url::parse(&format!(
+            "http://{}/feed.xml",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let res = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default());
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::Timeout(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_fail_unreachable_hosts_within_the_connect_timeout() {
+        // a non-routable address, which either fails to connect immediately or
+        // never responds to the connection attempt.
+        let feed_url = Url::parse("http://10.255.255.1/feed.xml").unwrap();
+        let client = build_http_client(&HttpClientOptions {
+            request_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_millis(200),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let started = std::time::Instant::now();
+        let res = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default());
+
+        assert!(res.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn should_cache_feeds_in_their_overridden_cache_path() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let override_dir = tempfile::tempdir().unwrap();
+        let override_path = override_dir.path().join("large");
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        std::fs::write(
+            conf_dir.path().join("test.toml"),
+            format!(
+                "url = \"{}\"\ncache_path = {:?}\n",
+                feed_url,
+                override_path.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let results = check_feeds(&CheckConfig::new(conf_dir.path(), cache_dir.path())).unwrap();
+        handle.join().unwrap();
+
+        assert!(matches!(results.get("test"), Some(Ok(_))));
+        assert!(cache_file_path(&override_path, "test").exists());
+        assert!(!cache_file_path(cache_dir.path(), "test").exists());
+    }
+
+    #[test]
+    fn should_migrate_caches_named_before_hashing() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        std::fs::write(cache_dir.path().join("test"), MOCK_LOCAL_GOOD_FEED).unwrap();
+        std::fs::write(cache_dir.path().join("test.meta"), r#"{"etag": "\"abc\""}"#).unwrap();
+
+        let cached_feed = load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Sharded)
+            .read_cache("test")
+            .unwrap();
+
+        let cache_path = CacheLayout::Sharded.feed_dir(cache_dir.path(), "test");
+        assert_eq!(cached_feed.get_links().len(), 2);
+        assert_eq!(cached_feed.metadata.etag.as_deref(), Some("\"abc\""));
+        assert!(cache_file_path(&cache_path, "test").exists());
+        assert!(cache_name_file_path(&cache_path, "test").exists());
+        assert!(!cache_dir.path().join("test").exists());
+        assert!(!cache_dir.path().join("test.meta").exists());
+    }
+
+    #[test]
+    fn should_clean_each_feeds_overridden_cache_path() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let override_dir = tempfile::tempdir().unwrap();
+        let override_path = override_dir.path().join("large");
+        std::fs::write(
+            conf_dir.path().join("test.toml"),
+            format!(
+                "url = \"http://example.com/a.xml\"\ncache_path = {:?}\n",
+                override_path.display().to_string()
+            ),
+        )
+        .unwrap();
+        let cached_feed = CachedFeed::new(
+            rss_feed_with_items(&[("1", "http://example.com/1")]),
+            CacheMetadata::default(),
+        );
+        for (cache_path, feed_name) in [
+            (override_path.as_path(), "test"),
+            (override_path.as_path(), "removed"),
+            // the feed is only active in its own cache path.
+            (cache_dir.path(), "test"),
+        ] {
+            cache_feed_to_disk(cache_path, CacheLayout::Flat)
+                .write_cache(feed_name, &cached_feed)
+                .unwrap();
+        }
+
+        let config = CheckConfig::new(conf_dir.path(), cache_dir.path());
+        let removed = clean_cache(
+            conf_dir.path(),
+            cache_dir.path(),
+            CacheLayout::Flat,
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            cache_paths(&config),
+            BTreeSet::from([cache_dir.path().to_owned(), override_path.clone()])
+        );
+        assert_eq!(removed.len(), 4);
+        assert!(cache_file_path(&override_path, "test").exists());
+        assert!(!cache_file_path(&override_path, "removed").exists());
+        assert!(!cache_file_path(cache_dir.path(), "test").exists());
+    }
+
+    #[test]
+    fn should_skip_repeatedly_failing_feeds_while_backing_off() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let feed_dir = tempfile::tempdir().unwrap();
+        let missing_feed_path = feed_dir.path().join("missing.xml");
+        std::fs::write(
+            conf_dir.path().join("test"),
+            Url::from_file_path(&missing_feed_path).unwrap().as_str(),
+        )
+        .unwrap();
+        let config = CheckConfig {
+            error_backoff: Some(BackoffPolicy::default()),
+            ..CheckConfig::new(conf_dir.path(), cache_dir.path())
+        };
+        let check = || check_feeds(&config).unwrap().remove("test").unwrap();
+
+        assert!(check().is_err());
+        assert!(check().is_err());
+        assert!(cache_failures_file_path(cache_dir.path(), "test").exists());
+        assert_eq!(check().unwrap(), FeedChanges::default());
+
+        // a successful check resets the failures, once the backoff has passed.
+        std::fs::write(&missing_feed_path, MOCK_LOCAL_GOOD_FEED).unwrap();
+        std::fs::remove_file(cache_failures_file_path(cache_dir.path(), "test")).unwrap();
+        assert!(check().is_ok());
+        assert!(!cache_failures_file_path(cache_dir.path(), "test").exists());
+    }
+
+    #[test]
+    fn should_report_every_config_error_when_checking_feeds() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        std::fs::write(conf_dir.path().join("a"), "not a url").unwrap();
+        std::fs::write(conf_dir.path().join("b"), "not a url").unwrap();
+
+        let res = check_feeds(&CheckConfig::new(conf_dir.path(), cache_dir.path()));
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::Multiple(errors),
+                ..
+            }) if errors.len() == 2
+        ));
+    }
+
+    #[test]
+    fn should_not_cache_feeds_outside_cache_path_for_crafted_names() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let root_dir = tempfile::tempdir().unwrap();
+        let cache_path = root_dir.path().join("cache");
+        std::fs::write(
+            conf_dir.path().join("test.toml"),
+            "url = \"http://example.com/feed.xml\"\nname = \"../escape\"",
+        )
+        .unwrap();
+
+        let res = check_feeds(&CheckConfig::new(conf_dir.path(), &cache_path));
+
+        assert!(matches!(
+            res,
+            Err(Error {
+                kind: ErrorKind::Multiple(errors),
+                ..
+            }) if matches!(errors.as_slice(), [Error { kind: ErrorKind::UnsafeFeedName(_), .. }])
+        ));
+        assert_eq!(std::fs::read_dir(root_dir.path()).unwrap().count(), 1);
+        assert_eq!(std::fs::read_dir(&cache_path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn should_clean_cache_files_of_unconfigured_feeds() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let root_dir = tempfile::tempdir().unwrap();
+        let cache_path = root_dir.path().join("cache");
+        std::fs::create_dir(&cache_path).unwrap();
+        std::fs::write(conf_dir.path().join("active"), "http://example.com/a.xml").unwrap();
+        let cached_feed = CachedFeed::new(
+            rss_feed_with_items(&[("1", "http://example.com/1")]),
+            CacheMetadata {
+                etag: Some("\"abc\"".to_string()),
+                last_modified: None,
+                ..Default::default()
+            },
+        );
+        for feed_name in ["active", "removed"] {
+            cache_feed_to_disk(&cache_path, CacheLayout::Flat)
+                .write_cache(feed_name, &cached_feed)
+                .unwrap();
+        }
+        std::fs::write(cache_path.join("unrelated"), "").unwrap();
+        std::fs::write(root_dir.path().join(cache_file_name("removed")), "").unwrap();
+
+        let dry_run_removed =
+            clean_cache(conf_dir.path(), &cache_path, CacheLayout::Flat, None, true).unwrap();
+        let removed =
+            clean_cache(conf_dir.path(), &cache_path, CacheLayout::Flat, None, false).unwrap();
+
+        assert_eq!(dry_run_removed, removed);
+        assert_eq!(
+            removed,
+            vec![
+                cache_file_path(&cache_path, "removed"),
+                cache_metadata_file_path(&cache_path, "removed"),
+                cache_name_file_path(&cache_path, "removed"),
+            ]
+        );
+        assert!(removed.iter().all(|path| !path.exists()));
+        assert!(cache_file_path(&cache_path, "active").exists());
+        assert!(cache_path.join("unrelated").exists());
+        assert!(root_dir.path().join(cache_file_name("removed")).exists());
+    }
+
+    #[test]
+    fn should_clean_cache_files_older_than_max_age() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        std::fs::write(conf_dir.path().join("active"), "http://example.com/a.xml").unwrap();
+        cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat)
+            .write_cache(
+                "active",
+                &CachedFeed::new(
+                    rss_feed_with_items(&[("1", "http://example.com/1")]),
+                    CacheMetadata::default(),
+                ),
+            )
+            .unwrap();
+
+        let fresh = clean_cache(
+            conf_dir.path(),
+            cache_dir.path(),
+            CacheLayout::Flat,
+            Some(Duration::from_secs(3600)),
+            false,
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let expired = clean_cache(
+            conf_dir.path(),
+            cache_dir.path(),
+            CacheLayout::Flat,
+            Some(Duration::from_millis(10)),
+            false,
+        )
+        .unwrap();
+
+        assert!(fresh.is_empty());
+        assert_eq!(expired.len(), 2);
+        assert!(!cache_file_path(cache_dir.path(), "active").exists());
+    }
+
+    #[test]
+    fn should_not_clean_the_cache_of_feeds_answering_not_modified() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (url, handle) = serve_once(
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        std::fs::write(conf_dir.path().join("active"), url.as_str()).unwrap();
+        cache_feed_to_disk(cache_dir.path(), CacheLayout::Flat)
+            .write_cache(
+                "active",
+                &CachedFeed::new(
+                    rss_feed_with_items(&[("1", "http://example.com/1")]),
+                    CacheMetadata {
+                        etag: Some("\"abc\"".to_string()),
+                        ..Default::default()
+                    },
+                ),
+            )
+            .unwrap();
+        // the feed's contents last changed well over `max_age` ago.
+        std::fs::File::options()
+            .write(true)
+            .open(cache_file_path(cache_dir.path(), "active"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+
+        let results = check_feeds(&CheckConfig::new(conf_dir.path(), cache_dir.path())).unwrap();
+        handle.join().unwrap();
+        let removed = clean_cache(
+            conf_dir.path(),
+            cache_dir.path(),
+            CacheLayout::Flat,
+            Some(Duration::from_secs(60 * 60)),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            results["active"].as_ref().unwrap().fetch_status,
+            FetchStatus::NotModified
+        );
+        assert!(removed.is_empty(), "{:?}", removed);
+        assert!(cache_file_path(cache_dir.path(), "active").exists());
+    }
+
+    #[test]
+    fn should_shard_cache_files_by_hashed_feed_name() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cached_feed = CachedFeed::new(
+            rss_feed_with_items(&[("1", "http://example.com/1")]),
+            CacheMetadata::default(),
+        );
+
+        cache_writer_for_mode(cache_dir.path(), CacheMode::Full, CacheLayout::Sharded)
+            .write_cache("test", &cached_feed)
+            .unwrap();
+        let loaded = load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Sharded)
+            .read_cache("test")
+            .unwrap();
+
+        let hash = cache_file_name("test");
+        let shard_path = cache_dir.path().join(&hash[..2]);
+        assert!(shard_path.join(&hash).exists());
+        assert!(!cache_dir.path().join(&hash).exists());
+        assert_eq!(
+            loaded.contents.get_item_links(),
+            vec!["http://example.com/1"]
+        );
+    }
+
+    #[test]
+    fn should_clean_cache_files_left_by_another_layout() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        std::fs::write(conf_dir.path().join("active"), "http://example.com/a.xml").unwrap();
+        let cached_feed = CachedFeed::new(
+            rss_feed_with_items(&[("1", "http://example.com/1")]),
+            CacheMetadata::default(),
+        );
+        for layout in [CacheLayout::Flat, CacheLayout::Sharded] {
+            cache_feed_to_disk(cache_dir.path(), layout)
+                .write_cache("active", &cached_feed)
+                .unwrap();
+        }
+
+        let removed = clean_cache(
+            conf_dir.path(),
+            cache_dir.path(),
+            CacheLayout::Sharded,
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            removed,
+            vec![
+                cache_file_path(cache_dir.path(), "active"),
+                cache_name_file_path(cache_dir.path(), "active"),
+            ]
+        );
+        assert!(
+            load_cached_feed_from_disk(cache_dir.path(), CacheLayout::Sharded)
+                .read_cache("active")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn should_declare_feed_formats_by_content_type() {
+        let declared = |content_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(reqwest::header::CONTENT_TYPE, content_type.parse().unwrap());
+            declared_feed_format(&headers)
+        };
+
+        assert_eq!(
+            declared("application/rss+xml; charset=utf-8"),
+            Some(FeedFormat::Rss)
+        );
+        assert_eq!(declared("Application/Atom+XML"), Some(FeedFormat::Atom));
+        assert_eq!(declared("application/feed+json"), Some(FeedFormat::Json));
+        assert_eq!(declared("text/xml"), None);
+    }
+
+    #[test]
+    fn should_transcode_latin1_feeds_by_response_charset() {
+        let body = b"<rss version=\"2.0\"><channel><title>caf\xe9</title><link>http://example.com</link><description>test</description><item><title>cr\xe8me br\xfbl\xe9e</title><link>http://example.com/cr\xe8me</link></item></channel></rss>";
+        let response = [
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml; charset=ISO-8859-1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .into_bytes(),
+            body.to_vec(),
+        ]
+        .concat();
+        let (feed_url, handle) = serve_once(response);
+        let client = build_http_client(&HttpClientOptions::default()).unwrap();
+
+        let feed = get_feed_with_blocking_http_request(
+            &client,
+            DEFAULT_MAX_BODY_BYTES,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        )
+        .get_feed("test", &feed_url, &CacheMetadata::default())
+        .map(expect_modified)
+        .unwrap();
+        handle.join().unwrap();
+
+        let CacheContents::Feed(contents) = feed.contents else {
+            panic!("expected feed contents");
+        };
+        assert_eq!(
+            contents.get_items(),
+            vec![FeedItem {
+                link: "http://example.com/cr%C3%A8me".to_string(),
+                title: Some("crème brûlée".to_string()),
+                published: None,
+                categories: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn should_prefer_byte_order_marks_and_xml_declarations_over_charset() {
+        let latin1 = b"<?xml version=\"1.0\" encoding='ISO-8859-1'?><title>caf\xe9</title>";
+        let utf8_with_bom = b"\xef\xbb\xbf{\"title\": \"caf\xc3\xa9\"}";
+
+        assert_eq!(
+            decode_feed_body(latin1, Some(encoding_rs::UTF_8)).as_ref(),
+            "<?xml version=\"1.0\" encoding='UTF-8'?><title>café</title>".as_bytes()
+        );
+        assert_eq!(
+            decode_feed_body(utf8_with_bom, Some(encoding_rs::WINDOWS_1252)).as_ref(),
+            "{\"title\": \"café\"}".as_bytes()
+        );
+        // JSON Feed is UTF-8 regardless of a mislabeled charset.
+        assert_eq!(
+            decode_feed_body(
+                " {\"title\": \"café\"}".as_bytes(),
+                Some(encoding_rs::WINDOWS_1252)
+            )
+            .as_ref(),
+            " {\"title\": \"café\"}".as_bytes()
+        );
+        assert!(matches!(
+            parse_fetched_feed(
+                "test",
+                &decode_feed_body(
+                    &[b"\xef\xbb\xbf", MOCK_LOCAL_GOOD_JSON_FEED.as_bytes()].concat(),
+                    None
+                ),
+                None,
+                None
+            ),
+            Ok(RssOrAtomFeed::Json(_))
+        ));
+    }
+
+    #[test]
+    fn should_parse_mislabeled_feeds_by_falling_back_to_every_format() {
+        let feed = parse_fetched_feed(
+            "test",
+            MOCK_LOCAL_GOOD_JSON_FEED.as_bytes(),
+            Some(FeedFormat::Atom),
+            None,
+        );
+
+        assert!(matches!(feed, Ok(RssOrAtomFeed::Json(_))));
+        assert!(matches!(
+            parse_fetched_feed(
+                "test",
+                MOCK_LOCAL_GOOD_FEED.as_bytes(),
+                Some(FeedFormat::Rss),
+                None
+            ),
+            Ok(RssOrAtomFeed::Rss2(_))
+        ));
+    }
+
+    #[test]
+    fn should_include_the_content_type_and_body_start_in_unparseable_feed_errors() {
+        let body = format!(
+            "<!DOCTYPE html><html><body>{}</body></html>",
+            "é".repeat(200)
+        );
+
+        let Err(err) = parse_fetched_feed(
+            "test",
+            body.as_bytes(),
+            None,
+            Some("text/html; charset=utf-8"),
+        ) else {
+            panic!("html parsed as a feed");
+        };
+
+        match &err.kind {
+            ErrorKind::FeedIsNeitherAtomOrRss {
+                content_type,
+                snippet,
+                ..
+            } => {
+                assert_eq!(content_type.as_deref(), Some("text/html; charset=utf-8"));
+                // the two byte é straddling the limit is dropped whole.
+                assert_eq!(snippet.len(), BODY_SNIPPET_MAX_BYTES - 1);
+                assert!(snippet.starts_with("<!DOCTYPE html><html><body>é"));
+            }
+            kind => panic!("unexpected error kind: {:?}", kind),
+        }
+        assert!(err
+            .to_string()
+            .contains("served as text/html; charset=utf-8, starting \"<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn should_find_xml_root_element_name() {
+        assert_eq!(
+            xml_root_element_name(
+                b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<!-- <rss> -->\n<!DOCTYPE feed>\n<atom:feed xmlns:atom=\"http://www.w3.org/2005/Atom\">"
+            ),
+            Some("feed")
+        );
+        assert_eq!(
+            xml_root_element_name(MOCK_LOCAL_GOOD_FEED.as_bytes()),
+            Some("rss")
+        );
+        assert_eq!(xml_root_element_name(b"not xml"), None);
+    }
+
+    #[test]
+    fn should_disambiguate_documents_parsing_as_both_rss_and_atom() {
+        let rss_doc = b"<rss version=\"2.0\"><feed/></rss>";
+        let atom_doc = b"<feed xmlns=\"http://www.w3.org/2005/Atom\"><rss/></feed>";
+
+        assert!(matches!(
+            disambiguate_feed(rss_doc, Channel::default(), Feed::default()),
+            RssOrAtomFeed::Rss2(_)
+        ));
+        assert!(matches!(
+            disambiguate_feed(atom_doc, Channel::default(), Feed::default()),
+            RssOrAtomFeed::Atom(_)
+        ));
+        // anything else falls back to rss.
+        assert!(matches!(
+            disambiguate_feed(b"", Channel::default(), Feed::default()),
+            RssOrAtomFeed::Rss2(_)
+        ));
+    }
+
+    #[test]
+    fn should_parse_valid_json_feed_skipping_items_without_links() {
+        let feed = jsonfeed::Feed::read_from(MOCK_LOCAL_GOOD_JSON_FEED.as_bytes())
+            .map(RssOrAtomFeed::Json)
+            .unwrap();
+
+        let links: Vec<_> = feed.get_links().into_iter().map(String::from).collect();
+
+        assert_eq!(
+            links,
+            vec![
+                "http://www.feedforall.com/",
+                "http://www.feedforall.com/feedforall-partners.htm"
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! An advisory lock on the cache directory, preventing concurrent runs, such
+//! as overlapping scheduled checks, from racing on the same cache files.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+use crate::{ensure_cache_dir, Error, ErrorKind};
+
+/// The file within the cache directory that is locked for the length of a
+/// run. Its leading `.` keeps it from being mistaken for a cache file.
+pub const CACHE_LOCK_FILE_NAME: &str = ".rss_checker.lock";
+
+/// An exclusive lock on a cache directory, released when dropped, including
+/// when the process exits.
+#[derive(Debug)]
+pub struct CacheLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl CacheLock {
+    /// Acquires the lock on `cache_path`, creating the directory if needed.
+    /// Fails immediately, rather than waiting, if another run holds it.
+    pub fn acquire(cache_path: &Path) -> Result<Self, Error> {
+        ensure_cache_dir(cache_path)?;
+
+        let path = cache_path.join(CACHE_LOCK_FILE_NAME);
+        let lock_error = |kind| Error::new(kind).with_data(format!("lock[{}]", path.display()));
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|err| lock_error(ErrorKind::IoErr(err)))?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Self { path, _file: file }),
+            Err(TryLockError::WouldBlock) => Err(Error::new(ErrorKind::CacheLocked(
+                cache_path.display().to_string(),
+            ))),
+            Err(TryLockError::Error(err)) => Err(lock_error(ErrorKind::IoErr(err))),
+        }
+    }
+
+    /// The path of the locked file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_only_allow_one_holder_of_the_cache_lock() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache_path = cache_dir.path().join("cache");
+
+        let lock = CacheLock::acquire(&cache_path).unwrap();
+        assert!(lock.path().exists());
+        assert!(matches!(
+            CacheLock::acquire(&cache_path),
+            Err(Error {
+                kind: ErrorKind::CacheLocked(_),
+                ..
+            })
+        ));
+
+        drop(lock);
+        assert!(CacheLock::acquire(&cache_path).is_ok());
+    }
+}
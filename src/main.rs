@@ -1,295 +1,1122 @@
-use std::collections::{BTreeSet, HashSet};
-use std::fs::OpenOptions;
-use std::io::{self, BufReader};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, IsTerminal};
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use rss_checker::backoff::BackoffPolicy;
+use rss_checker::duration::{parse_duration, parse_since};
+use rss_checker::exec::{ExecHook, DEFAULT_EXEC_CONCURRENCY};
+use rss_checker::filter::FeedNameFilter;
+use rss_checker::global_config::{self, GlobalConfig};
+use rss_checker::history;
+use rss_checker::lock::CacheLock;
+use rss_checker::normalize::UrlNormalizer;
+use rss_checker::notify::{
+    FileNotifier, Notifier, NotifyTarget, RunReport, StdoutNotifier, WebhookNotifier,
+};
+use rss_checker::opml::{self, OpmlOutline};
+use rss_checker::output::{
+    self, ColorChoice, NewItemsByFeed, OutputFormat, OutputOptions, RunSummary, SortBy,
+};
+use rss_checker::selftest::{self, SelfTestCheck};
+use rss_checker::validate;
+use rss_checker::walker::FeedUrl;
+use rss_checker::webhook::WebhookFormat;
+use rss_checker::{
+    walker, CacheLayout, CacheMode, CheckConfig, DedupKey, Error, ErrorKind, FeedCheckOptions,
+    FeedCheckResults, FetchStatus, HttpClientOptions, LinkSource, RetryPolicy,
+    DEFAULT_MAX_BODY_BYTES, DEFAULT_USER_AGENT,
+};
+use serde::Serialize;
 
-use atom_syndication::Feed;
-use clap::{Parser, ValueEnum};
-use rayon::prelude::*;
-use reqwest::Url;
-use rss::Channel;
-
-mod error;
-pub(crate) use error::{Error, ErrorKind};
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogLevelArg {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
 
-mod walker;
+impl From<LogLevelArg> for log::LevelFilter {
+    fn from(value: LogLevelArg) -> Self {
+        match value {
+            LogLevelArg::Off => Self::Off,
+            LogLevelArg::Error => Self::Error,
+            LogLevelArg::Warn => Self::Warn,
+            LogLevelArg::Info => Self::Info,
+            LogLevelArg::Debug => Self::Debug,
+            LogLevelArg::Trace => Self::Trace,
+        }
+    }
+}
 
-enum RssOrAtomFeed {
-    Rss2(Channel),
-    Atom(Feed),
+impl LogLevelArg {
+    /// Maps the number of times `-v` was passed to a log level, with each
+    /// occurrence increasing verbosity from warn up to trace.
+    fn from_verbosity(count: u8) -> Option<Self> {
+        match count {
+            0 => None,
+            1 => Some(Self::Warn),
+            2 => Some(Self::Info),
+            3 => Some(Self::Debug),
+            _ => Some(Self::Trace),
+        }
+    }
 }
 
-trait LinkProduceable {
-    fn get_links(&self) -> Vec<Url>;
+/// The format log records are written to stderr in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    /// env_logger's human readable format.
+    Text,
+    /// one json object per line, for log aggregators.
+    Json,
 }
 
-impl LinkProduceable for rss::Channel {
-    fn get_links(&self) -> Vec<Url> {
-        self.items()
-            .iter()
-            .filter_map(|item| item.link())
-            .filter_map(|link| Url::parse(link).ok())
-            .collect()
-    }
+/// A log record as written by [LogFormat::Json].
+#[derive(Serialize)]
+struct JsonLogRecord<'a> {
+    level: &'a str,
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed: Option<String>,
+    message: String,
 }
 
-impl LinkProduceable for atom_syndication::Feed {
-    fn get_links(&self) -> Vec<Url> {
-        self.entries()
-            .iter()
-            .flat_map(|entry| entry.links())
-            .filter_map(|link| Url::parse(link.href()).ok())
-            .collect()
-    }
+/// The structured field identifying the feed a log record concerns.
+const FEED_LOG_KEY: &str = "feed";
+
+fn write_json_log_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+) -> io::Result<()> {
+    use std::io::Write;
+
+    let json_record = JsonLogRecord {
+        level: record.level().as_str(),
+        timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        feed: record
+            .key_values()
+            .get(log::kv::Key::from_str(FEED_LOG_KEY))
+            .map(|feed| feed.to_string()),
+        message: record.args().to_string(),
+    };
+
+    serde_json::to_writer(&mut *buf, &json_record)?;
+    writeln!(buf)
 }
 
-impl LinkProduceable for RssOrAtomFeed {
-    fn get_links(&self) -> Vec<Url> {
-        match self {
-            RssOrAtomFeed::Rss2(channel) => channel.get_links(),
-            RssOrAtomFeed::Atom(feed) => feed.get_links(),
+/// The exit code signaling new links were found, when requested.
+const NEW_LINKS_FOUND_EXIT_CODE: u8 = 10;
+
+/// Validates a proxy url at startup, rather than on the first request.
+fn parse_proxy(url: &str) -> Result<reqwest::Proxy, String> {
+    let url = reqwest::Url::parse(url).map_err(|err| err.to_string())?;
+
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => {
+            reqwest::Proxy::all(url).map_err(|err| err.to_string())
         }
+        scheme => Err(format!("unsupported proxy scheme: {}", scheme)),
     }
 }
 
-trait FeedCacheReadable {
-    fn read_cache(&self, feed_name: &str) -> Result<RssOrAtomFeed, Error>;
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+enum Command {
+    /// check for new links in all configured feeds. This is the default
+    Check,
+    /// validate all configuration files, reporting every error found, without
+    /// fetching any feeds
+    CheckConfig,
+    /// write all configured feeds as an OPML document
+    ExportOpml {
+        /// the file to write the document to, defaulting to stdout
+        #[arg(long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// remove cache files left by feeds that are no longer configured,
+    /// listing each file removed. Nothing is removed under --dry-run
+    CleanCache {
+        /// also remove the cache of any feed last cached longer ago than this
+        /// duration, such as `30d`
+        #[arg(long = "max-age", value_parser = parse_duration)]
+        max_age: Option<Duration>,
+    },
+    /// fetch a single feed and report whether it parsed, how many of its
+    /// items yield links and any issues found, without touching the cache.
+    /// Fails if the feed can't be fetched or parsed
+    ValidateFeed {
+        /// the url of the feed to validate
+        url: reqwest::Url,
+    },
+    /// print the value of every option in effect, as resolved from flags,
+    /// environment variables, the global config file and defaults, each
+    /// annotated with where it was set. Nothing is fetched
+    PrintConfig {
+        /// the format the options are printed in
+        #[arg(long = "format", default_value = "toml")]
+        format: ConfigFormat,
+    },
+    /// check that the configured feeds parse, the cache is writable and
+    /// feeds can be fetched, reporting the outcome of each check. Fails if
+    /// any check fails
+    Selftest {
+        /// the feed fetched to check outbound requests, defaulting to the
+        /// first configured http feed without credentials
+        #[arg(long = "selftest-url")]
+        selftest_url: Option<reqwest::Url>,
+    },
 }
 
-impl<F> FeedCacheReadable for F
-where
-    F: Fn(&str) -> Result<RssOrAtomFeed, Error>,
-{
-    fn read_cache(&self, feed_name: &str) -> Result<RssOrAtomFeed, Error> {
-        (self)(feed_name)
+impl Command {
+    /// Returns whether the command reads the configured feeds.
+    fn reads_feeds(&self) -> bool {
+        !matches!(self, Self::ValidateFeed { .. } | Self::PrintConfig { .. })
     }
 }
 
-trait FeedGettable {
-    fn get_feed(&self, feed_name: &str, url: &Url) -> Result<RssOrAtomFeed, Error>;
+/// The formats the effective configuration is printed in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    /// a toml document, with each option's source as a preceding comment.
+    Toml,
+    /// a json object mapping each option to its value and source.
+    Json,
 }
 
-impl<F> FeedGettable for F
-where
-    F: Fn(&str, &Url) -> Result<RssOrAtomFeed, Error>,
-{
-    fn get_feed(&self, feed_name: &str, url: &Url) -> Result<RssOrAtomFeed, Error> {
-        (self)(feed_name, url)
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// a toml file providing defaults for options not otherwise set by a
+    /// flag or environment variable. Defaults to
+    /// `~/.config/rss_checker/config.toml`, if present
+    #[arg(long = "config-file", env = "RSS_CHECKER_CONFIG_FILE")]
+    config_file: Option<PathBuf>,
+
+    /// the directory path to source configuration files. Not required when
+    /// feeds are given by --feeds-from or --feed
+    #[arg(
+        long = "conf-path",
+        env = "RSS_CHECKER_CONF_PATH",
+        required_unless_present_any = ["feeds_from", "feeds"]
+    )]
+    conf_path: Option<PathBuf>,
+
+    /// a file listing the feeds to check in place of the configuration
+    /// directory, or `-` to read them from stdin. Each line is either a bare
+    /// url, named after its host, or a `name=url` pair
+    #[arg(long = "feeds-from", env = "RSS_CHECKER_FEEDS_FROM")]
+    feeds_from: Option<PathBuf>,
+
+    /// a feed to check in place of the configuration directory, given as a
+    /// bare url or a `name=url` pair. Repeatable, and combined with any
+    /// --feeds-from list
+    #[arg(long = "feed")]
+    feeds: Vec<String>,
+
+    /// only check feeds whose name matches this glob, where `*` matches any
+    /// run of characters and `?` any single character. Repeatable, failing if
+    /// no feed matches any
+    #[arg(long = "only")]
+    only: Vec<String>,
+
+    /// skip feeds whose name matches this glob. Repeatable, and takes
+    /// precedence over --only
+    #[arg(long = "exclude")]
+    exclude: Vec<String>,
+
+    /// the directory path to store all cache files
+    #[arg(
+        long = "cache-path",
+        env = "RSS_CHECKER_CACHE_PATH",
+        default_value = ".rss_checker/cache"
+    )]
+    cache_path: PathBuf,
+
+    /// what is persisted when caching a feed
+    #[arg(
+        long = "cache-mode",
+        env = "RSS_CHECKER_CACHE_MODE",
+        default_value = "full"
+    )]
+    cache_mode: CacheMode,
+
+    /// how cache files are arranged within the cache directory. Switching
+    /// layouts starts each feed's cache afresh, run `clean-cache` to remove
+    /// the previous layout's files
+    #[arg(
+        long = "cache-layout",
+        env = "RSS_CHECKER_CACHE_LAYOUT",
+        default_value = "flat"
+    )]
+    cache_layout: CacheLayout,
+
+    /// the directory path to store all cache files
+    #[arg(long = "log-level", env = "RUST_LOG", default_value = "error")]
+    log_level: Option<LogLevelArg>,
+
+    /// increase logging verbosity, repeatable up to `-vvvv`. An explicit
+    /// `--log-level` takes precedence
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    /// write nothing to stderr, neither log records nor the --summary,
+    /// leaving only links on stdout. Failures are still reported by the exit
+    /// code. Takes precedence over --log-level and --verbose
+    #[arg(short = 'q', long = "quiet", env = "RSS_CHECKER_QUIET")]
+    quiet: bool,
+
+    /// when to colorize plain output and log records, one of auto, always or
+    /// never, `auto` only doing so when writing to a terminal. `true` and `1`
+    /// are taken as `always`, `false` and `0` as `never`. A bare `--color` is
+    /// taken as `always`
+    #[arg(
+        long = "color",
+        env = "RSS_CHECKER_COLOR",
+        default_value = "auto",
+        num_args = 0..=1,
+        default_missing_value = "always",
+        value_parser = ColorChoice::parse
+    )]
+    color: ColorChoice,
+
+    /// the format log records are written to stderr in
+    #[arg(
+        long = "log-format",
+        env = "RSS_CHECKER_LOG_FORMAT",
+        default_value = "text"
+    )]
+    log_format: LogFormat,
+
+    /// the maximum time to wait on a single feed request, including reading
+    /// its body, as a duration such as `30s`. A bare number is taken as
+    /// seconds
+    #[arg(
+        long = "request-timeout",
+        alias = "read-timeout",
+        env = "RSS_CHECKER_REQUEST_TIMEOUT",
+        default_value = "30s",
+        value_parser = parse_duration
+    )]
+    request_timeout: Duration,
+
+    /// the maximum time to wait on connecting to a feed's host, as a duration
+    /// such as `5s`. A bare number is taken as seconds
+    #[arg(
+        long = "connect-timeout",
+        env = "RSS_CHECKER_CONNECT_TIMEOUT",
+        default_value = "10s",
+        value_parser = parse_duration
+    )]
+    connect_timeout: Duration,
+
+    /// the minimum age of a feed's cache before it is fetched again, as a
+    /// duration such as `15m`. A bare number is taken as seconds. 0 always
+    /// fetches
+    #[arg(
+        long = "min-cache-age",
+        env = "RSS_CHECKER_MIN_CACHE_AGE",
+        default_value = "0s",
+        value_parser = parse_duration
+    )]
+    min_cache_age: Duration,
+
+    /// also skip fetching a feed while within the freshness lifetime its
+    /// server declared via `Cache-Control: max-age`. Whichever of this and
+    /// --min-cache-age is longer applies
+    #[arg(
+        long = "respect-cache-control",
+        env = "RSS_CHECKER_RESPECT_CACHE_CONTROL"
+    )]
+    respect_cache_control: bool,
+
+    /// the key used to determine whether an item is new
+    #[arg(
+        long = "dedup-key",
+        env = "RSS_CHECKER_DEDUP_KEY",
+        default_value = "link"
+    )]
+    dedup_key: DedupKey,
+
+    /// the element of each RSS item its link is taken from, such as a podcast
+    /// episode's enclosure
+    #[arg(
+        long = "link-source",
+        env = "RSS_CHECKER_LINK_SOURCE",
+        default_value = "link"
+    )]
+    link_source: LinkSource,
+
+    /// discard new items published before this RFC3339 timestamp, or relative
+    /// age such as `7d`
+    #[arg(long = "since", env = "RSS_CHECKER_SINCE", value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// discard new items without a publication date when filtering with
+    /// `--since`
+    #[arg(
+        long = "skip-undated",
+        env = "RSS_CHECKER_SKIP_UNDATED",
+        requires = "since"
+    )]
+    skip_undated: bool,
+
+    /// the maximum number of new links reported for a single feed, keeping
+    /// the most recently published when every new item is dated, otherwise
+    /// the first in feed order. Defaults to unlimited
+    #[arg(long = "max-items-per-feed", env = "RSS_CHECKER_MAX_ITEMS_PER_FEED")]
+    max_items_per_feed: Option<NonZeroUsize>,
+
+    /// a comma separated list of query parameters ignored when matching links,
+    /// such as `utm_source`. A trailing `*` matches by prefix, such as `utm_*`
+    #[arg(
+        long = "strip-query-params",
+        env = "RSS_CHECKER_STRIP_QUERY_PARAMS",
+        value_delimiter = ','
+    )]
+    strip_query_params: Vec<String>,
+
+    /// ignore the scheme and trailing slashes of links when matching them
+    #[arg(long = "normalize-urls", env = "RSS_CHECKER_NORMALIZE_URLS")]
+    normalize_urls: bool,
+
+    /// the user agent sent with each feed request
+    #[arg(
+        long = "user-agent",
+        env = "RSS_CHECKER_USER_AGENT",
+        default_value = DEFAULT_USER_AGENT
+    )]
+    user_agent: String,
+
+    /// a http or socks5 proxy url all feed requests are routed through.
+    /// Otherwise any system proxy, such as `HTTPS_PROXY`, is honored
+    #[arg(long = "proxy", env = "RSS_CHECKER_PROXY", value_parser = parse_proxy)]
+    proxy: Option<reqwest::Proxy>,
+
+    /// the maximum number of redirects followed for a single feed request
+    #[arg(
+        long = "max-redirects",
+        env = "RSS_CHECKER_MAX_REDIRECTS",
+        default_value_t = 10
+    )]
+    max_redirects: usize,
+
+    /// the maximum size, in bytes, of a feed response body
+    #[arg(
+        long = "max-body-bytes",
+        env = "RSS_CHECKER_MAX_BODY_BYTES",
+        default_value_t = DEFAULT_MAX_BODY_BYTES
+    )]
+    max_body_bytes: u64,
+
+    /// the maximum number of times a transiently failing feed is retried
+    #[arg(
+        long = "max-retries",
+        env = "RSS_CHECKER_MAX_RETRIES",
+        default_value_t = 3
+    )]
+    max_retries: u32,
+
+    /// the delay, in milliseconds, before the first retry. Doubles with each
+    /// subsequent attempt
+    #[arg(
+        long = "retry-base-delay-ms",
+        env = "RSS_CHECKER_RETRY_BASE_DELAY_MS",
+        default_value_t = 500
+    )]
+    retry_base_delay_ms: u64,
+
+    /// the maximum, in milliseconds, of a random delay before each feed is
+    /// fetched, spreading out parallel requests to the same host
+    #[arg(
+        long = "fetch-jitter-ms",
+        env = "RSS_CHECKER_FETCH_JITTER_MS",
+        default_value_t = 0
+    )]
+    fetch_jitter_ms: u64,
+
+    /// the maximum number of requests per second made to any one host,
+    /// including retries. Unlimited when unset
+    #[arg(long = "per-host-rps", env = "RSS_CHECKER_PER_HOST_RPS")]
+    per_host_rps: Option<NonZeroU32>,
+
+    /// the format new links are written to stdout in
+    #[arg(
+        long = "output-format",
+        env = "RSS_CHECKER_OUTPUT_FORMAT",
+        default_value = "plain"
+    )]
+    output_format: OutputFormat,
+
+    /// group new links under a header line for the feed they belong to
+    #[arg(long = "group-by-feed", env = "RSS_CHECKER_GROUP_BY_FEED")]
+    group_by_feed: bool,
+
+    /// include feeds without any new links when grouping by feed
+    #[arg(
+        long = "show-empty",
+        env = "RSS_CHECKER_SHOW_EMPTY",
+        requires = "group_by_feed"
+    )]
+    show_empty: bool,
+
+    /// the order new links are written in
+    #[arg(long = "sort-by", env = "RSS_CHECKER_SORT_BY", default_value = "link")]
+    sort_by: SortBy,
+
+    /// order links oldest first when sorting by date
+    #[arg(long = "oldest-first", env = "RSS_CHECKER_OLDEST_FIRST")]
+    oldest_first: bool,
+
+    /// report a link found in many feeds once, under the first feed by name,
+    /// rather than once per feed
+    #[arg(
+        long = "cross-feed-dedup",
+        env = "RSS_CHECKER_CROSS_FEED_DEDUP",
+        default_value = "on",
+        action = ArgAction::Set,
+        value_parser = BoolishValueParser::new()
+    )]
+    cross_feed_dedup: bool,
+
+    /// skip feeds that have failed repeatedly until a backoff, doubling with
+    /// each further failure up to 6h, has passed. Failures are counted in
+    /// each feed's cache directory and reset by a successful check
+    #[arg(
+        long = "error-backoff",
+        env = "RSS_CHECKER_ERROR_BACKOFF",
+        default_value = "off",
+        action = ArgAction::Set,
+        value_parser = BoolishValueParser::new()
+    )]
+    error_backoff: bool,
+
+    /// also report links that have disappeared from a feed since it was last
+    /// cached, distinguishing new links with a `+` prefix and removed links
+    /// with a `-` in plain output
+    #[arg(long = "report-removed", env = "RSS_CHECKER_REPORT_REMOVED")]
+    report_removed: bool,
+
+    /// also report links of previously seen items whose title, description
+    /// or content has changed, distinguished with a `~` prefix in plain
+    /// output
+    #[arg(long = "detect-updates", env = "RSS_CHECKER_DETECT_UPDATES")]
+    detect_updates: bool,
+
+    /// read only each feed's cached etag and last modified date ahead of
+    /// fetching it, reading its cached items only once it responds that it
+    /// has changed, so runs where nothing changed do next to no parsing
+    #[arg(long = "check-only-changed", env = "RSS_CHECKER_CHECK_ONLY_CHANGED")]
+    check_only_changed: bool,
+
+    /// the maximum number of feeds fetched concurrently, defaulting to the
+    /// number of CPUs
+    #[arg(long = "max-concurrency", env = "RSS_CHECKER_MAX_CONCURRENCY")]
+    max_concurrency: Option<NonZeroUsize>,
+
+    /// report every link currently in each feed, neither reading nor writing
+    /// the cache. Unlike --dry-run, links are not diffed against the cache
+    #[arg(long = "no-cache", env = "RSS_CHECKER_NO_CACHE")]
+    no_cache: bool,
+
+    /// skip locking the cache directory, allowing runs sharing a cache path
+    /// to overlap. Overlapping runs may corrupt the cache or report a link
+    /// twice
+    #[arg(long = "no-lock", env = "RSS_CHECKER_NO_LOCK")]
+    no_lock: bool,
+
+    /// report every link currently in a feed checked for the first time,
+    /// rather than only seeding its cache
+    #[arg(
+        long = "report-on-first-seed",
+        env = "RSS_CHECKER_REPORT_ON_FIRST_SEED"
+    )]
+    report_on_first_seed: bool,
+
+    /// report new links without writing any changes to the cache
+    #[arg(long = "dry-run", env = "RSS_CHECKER_DRY_RUN")]
+    dry_run: bool,
+
+    /// print a summary of the run, such as the number of feeds checked and new
+    /// links found, to stderr
+    #[arg(long = "summary", env = "RSS_CHECKER_SUMMARY")]
+    summary: bool,
+
+    /// keep running, re-checking every feed each --interval and printing only
+    /// the links discovered since the previous check. Exits between checks
+    /// on SIGINT or SIGTERM
+    #[arg(long = "watch", env = "RSS_CHECKER_WATCH")]
+    watch: bool,
+
+    /// the time between the start of each check in watch mode, as a duration
+    /// such as `5m`. A bare number is taken as seconds
+    #[arg(
+        long = "interval",
+        env = "RSS_CHECKER_INTERVAL",
+        default_value = "5m",
+        value_parser = parse_duration,
+        requires = "watch"
+    )]
+    interval: Duration,
+
+    /// exit with a distinct status code when any new links are found
+    #[arg(long = "signal-new-via-exit", env = "RSS_CHECKER_SIGNAL_NEW_VIA_EXIT")]
+    signal_new_via_exit: bool,
+
+    /// exit with a failure status code if any feed could not be checked
+    #[arg(long = "fail-on-error", env = "RSS_CHECKER_FAIL_ON_ERROR")]
+    fail_on_error: bool,
+
+    /// a json lines file recording when each new link was first seen. Links
+    /// already recorded are never reported again
+    #[arg(long = "history-file", env = "RSS_CHECKER_HISTORY_FILE")]
+    history_file: Option<PathBuf>,
+
+    /// a prometheus textfile the results of each run are written to, for
+    /// scraping by the node exporter's textfile collector
+    #[arg(long = "metrics-file", env = "RSS_CHECKER_METRICS_FILE")]
+    metrics_file: Option<PathBuf>,
+
+    /// a file results are written to in place of stdout, truncating it with
+    /// each run unless --output-append is set
+    #[arg(long = "output-file", env = "RSS_CHECKER_OUTPUT_FILE")]
+    output_file: Option<PathBuf>,
+
+    /// append each run's results to --output-file rather than truncating it
+    #[arg(
+        long = "output-append",
+        env = "RSS_CHECKER_OUTPUT_APPEND",
+        requires = "output_file"
+    )]
+    output_append: bool,
+
+    /// the target new links are delivered to. Defaults to the webhook when a
+    /// webhook url is set, otherwise stdout, or --output-file when set. Links
+    /// are still written to the output if any other target fails
+    #[arg(long = "notify", env = "RSS_CHECKER_NOTIFY")]
+    notify: Option<NotifyTarget>,
+
+    /// the file new links are appended to by the file target
+    #[arg(
+        long = "notify-file",
+        env = "RSS_CHECKER_NOTIFY_FILE",
+        required_if_eq("notify", "file")
+    )]
+    notify_file: Option<PathBuf>,
+
+    /// a webhook url new links are posted to
+    #[arg(
+        long = "webhook-url",
+        env = "RSS_CHECKER_WEBHOOK_URL",
+        required_if_eq("notify", "webhook")
+    )]
+    webhook_url: Option<reqwest::Url>,
+
+    /// the json payload format posted to the webhook
+    #[arg(
+        long = "webhook-format",
+        env = "RSS_CHECKER_WEBHOOK_FORMAT",
+        default_value = "generic",
+        requires = "webhook_url"
+    )]
+    webhook_format: WebhookFormat,
+
+    /// a shell command run for each new link, with {link}, {feed} and {title}
+    /// substituted by shell quoted values, such as `--exec 'yt-dlp {link}'`.
+    /// Only ever set by this flag, never an environment variable or config
+    /// file, and not run during a --dry-run
+    #[arg(long = "exec", value_name = "COMMAND")]
+    exec: Option<String>,
+
+    /// the maximum number of --exec commands run at once
+    #[arg(
+        long = "exec-concurrency",
+        env = "RSS_CHECKER_EXEC_CONCURRENCY",
+        default_value_t = NonZeroUsize::new(DEFAULT_EXEC_CONCURRENCY).unwrap(),
+        requires = "exec"
+    )]
+    exec_concurrency: NonZeroUsize,
+}
+
+/// Replaces an argument with its global config file value, unless it was
+/// explicitly set by a flag or environment variable.
+fn merge_arg<T>(matches: &ArgMatches, id: &str, arg: &mut T, value: Option<T>) {
+    let explicitly_set = matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    );
+
+    if let (false, Some(value)) = (explicitly_set, value) {
+        *arg = value;
+    }
+}
+
+impl Args {
+    /// Applies global config file defaults. Flags take precedence over
+    /// environment variables, which take precedence over the config file,
+    /// which takes precedence over built-in defaults.
+    fn merge_global_config(&mut self, matches: &ArgMatches, config: GlobalConfig) {
+        merge_arg(
+            matches,
+            "cache_path",
+            &mut self.cache_path,
+            config.cache_path,
+        );
+        merge_arg(
+            matches,
+            "cache_mode",
+            &mut self.cache_mode,
+            config.cache_mode,
+        );
+        merge_arg(
+            matches,
+            "cache_layout",
+            &mut self.cache_layout,
+            config.cache_layout,
+        );
+        merge_arg(
+            matches,
+            "request_timeout",
+            &mut self.request_timeout,
+            config.request_timeout,
+        );
+        merge_arg(
+            matches,
+            "connect_timeout",
+            &mut self.connect_timeout,
+            config.connect_timeout,
+        );
+        merge_arg(
+            matches,
+            "min_cache_age",
+            &mut self.min_cache_age,
+            config.min_cache_age,
+        );
+        merge_arg(
+            matches,
+            "respect_cache_control",
+            &mut self.respect_cache_control,
+            config.respect_cache_control,
+        );
+        merge_arg(matches, "dedup_key", &mut self.dedup_key, config.dedup_key);
+        merge_arg(
+            matches,
+            "link_source",
+            &mut self.link_source,
+            config.link_source,
+        );
+        merge_arg(
+            matches,
+            "user_agent",
+            &mut self.user_agent,
+            config.user_agent,
+        );
+        merge_arg(
+            matches,
+            "max_redirects",
+            &mut self.max_redirects,
+            config.max_redirects,
+        );
+        merge_arg(
+            matches,
+            "max_body_bytes",
+            &mut self.max_body_bytes,
+            config.max_body_bytes,
+        );
+        merge_arg(
+            matches,
+            "max_retries",
+            &mut self.max_retries,
+            config.max_retries,
+        );
+        merge_arg(
+            matches,
+            "retry_base_delay_ms",
+            &mut self.retry_base_delay_ms,
+            config.retry_base_delay_ms,
+        );
+        merge_arg(
+            matches,
+            "fetch_jitter_ms",
+            &mut self.fetch_jitter_ms,
+            config.fetch_jitter_ms,
+        );
+        merge_arg(
+            matches,
+            "per_host_rps",
+            &mut self.per_host_rps,
+            config.per_host_rps.map(Some),
+        );
+        merge_arg(
+            matches,
+            "output_format",
+            &mut self.output_format,
+            config.output_format,
+        );
+        merge_arg(
+            matches,
+            "group_by_feed",
+            &mut self.group_by_feed,
+            config.group_by_feed,
+        );
+        merge_arg(
+            matches,
+            "show_empty",
+            &mut self.show_empty,
+            config.show_empty,
+        );
+        merge_arg(matches, "sort_by", &mut self.sort_by, config.sort_by);
+        merge_arg(
+            matches,
+            "cross_feed_dedup",
+            &mut self.cross_feed_dedup,
+            config.cross_feed_dedup,
+        );
+        merge_arg(
+            matches,
+            "max_concurrency",
+            &mut self.max_concurrency,
+            config.max_concurrency.map(Some),
+        );
     }
 }
 
-trait FeedCacheWriteable {
-    fn write_cache(&self, feed_name: &str, feed: &RssOrAtomFeed) -> Result<(), Error>;
+/// An option's value in effect, alongside where it was set.
+#[derive(Debug, Serialize)]
+struct EffectiveOption {
+    value: toml::Value,
+    source: &'static str,
 }
 
-impl<F> FeedCacheWriteable for F
-where
-    F: Fn(&str, &RssOrAtomFeed) -> Result<(), Error>,
-{
-    fn write_cache(&self, feed_name: &str, feed: &RssOrAtomFeed) -> Result<(), Error> {
-        (self)(feed_name, feed)
+/// Masks the password of a url valued option, such as a proxy.
+fn redact_url_password(value: String) -> String {
+    match reqwest::Url::parse(&value) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("redacted"));
+            url.to_string()
+        }
+        _ => value,
     }
 }
 
-#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
-enum LogLevelArg {
-    Off,
-    Error,
-    Warn,
-    Info,
-    Debug,
-    Trace,
+/// Resolves every option set by any source to its value in effect, with the
+/// same precedence as [Args::merge_global_config]. `config_values` holds the
+/// options set by the global config file. Values are as given, before
+/// parsing.
+fn effective_options(
+    matches: &ArgMatches,
+    config_values: &toml::Table,
+) -> BTreeMap<String, EffectiveOption> {
+    let raw_value = |arg: &clap::Arg| {
+        let values: Vec<_> = matches
+            .get_raw(arg.get_id().as_str())?
+            .map(|value| redact_url_password(value.to_string_lossy().into_owned()))
+            .collect();
+
+        match (arg.get_action(), values.as_slice()) {
+            (ArgAction::Append, _) => Some(toml::Value::from(values)),
+            (_, [value]) => Some(toml::Value::from(value.as_str())),
+            _ => Some(toml::Value::from(values)),
+        }
+    };
+
+    Args::command()
+        .get_arguments()
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let (value, source) = match (matches.value_source(id), config_values.get(id)) {
+                (Some(ValueSource::CommandLine), _) => (raw_value(arg)?, "command line"),
+                (Some(ValueSource::EnvVariable), _) => (raw_value(arg)?, "environment"),
+                (_, Some(value)) => (value.clone(), "config file"),
+                (Some(_), None) => (raw_value(arg)?, "default"),
+                (None, None) => return None,
+            };
+
+            Some((id.to_string(), EffectiveOption { value, source }))
+        })
+        .collect()
 }
 
-impl From<LogLevelArg> for log::LevelFilter {
-    fn from(value: LogLevelArg) -> Self {
-        match value {
-            LogLevelArg::Off => Self::Off,
-            LogLevelArg::Error => Self::Error,
-            LogLevelArg::Warn => Self::Warn,
-            LogLevelArg::Info => Self::Info,
-            LogLevelArg::Debug => Self::Debug,
-            LogLevelArg::Trace => Self::Trace,
+/// Prints the value and source of every option in effect.
+fn print_config(
+    matches: &ArgMatches,
+    config_values: &toml::Table,
+    format: ConfigFormat,
+) -> ExitCode {
+    let options = effective_options(matches, config_values);
+
+    let printed = match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(&options).map_err(|err| err.to_string()),
+        ConfigFormat::Toml => options
+            .iter()
+            .map(|(id, option)| {
+                let line = toml::Table::from_iter([(id.clone(), option.value.clone())]);
+                toml::to_string(&line).map(|line| format!("# {}\n{}", option.source, line))
+            })
+            .collect::<Result<String, _>>()
+            .map(|lines| lines.trim_end().to_string())
+            .map_err(|err| err.to_string()),
+    };
+
+    match printed {
+        Ok(printed) => {
+            println!("{}", printed);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
 
-fn get_feed_with_blocking_http_request(feed_name: &str, url: &Url) -> Result<RssOrAtomFeed, Error> {
-    let resp = reqwest::blocking::get(url.as_str()).map_err(|err| {
-        Error::new(ErrorKind::ReqwestErr(err)).with_data(format!("feed[{}]", feed_name))
-    })?;
+/// Reads the feeds given by `--feeds-from` and `--feed`, if any, with `-`
+/// reading the list from stdin.
+fn read_feed_list(
+    feeds_from: Option<&Path>,
+    feeds: &[String],
+) -> Result<Option<BTreeMap<String, FeedUrl>>, Vec<Error>> {
+    if feeds_from.is_none() && feeds.is_empty() {
+        return Ok(None);
+    }
 
-    let contents = resp.text().map_err(|err| {
-        Error::new(ErrorKind::ReqwestErr(err)).with_data(format!("feed[{}]", feed_name))
+    let listed_feeds = match feeds_from {
+        Some(path) if path == Path::new("-") => io::read_to_string(io::stdin()),
+        Some(path) => std::fs::read_to_string(path),
+        None => Ok(String::new()),
+    }
+    .map_err(|err| {
+        let path = feeds_from.unwrap_or(Path::new("-"));
+        vec![Error::from(err).with_data(format!("feeds-from[{}]", path.display()))]
     })?;
 
-    let maybe_channel =
-        Channel::read_from(contents.as_bytes()).map_err(|err| Error::new(ErrorKind::RssErr(err)));
-    let maybe_feed = Feed::read_from(contents.as_bytes())
-        .map_err(|err| Error::new(ErrorKind::AtomErr(err.to_string())));
+    walker::parse_feed_list(listed_feeds.lines().chain(feeds.iter().map(String::as_str))).map(Some)
+}
+
+/// Validates the configuration directory, logging every error found.
+fn check_config(conf_dir_path: &Path) -> ExitCode {
+    let errors = walker::walk_conf_dir(conf_dir_path)
+        .err()
+        .unwrap_or_default();
+
+    for e in &errors {
+        log::error!("{}", e);
+    }
 
-    match (maybe_channel, maybe_feed) {
-        (Ok(_), Ok(_)) => unreachable!(),
-        (Ok(channel), Err(_)) => Ok(RssOrAtomFeed::Rss2(channel)),
-        (Err(_), Ok(feed)) => Ok(RssOrAtomFeed::Atom(feed)),
-        (Err(_), Err(_)) => Err(Error::new(ErrorKind::FeedIsNeitherAtomOrRss(
-            feed_name.to_string(),
-        ))),
+    if errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        log::error!("{} configuration errors found", errors.len());
+        ExitCode::FAILURE
     }
 }
 
-fn load_cached_feed_from_disk(cache_path: &Path) -> impl Fn(&str) -> Result<RssOrAtomFeed, Error> {
-    let cache_path = cache_path.to_owned();
-
-    move |feed_name: &str| {
-        let cache_file_path = cache_path.join(feed_name);
-        let cache_file = OpenOptions::new()
-            .read(true)
-            .open(&cache_file_path)
-            .map_err(|err| {
-                Error::new(ErrorKind::IoErr(err)).with_data(format!("feed[{}]", feed_name))
-            })?;
-
-        let channel_load_result = Channel::read_from(BufReader::new(cache_file))
-            .map_err(|err| Error::new(ErrorKind::RssErr(err)));
-
-        let cache_file = OpenOptions::new()
-            .read(true)
-            .open(&cache_file_path)
-            .map_err(|err| {
-                Error::new(ErrorKind::IoErr(err)).with_data(format!("feed[{}]", feed_name))
-            })?;
-        let feed_load_result = Feed::read_from(BufReader::new(cache_file))
-            .map_err(|err| Error::new(ErrorKind::AtomErr(err.to_string())));
-
-        match (channel_load_result, feed_load_result) {
-            (Ok(_), Ok(_)) => unreachable!(),
-            (Ok(channel), Err(_)) => Ok(RssOrAtomFeed::Rss2(channel)),
-            (Err(_), Ok(feed)) => Ok(RssOrAtomFeed::Atom(feed)),
-            (Err(_), Err(_)) => Err(Error::new(ErrorKind::InvalidCache(feed_name.to_string()))),
+/// Prints the health of a single feed, failing if it can't be fetched or
+/// parsed.
+fn validate_feed(http_client_options: &HttpClientOptions, url: &reqwest::Url) -> ExitCode {
+    match validate::validate_feed(http_client_options, url) {
+        Ok(health) => {
+            println!("{}", health);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
 
-fn cache_feed_to_disk(cache_path: &Path) -> impl Fn(&str, &RssOrAtomFeed) -> Result<(), Error> {
-    use std::fs::OpenOptions;
-
-    let cache_path = cache_path.to_owned();
-
-    move |feed_name: &str, feed: &RssOrAtomFeed| {
-        let cache_file_path = cache_path.join(feed_name);
-        let cache_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&cache_file_path)
-            .map_err(|err| {
-                Error::new(ErrorKind::IoErr(err)).with_data(format!("feed[{}]", feed_name))
-            })?;
-
-        log::debug!(
-            "writing cache for feed[{}] to {}",
-            feed_name,
-            cache_file_path.display()
-        );
+/// Prints the outcome of each self-test check, failing if any check failed.
+fn selftest(config: &CheckConfig, url: Option<&reqwest::Url>) -> ExitCode {
+    let checks = selftest::run_selftest(config, url);
+    for check in &checks {
+        println!("{}", check);
+    }
 
-        match feed {
-            RssOrAtomFeed::Rss2(channel) => channel
-                .write_to(cache_file)
-                .map(|_| ())
-                .map_err(|err| Error::new(ErrorKind::RssErr(err))),
-            RssOrAtomFeed::Atom(feed) => feed
-                .write_to(cache_file)
-                .map(|_| ())
-                .map_err(|err| Error::new(ErrorKind::AtomErr(err.to_string()))),
-        }
+    if checks.iter().all(SelfTestCheck::passed) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
 
-/// Handle the lookup of and caching of an individual feed.
-fn get_and_cache_new_items_from_feed<
-    R: FeedCacheReadable,
-    F: FeedGettable,
-    W: FeedCacheWriteable,
->(
-    feed_name: &str,
-    feed_url: &Url,
-    feed_cache_readable: R,
-    fetch_feed: F,
-    feed_writer: W,
-) -> Result<Vec<String>, Error> {
-    let maybe_cached_feed = feed_cache_readable.read_cache(feed_name);
+/// Writes every configured feed as an OPML document, failing without writing
+/// anything if any configuration file is invalid.
+fn export_opml(conf_dir_path: &Path, output: Option<&Path>) -> ExitCode {
+    let feeds = match walker::walk_conf_dir(conf_dir_path) {
+        Ok(feeds) => feeds,
+        Err(errors) => {
+            for e in &errors {
+                log::error!("{}", e);
+            }
+            return ExitCode::FAILURE;
+        }
+    };
 
-    match maybe_cached_feed {
-        // if the cache file exists, load it and return new feed urls
-        Ok(cached_feed) => {
-            log::debug!("cache file found for {}", feed_name);
+    // credentials are omitted, having been split from each url when parsed.
+    let outlines: Vec<_> = feeds
+        .into_values()
+        .map(|feed_url| OpmlOutline {
+            name: feed_url.name,
+            xml_url: feed_url.url.to_string(),
+        })
+        .collect();
 
-            let new_feed = fetch_feed.get_feed(feed_name, feed_url)?;
+    let res = match output {
+        Some(path) => {
+            std::fs::File::create(path).and_then(|file| opml::write_opml(file, &outlines))
+        }
+        None => opml::write_opml(io::stdout().lock(), &outlines),
+    };
 
-            let cached_item_links: HashSet<_> = cached_feed.get_links().into_iter().collect();
-            let new_item_links: HashSet<_> = new_feed.get_links().into_iter().collect();
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-            let new_links: Vec<_> = new_item_links
-                .difference(&cached_item_links)
-                .map(|link| link.to_string())
-                .collect();
+/// Removes stale cache files, printing the path of each.
+fn clean_cache(config: &CheckConfig, max_age: Option<Duration>) -> ExitCode {
+    match rss_checker::clean_cache(
+        &config.conf_path,
+        &config.cache_path,
+        config.cache_layout,
+        max_age,
+        config.dry_run,
+    ) {
+        Ok(removed) => {
+            for path in removed {
+                println!("{}", path.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-            feed_writer.write_cache(feed_name, &new_feed)?;
-            Ok(new_links)
+/// Set once SIGINT or SIGTERM is received, asking watch mode to exit before
+/// its next check.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Requests a shutdown on the first SIGINT or SIGTERM, restoring the default
+/// disposition so that a second signal terminates immediately.
+#[cfg(unix)]
+fn install_shutdown_handler() {
+    extern "C" fn request_shutdown(signal: libc::c_int) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+        // SAFETY: signal is async-signal-safe.
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
         }
+    }
 
-        // if the cache file doesn't exists, save the cache
-        Err(Error {
-            kind: ErrorKind::IoErr(err),
-            ..
-        }) if err.kind() == io::ErrorKind::NotFound => {
-            log::debug!("cache file not found for {}", feed_name);
+    let handler = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only performs async-signal-safe operations.
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_shutdown_handler() {}
 
-            let new_feed = fetch_feed.get_feed(feed_name, feed_url)?;
-            feed_writer.write_cache(feed_name, &new_feed)?;
+/// The longest a sleeping watch waits before checking for a shutdown.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
-            Ok(vec![])
+/// Sleeps until `deadline`, returning early with false if a shutdown is
+/// requested.
+fn sleep_until_or_shutdown(deadline: Instant) -> bool {
+    loop {
+        if SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+            return false;
         }
 
-        // any other Error should be bubbled up
-        Err(err) => Err(err),
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(SHUTDOWN_POLL_INTERVAL));
     }
 }
 
-/// A rss feed checker
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// the directory path to source configuration files
-    #[arg(long = "conf-path", env = "RSS_CHECKER_CONF_PATH")]
-    conf_path: PathBuf,
+/// Checks every feed each `interval` until a shutdown is requested. A failed
+/// check is logged and retried on the next interval, rather than exiting.
+fn watch(config: &CheckConfig, options: &RunOptions, interval: Duration) -> ExitCode {
+    install_shutdown_handler();
 
-    /// the directory path to store all cache files
-    #[arg(
-        long = "cache-path",
-        env = "RSS_CHECKER_CACHE_PATH",
-        default_value = ".rss_checker/cache"
-    )]
-    cache_path: PathBuf,
+    loop {
+        let check_started = Instant::now();
+        if check_once(config, options) == ExitCode::FAILURE {
+            log::warn!("check failed, retrying in {:?}", interval);
+        }
 
-    /// the directory path to store all cache files
-    #[arg(long = "log-level", env = "RUST_LOG", default_value = "error")]
-    log_level: Option<LogLevelArg>,
+        if !sleep_until_or_shutdown(check_started + interval) {
+            log::info!("shutdown requested, exiting");
+            return ExitCode::SUCCESS;
+        }
+    }
+}
+
+/// Checks all feeds on a tokio runtime.
+#[cfg(feature = "async")]
+fn run_checks(config: &CheckConfig) -> Result<FeedCheckResults, Error> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(rss_checker::nonblocking::check_feeds(config))
+}
 
-    #[arg(long = "color", env = "RSS_CHECKER_COLOR")]
-    color: bool,
+/// Checks all feeds on a pool of blocking threads.
+#[cfg(not(feature = "async"))]
+fn run_checks(config: &CheckConfig) -> Result<FeedCheckResults, Error> {
+    rss_checker::check_feeds(config)
 }
 
 fn main() -> ExitCode {
     use env_logger::{Builder, WriteStyle};
 
-    let args = Args::parse();
-    let conf_dir_path = args.conf_path;
-    let cache_dir_path = args.cache_path;
-    let maybe_log_level = args.log_level;
-    let colorized = args.color;
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = args.command.clone().unwrap_or(Command::Check);
+    // subcommands negate required arguments, leaving those that read feeds to
+    // require them here.
+    if command.reads_feeds()
+        && args.conf_path.is_none()
+        && args.feeds_from.is_none()
+        && args.feeds.is_empty()
+    {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--conf-path is required unless feeds are given by --feeds-from or --feed",
+            )
+            .exit();
+    }
+    // an explicit log level takes precedence over any verbosity flags.
+    let maybe_log_level = match matches.value_source("log_level") {
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable) => args.log_level,
+        _ => LogLevelArg::from_verbosity(args.verbose).or(args.log_level),
+    };
 
     let mut logger_builder = Builder::from_default_env();
     if let Some(log_level_arg) = maybe_log_level {
@@ -298,113 +1125,355 @@ fn main() -> ExitCode {
         logger_builder.filter_level(level);
     };
 
-    let write_style = if colorized {
-        WriteStyle::Always
-    } else {
-        WriteStyle::Never
+    // env_logger detects whether stderr is a terminal itself.
+    let write_style = match args.color {
+        ColorChoice::Auto => WriteStyle::Auto,
+        ColorChoice::Always => WriteStyle::Always,
+        ColorChoice::Never => WriteStyle::Never,
     };
     logger_builder.write_style(write_style);
+    if args.log_format == LogFormat::Json {
+        logger_builder.format(write_json_log_record);
+    }
 
     logger_builder.init();
+    // set after initializing, as RUST_LOG may otherwise enable some modules.
+    if args.quiet {
+        log::set_max_level(log::LevelFilter::Off);
+    }
 
-    // create the cache directory pathing
-    let maybe_cache_dir_metadata = std::fs::metadata(&cache_dir_path);
-    match maybe_cache_dir_metadata {
-        Ok(meta) if meta.is_dir() => (),
-        Ok(_) => {
-            log::error!(
-                "cache directory path exists and is not a directory: {:?}",
-                &cache_dir_path
-            );
+    // an explicitly provided config file must exist, unlike the default.
+    let global_config = match args.config_file.clone() {
+        Some(path) => GlobalConfig::from_file(&path, true),
+        None => global_config::default_config_file_path()
+            .map(|path| GlobalConfig::from_file(&path, false))
+            .unwrap_or_else(|| Ok(GlobalConfig::default())),
+    };
+    let config_values = match global_config {
+        Ok(global_config) => {
+            // unset options are omitted, leaving only those set by the file.
+            let config_values = toml::Table::try_from(&global_config).unwrap_or_default();
+            args.merge_global_config(&matches, global_config);
+            config_values
+        }
+        Err(e) => {
+            log::error!("{}", e);
             return ExitCode::FAILURE;
         }
+    };
 
-        // Attempt to create the directory if it doesn't exist.
-        Err(_) => {
-            log::debug!("creating cache directory at {:?}", &cache_dir_path);
-            if let Err(e) = std::fs::create_dir_all(&cache_dir_path) {
+    let report_removed = args.report_removed;
+    let detect_updates = args.detect_updates;
+    let no_lock = args.no_lock;
+    let watch_interval = args.watch.then_some(args.interval);
+    let http_client_options = HttpClientOptions {
+        request_timeout: args.request_timeout,
+        connect_timeout: args.connect_timeout,
+        user_agent: args.user_agent,
+        proxy: args.proxy,
+        max_redirects: args.max_redirects,
+        max_body_bytes: args.max_body_bytes,
+    };
+    let output_options = OutputOptions {
+        format: args.output_format,
+        group_by_feed: args.group_by_feed,
+        show_empty: args.show_empty,
+        sort_by: args.sort_by,
+        oldest_first: args.oldest_first,
+        color: args.color.should_colorize(io::stdout().is_terminal()),
+    };
+    let notify_target = args.notify.unwrap_or(match args.webhook_url {
+        Some(_) => NotifyTarget::Webhook,
+        None => NotifyTarget::Stdout,
+    });
+    // files are never colorized, as they're read back by other tools.
+    let file_notifier = |path, append| FileNotifier {
+        path,
+        append,
+        output_options: OutputOptions {
+            color: false,
+            ..output_options
+        },
+    };
+    // the output file takes the place of stdout, including as a fallback.
+    let output_notifier = || -> Box<dyn Notifier> {
+        match &args.output_file {
+            Some(path) => Box::new(file_notifier(path.clone(), args.output_append)),
+            None => Box::new(StdoutNotifier { output_options }),
+        }
+    };
+    let notifier: Box<dyn Notifier> = match (
+        notify_target,
+        args.webhook_url.clone(),
+        args.notify_file.clone(),
+    ) {
+        (NotifyTarget::Webhook, Some(url), _) => Box::new(WebhookNotifier {
+            http_client_options: http_client_options.clone(),
+            url,
+            format: args.webhook_format,
+        }),
+        (NotifyTarget::File, _, Some(path)) => Box::new(file_notifier(path, true)),
+        // clap requires the webhook url and notify file of their targets.
+        _ => output_notifier(),
+    };
+    let fallback_notifier = (notify_target != NotifyTarget::Stdout).then(output_notifier);
+    let run_options = RunOptions {
+        notifier,
+        fallback_notifier,
+        signal_new_via_exit: args.signal_new_via_exit,
+        fail_on_error: args.fail_on_error,
+        summary: args.summary && !args.quiet,
+        history_file: args.history_file,
+        metrics_file: args.metrics_file,
+        cross_feed_dedup: args.cross_feed_dedup,
+        report_removed,
+        detect_updates,
+        exec_hook: args.exec.map(|command| ExecHook {
+            command,
+            max_concurrency: args.exec_concurrency,
+        }),
+    };
+    let feeds = match read_feed_list(args.feeds_from.as_deref(), &args.feeds) {
+        Ok(feeds) => feeds,
+        Err(errors) => {
+            for e in errors {
                 log::error!("{}", e);
-                return ExitCode::FAILURE;
             }
+            return ExitCode::FAILURE;
         }
     };
+    let config = CheckConfig {
+        conf_path: args.conf_path.unwrap_or_default(),
+        feeds,
+        feed_name_filter: FeedNameFilter::new(&args.only, &args.exclude),
+        cache_path: args.cache_path,
+        cache_mode: args.cache_mode,
+        cache_layout: args.cache_layout,
+        http_client_options,
+        retry_policy: RetryPolicy {
+            max_retries: args.max_retries,
+            base_delay: Duration::from_millis(args.retry_base_delay_ms),
+        },
+        error_backoff: args.error_backoff.then(BackoffPolicy::default),
+        fetch_jitter: Duration::from_millis(args.fetch_jitter_ms),
+        per_host_rps: args.per_host_rps,
+        feed_check_options: FeedCheckOptions {
+            min_cache_age: args.min_cache_age,
+            respect_cache_control: args.respect_cache_control,
+            dedup_key: args.dedup_key,
+            link_source: args.link_source,
+            since: args.since,
+            skip_undated: args.skip_undated,
+            url_normalizer: UrlNormalizer {
+                strip_query_params: args.strip_query_params,
+                normalize_urls: args.normalize_urls,
+            },
+            report_removed,
+            detect_updates,
+            no_cache: args.no_cache,
+            report_on_first_seed: args.report_on_first_seed,
+            check_only_changed: args.check_only_changed,
+            max_items_per_feed: args.max_items_per_feed,
+            ..Default::default()
+        },
+        max_concurrency: args.max_concurrency,
+        dry_run: args.dry_run,
+    };
 
-    let feed_mappings = match walker::walk_conf_dir(&conf_dir_path) {
-        Ok(mappings) => mappings,
+    // held until exit, so that runs sharing any cache path never overlap.
+    let uses_cache = match &command {
+        Command::Check => !config.feed_check_options.no_cache,
+        Command::CleanCache { .. } => true,
+        Command::CheckConfig
+        | Command::ExportOpml { .. }
+        | Command::ValidateFeed { .. }
+        | Command::PrintConfig { .. }
+        | Command::Selftest { .. } => false,
+    };
+    let cache_paths = if uses_cache && !no_lock {
+        rss_checker::cache_paths(&config)
+    } else {
+        BTreeSet::new()
+    };
+    let _cache_locks = match cache_paths
+        .iter()
+        .map(|cache_path| CacheLock::acquire(cache_path))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(cache_locks) => cache_locks,
         Err(e) => {
-            log::error!("{}", e);
+            log::error!("{}, pass --no-lock to run anyway", e);
             return ExitCode::FAILURE;
         }
     };
 
-    let fetch_feeds: Vec<_> = feed_mappings
-        .par_iter()
-        .map(|(feed_name, feed_url)| {
-            (
-                feed_name,
-                get_and_cache_new_items_from_feed(
-                    feed_name,
-                    feed_url,
-                    load_cached_feed_from_disk(&cache_dir_path),
-                    get_feed_with_blocking_http_request,
-                    cache_feed_to_disk(&cache_dir_path),
-                ),
-            )
-        })
-        .collect();
-
-    let mut new_unique_links = BTreeSet::new();
-    for (feed_name, maybe_feed) in fetch_feeds {
-        match maybe_feed {
-            Ok(new_links) => new_unique_links.extend(new_links.into_iter()),
-            Err(e) => log::error!("[{}]: {}", feed_name, e),
-        }
+    match command {
+        Command::Check => (),
+        Command::CheckConfig => return check_config(&config.conf_path),
+        Command::ExportOpml { output } => return export_opml(&config.conf_path, output.as_deref()),
+        Command::CleanCache { max_age } => return clean_cache(&config, max_age),
+        Command::ValidateFeed { url } => return validate_feed(&config.http_client_options, &url),
+        Command::PrintConfig { format } => return print_config(&matches, &config_values, format),
+        Command::Selftest { selftest_url } => return selftest(&config, selftest_url.as_ref()),
     }
 
-    for new_link in new_unique_links {
-        println!("{}", new_link)
+    match watch_interval {
+        Some(interval) => watch(&config, &run_options, interval),
+        None => check_once(&config, &run_options),
     }
+}
 
-    ExitCode::SUCCESS
+/// Options controlling how the results of each check are reported.
+struct RunOptions {
+    notifier: Box<dyn Notifier>,
+    /// written to in place of the notifier should it fail.
+    fallback_notifier: Option<Box<dyn Notifier>>,
+    signal_new_via_exit: bool,
+    fail_on_error: bool,
+    summary: bool,
+    history_file: Option<PathBuf>,
+    metrics_file: Option<PathBuf>,
+    cross_feed_dedup: bool,
+    report_removed: bool,
+    detect_updates: bool,
+    /// run for each new link once it's been delivered.
+    exec_hook: Option<ExecHook>,
 }
 
-#[cfg(test)]
-mod tests {
-    use url::Url;
+/// Checks every feed once, reporting new links as configured.
+fn check_once(config: &CheckConfig, options: &RunOptions) -> ExitCode {
+    let fetch_started = Instant::now();
+    let fetch_feeds = match run_checks(config) {
+        Ok(fetch_feeds) => fetch_feeds,
+        Err(Error {
+            kind: ErrorKind::Multiple(errors),
+            ..
+        }) => {
+            for e in errors {
+                log::error!("{}", e);
+            }
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let fetch_duration = fetch_started.elapsed();
+    let feed_count = fetch_feeds.len();
+    let mut failed_feeds = vec![];
+    let mut new_links = NewItemsByFeed::new();
+    let mut updated_links = NewItemsByFeed::new();
+    let mut removed_links = NewItemsByFeed::new();
+    let (mut feeds_fetched, mut feeds_not_modified) = (0, 0);
+    // results are ordered by feed name, so errors are logged reproducibly.
+    for (feed_name, maybe_feed) in fetch_feeds {
+        match maybe_feed {
+            Ok(changes) => {
+                match changes.fetch_status {
+                    FetchStatus::Modified => feeds_fetched += 1,
+                    FetchStatus::NotModified => feeds_not_modified += 1,
+                    FetchStatus::Skipped => (),
+                }
+                updated_links.insert(
+                    feed_name.clone(),
+                    changes.updated_items.into_iter().collect(),
+                );
+                removed_links.insert(
+                    feed_name.clone(),
+                    changes.removed_items.into_iter().collect(),
+                );
+                new_links.insert(feed_name, changes.new_items.into_iter().collect());
+            }
+            Err(e) => {
+                log::error!(feed = feed_name.as_str(); "[{}]: {}", feed_name, e);
+                failed_feeds.push((feed_name, e));
+            }
+        }
+    }
 
-    use super::*;
+    if let Some(history_file) = &options.history_file {
+        if let Err(e) = history::filter_and_record_new_links(
+            history_file,
+            &mut new_links,
+            chrono::Utc::now(),
+            config.dry_run,
+        ) {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
 
-    /// Provides a rss 2.0 feed in xml format locally.
-    const MOCK_LOCAL_GOOD_FEED: &str = include_str!("../dev/nginx/www/feed.xml");
+    if options.cross_feed_dedup {
+        output::dedup_across_feeds(&mut new_links);
+    }
 
-    #[allow(unused)]
-    struct MockFeedGetter<'data> {
-        contents: &'data str,
+    let report = RunReport {
+        new_links: &new_links,
+        updated_links: options.detect_updates.then_some(&updated_links),
+        removed_links: options.report_removed.then_some(&removed_links),
+        failed_feeds: &failed_feeds,
+    };
+    let delivered = match (options.notifier.notify(&report), &options.fallback_notifier) {
+        (Err(e), Some(fallback_notifier)) => {
+            log::error!("{}, falling back to the output", e);
+            fallback_notifier.notify(&report)
+        }
+        (delivered, _) => delivered,
+    };
+    if let Err(e) = delivered {
+        log::error!("{}", e);
+        return ExitCode::FAILURE;
     }
 
-    impl<'data> MockFeedGetter<'data> {
-        fn new(contents: &'data str) -> Self {
-            Self { contents }
+    match &options.exec_hook {
+        Some(_) if config.dry_run => log::info!("dry run, skipping --exec for new links"),
+        Some(exec_hook) => {
+            if let Err(e) = exec_hook.run_for_new_links(&new_links) {
+                log::error!("{}", e);
+                return ExitCode::FAILURE;
+            }
         }
+        None => (),
     }
 
-    impl FeedGettable for MockFeedGetter<'_> {
-        fn get_feed(&self, _feed_name: &str, _url: &Url) -> Result<RssOrAtomFeed, Error> {
-            Channel::read_from(self.contents.as_bytes())
-                .map_err(|err| Error::new(ErrorKind::RssErr(err)))
-                .map(RssOrAtomFeed::Rss2)
+    if let Some(metrics_file) = &options.metrics_file {
+        if let Err(e) = rss_checker::metrics::write_metrics_file(
+            metrics_file,
+            &new_links,
+            &failed_feeds,
+            chrono::Utc::now(),
+        ) {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
         }
     }
 
-    #[test]
-    fn should_parse_valid_feed() {
-        let feed_url = Url::parse("http://example.com/feed.xml").unwrap();
-        let feed_name = "test";
-        let feed_getter = MockFeedGetter::new(MOCK_LOCAL_GOOD_FEED);
+    if options.summary {
+        let summary = RunSummary {
+            feeds_checked: feed_count,
+            feeds_failed: failed_feeds.len(),
+            feeds_fetched,
+            feeds_not_modified,
+            new_links: new_links.values().map(BTreeSet::len).sum(),
+            fetch_duration,
+        };
+        eprintln!("{}", summary);
+    }
+
+    if !failed_feeds.is_empty() {
+        log::error!("{} of {} feeds failed", failed_feeds.len(), feed_count);
 
-        let channel = feed_getter.get_feed(feed_name, &feed_url).unwrap();
-        let channel_items = channel.get_links();
+        if options.fail_on_error {
+            return ExitCode::FAILURE;
+        }
+    }
 
-        assert_eq!(channel_items.len(), 3);
+    let found_new_links = new_links.values().any(|links| !links.is_empty());
+    if options.signal_new_via_exit && found_new_links {
+        ExitCode::from(NEW_LINKS_FOUND_EXIT_CODE)
+    } else {
+        ExitCode::SUCCESS
     }
 }
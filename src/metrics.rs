@@ -0,0 +1,273 @@
+//! Writes the results of a run as a Prometheus textfile, as scraped by the
+//! node exporter's textfile collector. Counters, and the last success of
+//! feeds failing this run, are carried over from the previous file.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use crate::output::NewItemsByFeed;
+use crate::Error;
+
+const NEW_LINKS_METRIC: &str = "rss_checker_feed_new_links";
+const LAST_SUCCESS_METRIC: &str = "rss_checker_feed_last_success_timestamp";
+const ERRORS_METRIC: &str = "rss_checker_feed_errors_total";
+
+fn io_error_for_metrics(path: &Path, err: io::Error) -> Error {
+    Error::from(err).with_data(format!("metrics-file[{}]", path.display()))
+}
+
+/// Escapes a label value per the Prometheus text exposition format.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Parses a sample labelled only by its feed, such as
+/// `rss_checker_feed_errors_total{feed="blog"} 2`, into its metric name, feed
+/// and value.
+fn parse_feed_sample(line: &str) -> Option<(&str, String, f64)> {
+    let (metric, rest) = line.split_once("{feed=\"")?;
+
+    let mut feed = String::new();
+    let mut chars = rest.char_indices();
+    let value_start = loop {
+        match chars.next()? {
+            (_, '\\') => match chars.next()?.1 {
+                'n' => feed.push('\n'),
+                escaped => feed.push(escaped),
+            },
+            (idx, '"') => break idx + 1,
+            (_, c) => feed.push(c),
+        }
+    };
+
+    let value = rest[value_start..].strip_prefix('}')?.trim().parse().ok()?;
+    Some((metric, feed, value))
+}
+
+/// Metrics carried over from a previous run, keyed by feed.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct PreviousMetrics {
+    last_success: BTreeMap<String, f64>,
+    errors: BTreeMap<String, f64>,
+}
+
+impl PreviousMetrics {
+    fn parse(contents: &str) -> Self {
+        let mut previous = Self::default();
+
+        for (metric, feed, value) in contents.lines().filter_map(parse_feed_sample) {
+            match metric {
+                LAST_SUCCESS_METRIC => {
+                    previous.last_success.insert(feed, value);
+                }
+                ERRORS_METRIC => {
+                    previous.errors.insert(feed, value);
+                }
+                _ => (),
+            }
+        }
+
+        previous
+    }
+}
+
+/// Renders a single metric, with a sample for each feed.
+fn render_metric<W: Write>(
+    writer: &mut W,
+    metric: &str,
+    kind: &str,
+    help: &str,
+    samples: &BTreeMap<&str, f64>,
+) -> io::Result<()> {
+    writeln!(writer, "# HELP {} {}", metric, help)?;
+    writeln!(writer, "# TYPE {} {}", metric, kind)?;
+
+    for (feed, value) in samples {
+        writeln!(
+            writer,
+            "{}{{feed=\"{}\"}} {}",
+            metric,
+            escape_label_value(feed),
+            value
+        )?;
+    }
+
+    Ok(())
+}
+
+fn render_metrics<W: Write>(
+    writer: &mut W,
+    previous: &PreviousMetrics,
+    new_links: &NewItemsByFeed,
+    failed_feeds: &[(String, Error)],
+    now: DateTime<Utc>,
+) -> io::Result<()> {
+    let new_link_counts: BTreeMap<_, _> = new_links
+        .iter()
+        .map(|(feed, items)| (feed.as_str(), items.len() as f64))
+        .collect();
+
+    let mut last_success: BTreeMap<_, _> = failed_feeds
+        .iter()
+        .filter_map(|(feed, _)| {
+            let previous_success = previous.last_success.get(feed)?;
+            Some((feed.as_str(), *previous_success))
+        })
+        .collect();
+    last_success.extend(
+        new_links
+            .keys()
+            .map(|feed| (feed.as_str(), now.timestamp() as f64)),
+    );
+
+    let previous_errors = |feed: &str| previous.errors.get(feed).copied().unwrap_or(0.0);
+    let mut errors: BTreeMap<_, _> = new_links
+        .keys()
+        .map(|feed| (feed.as_str(), previous_errors(feed)))
+        .collect();
+    errors.extend(
+        failed_feeds
+            .iter()
+            .map(|(feed, _)| (feed.as_str(), previous_errors(feed) + 1.0)),
+    );
+
+    render_metric(
+        writer,
+        NEW_LINKS_METRIC,
+        "gauge",
+        "New links found in the feed by the last run.",
+        &new_link_counts,
+    )?;
+    render_metric(
+        writer,
+        LAST_SUCCESS_METRIC,
+        "gauge",
+        "Unix timestamp of the last successful check of the feed.",
+        &last_success,
+    )?;
+    render_metric(
+        writer,
+        ERRORS_METRIC,
+        "counter",
+        "Checks of the feed that failed.",
+        &errors,
+    )
+}
+
+/// Returns the path metrics are written to before being renamed into place.
+/// The textfile collector only reads files ending in `.prom`, so never reads
+/// a partially written file.
+fn temporary_metrics_file_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+/// Atomically replaces the metrics file at `path` with the results of a run,
+/// carrying over any metrics retained from its previous contents.
+pub fn write_metrics_file(
+    path: &Path,
+    new_links: &NewItemsByFeed,
+    failed_feeds: &[(String, Error)],
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let previous = match std::fs::read_to_string(path) {
+        Ok(contents) => PreviousMetrics::parse(&contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => PreviousMetrics::default(),
+        Err(err) => return Err(io_error_for_metrics(path, err)),
+    };
+
+    let mut contents = vec![];
+    render_metrics(&mut contents, &previous, new_links, failed_feeds, now)
+        .map_err(|err| io_error_for_metrics(path, err))?;
+
+    let temporary_path = temporary_metrics_file_path(path);
+    std::fs::write(&temporary_path, contents)
+        .and_then(|_| std::fs::rename(&temporary_path, path))
+        .map_err(|err| io_error_for_metrics(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{ErrorKind, FeedItem};
+
+    fn new_links() -> NewItemsByFeed {
+        NewItemsByFeed::from([(
+            "a \"blog\"".to_string(),
+            BTreeSet::from([FeedItem {
+                link: "http://example.com/1".to_string(),
+                title: None,
+                published: None,
+                categories: vec![],
+            }]),
+        )])
+    }
+
+    #[test]
+    fn should_render_metrics_for_successful_and_failed_feeds() {
+        let failed_feeds = [(
+            "b".to_string(),
+            Error::new(ErrorKind::Timeout("b".to_string())),
+        )];
+        let now = DateTime::parse_from_rfc3339("2004-10-26T14:06:44Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut out = vec![];
+        render_metrics(
+            &mut out,
+            &PreviousMetrics::default(),
+            &new_links(),
+            &failed_feeds,
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# HELP rss_checker_feed_new_links New links found in the feed by the last run.
+# TYPE rss_checker_feed_new_links gauge
+rss_checker_feed_new_links{feed=\"a \\\"blog\\\"\"} 1
+# HELP rss_checker_feed_last_success_timestamp Unix timestamp of the last successful check of the feed.
+# TYPE rss_checker_feed_last_success_timestamp gauge
+rss_checker_feed_last_success_timestamp{feed=\"a \\\"blog\\\"\"} 1098799604
+# HELP rss_checker_feed_errors_total Checks of the feed that failed.
+# TYPE rss_checker_feed_errors_total counter
+rss_checker_feed_errors_total{feed=\"a \\\"blog\\\"\"} 0
+rss_checker_feed_errors_total{feed=\"b\"} 1
+"
+        );
+    }
+
+    #[test]
+    fn should_carry_over_counters_and_last_success_between_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rss_checker.prom");
+        let failed_feeds = || {
+            vec![(
+                "a \"blog\"".to_string(),
+                Error::new(ErrorKind::Timeout("a".to_string())),
+            )]
+        };
+
+        write_metrics_file(&path, &new_links(), &[], Utc::now()).unwrap();
+        let succeeded = PreviousMetrics::parse(&std::fs::read_to_string(&path).unwrap());
+        for _ in 0..2 {
+            write_metrics_file(&path, &NewItemsByFeed::new(), &failed_feeds(), Utc::now()).unwrap();
+        }
+        let failed = PreviousMetrics::parse(&std::fs::read_to_string(&path).unwrap());
+
+        assert_eq!(failed.last_success, succeeded.last_success);
+        assert_eq!(failed.errors.get("a \"blog\""), Some(&2.0));
+        assert!(!temporary_metrics_file_path(&path).exists());
+    }
+}
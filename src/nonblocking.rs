@@ -0,0 +1,398 @@
+//! An asynchronous counterpart to [crate::check_feeds], fetching feeds
+//! concurrently on a tokio runtime rather than on a pool of blocking threads.
+//! Cache reads and writes remain synchronous and are offloaded to tokio's
+//! blocking pool.
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+
+use crate::ratelimit::HostRateLimiter;
+use crate::walker::FeedUrl;
+use crate::{
+    bearer_token_header, body_capacity, cache_writer_for_mode, cache_writer_with_dry_run,
+    declared_charset, declared_feed_format, decode_feed_body, diff_fetched_feed, ensure_cache_dir,
+    ensure_feed_cache_dirs, error_for_feed_candidates, feed_cache_path, feeds_to_check,
+    get_feed_by_scheme, is_feed_backing_off, is_http_url, is_retryable, load_cached_feed_from_disk,
+    lookup_cache, non_http_scheme_fetchers, parse_fetched_feed, random_jitter,
+    record_feed_check_outcome, reqwest_error_for_feed, response_content_type, response_metadata,
+    status_error_for_feed, truncate_new_items, CacheLookup, CacheMetadata, CachedFeed, CheckConfig,
+    Error, ErrorKind, FeedCacheReadable, FeedChanges, FeedCheckOptions, FeedCheckResults,
+    FeedGettable, FetchStatus, FetchedFeed, HttpClientOptions, RetryPolicy,
+};
+
+/// Builds the http client shared across all feed requests.
+fn build_http_client(options: &HttpClientOptions) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(options.request_timeout)
+        .connect_timeout(options.connect_timeout)
+        .user_agent(&options.user_agent)
+        .redirect(reqwest::redirect::Policy::limited(options.max_redirects));
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(proxy.clone());
+    }
+
+    builder.build()
+}
+
+fn task_error_for_feed(feed_name: &str, err: tokio::task::JoinError) -> Error {
+    Error::new(ErrorKind::TaskErr(err)).with_data(format!("feed[{}]", feed_name))
+}
+
+/// Streams a response body, failing once it exceeds `max_body_bytes` rather
+/// than buffering it in full. The body is kept as raw bytes, leaving its
+/// decoding to the feed parsers.
+async fn read_body_with_limit(
+    feed_name: &str,
+    mut resp: reqwest::Response,
+    max_body_bytes: u64,
+) -> Result<Vec<u8>, Error> {
+    let mut contents = Vec::with_capacity(body_capacity(resp.content_length(), max_body_bytes));
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|err| reqwest_error_for_feed(feed_name, err))?
+    {
+        contents.extend_from_slice(&chunk);
+
+        if contents.len() as u64 > max_body_bytes {
+            return Err(Error::new(ErrorKind::BodyTooLarge(feed_name.to_string())));
+        }
+    }
+
+    Ok(contents)
+}
+
+/// Fetches a feed from `url`, either its own url or one of its mirrors.
+async fn get_feed_with_http_request(
+    client: &reqwest::Client,
+    options: &HttpClientOptions,
+    feed_url: &FeedUrl,
+    url: &Url,
+    metadata: &CacheMetadata,
+) -> Result<FetchedFeed, Error> {
+    use reqwest::header::{AUTHORIZATION, IF_MODIFIED_SINCE, IF_NONE_MATCH, USER_AGENT};
+
+    let feed_name = &feed_url.name;
+
+    // only http is fetched asynchronously, every other scheme's fetcher
+    // blocks.
+    if !is_http_url(url) {
+        let (feed_name, url, metadata) = (feed_name.clone(), url.clone(), metadata.clone());
+        let options = options.clone();
+        return tokio::task::spawn_blocking(move || {
+            get_feed_by_scheme(non_http_scheme_fetchers(&options))
+                .get_feed(&feed_name, &url, &metadata)
+        })
+        .await
+        .map_err(|err| task_error_for_feed(&feed_url.name, err))?;
+    }
+
+    let mut req = client.get(url.as_str());
+    // a per-feed user agent takes precedence over the client default.
+    if let Some(user_agent) = &feed_url.user_agent {
+        req = req.header(USER_AGENT, user_agent);
+    }
+    if let Some(credentials) = &feed_url.credentials {
+        req = req.basic_auth(&credentials.username, credentials.password.as_ref());
+    }
+    if let Some(token_env) = &feed_url.token_env {
+        req = req.header(AUTHORIZATION, bearer_token_header(feed_name, token_env)?);
+    }
+    req = req.headers(feed_url.headers.clone());
+    if let Some(etag) = &metadata.etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &metadata.last_modified {
+        req = req.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let resp = req
+        .send()
+        .await
+        .map_err(|err| reqwest_error_for_feed(feed_name, err))?;
+    resp.error_for_status_ref()
+        .map_err(|err| status_error_for_feed(feed_name, err))?;
+
+    if resp.url() != url {
+        log::debug!("feed[{}] redirected to {}", feed_name, resp.url());
+    }
+
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(FetchedFeed::NotModified(response_metadata(resp.headers())));
+    }
+
+    let metadata = response_metadata(resp.headers());
+    let declared_format = declared_feed_format(resp.headers());
+    let content_type =
+        response_content_type(resp.headers()).map(|content_type| content_type.to_string());
+    let charset = declared_charset(resp.headers());
+    // relative links resolve against the feed's url after any redirects.
+    let resolved_url = resp.url().clone();
+
+    let contents = read_body_with_limit(feed_name, resp, options.max_body_bytes).await?;
+    let contents = decode_feed_body(&contents, charset);
+
+    parse_fetched_feed(
+        feed_name,
+        &contents,
+        declared_format,
+        content_type.as_deref(),
+    )
+    .map(|feed| {
+        let feed = feed.resolve_relative_links(&resolved_url);
+        FetchedFeed::Modified(CachedFeed::new(feed, metadata))
+    })
+}
+
+/// Fetches a feed from `url`, retrying retryable failures with an
+/// exponential backoff.
+async fn get_feed_with_retries(
+    client: &reqwest::Client,
+    options: &HttpClientOptions,
+    feed_url: &FeedUrl,
+    url: &Url,
+    metadata: &CacheMetadata,
+    policy: RetryPolicy,
+    rate_limiter: Option<&HostRateLimiter>,
+) -> Result<FetchedFeed, Error> {
+    let mut attempt = 0;
+
+    loop {
+        let delay = rate_limiter.map_or(Duration::ZERO, |limiter| limiter.reserve(url));
+        if !delay.is_zero() {
+            log::debug!("feed[{}]: rate limited for {:?}", feed_url.name, delay);
+            tokio::time::sleep(delay).await;
+        }
+
+        match get_feed_with_http_request(client, options, feed_url, url, metadata).await {
+            Err(err) if attempt < policy.max_retries && is_retryable(&err) => {
+                attempt += 1;
+
+                let delay = policy.delay_for_attempt(attempt);
+                log::warn!(
+                    "feed[{}]: {}, retrying in {:?} (attempt {} of {})",
+                    feed_url.name,
+                    err,
+                    delay,
+                    attempt,
+                    policy.max_retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Fetches a feed from its url, falling back to each of its mirrors in turn,
+/// so that the feed only fails once every candidate has.
+async fn get_feed_with_mirrors(
+    client: &reqwest::Client,
+    options: &HttpClientOptions,
+    feed_url: &FeedUrl,
+    metadata: &CacheMetadata,
+    policy: RetryPolicy,
+    rate_limiter: Option<&HostRateLimiter>,
+) -> Result<FetchedFeed, Error> {
+    let mut errors = vec![];
+
+    for candidate in std::iter::once(&feed_url.url).chain(&feed_url.mirrors) {
+        let fetched_feed = get_feed_with_retries(
+            client,
+            options,
+            feed_url,
+            candidate,
+            metadata,
+            policy,
+            rate_limiter,
+        )
+        .await;
+
+        match fetched_feed {
+            Ok(fetched_feed) => {
+                if !feed_url.mirrors.is_empty() {
+                    log::debug!("feed[{}] fetched from {}", feed_url.name, candidate);
+                }
+                return Ok(fetched_feed);
+            }
+            Err(err) => {
+                log::debug!("feed[{}]: {} from {}", feed_url.name, err, candidate);
+                errors.push(err);
+            }
+        }
+    }
+
+    Err(error_for_feed_candidates(errors))
+}
+
+/// Handle the lookup of and caching of an individual feed.
+async fn get_and_cache_new_items_from_feed(
+    client: &reqwest::Client,
+    feed_url: &FeedUrl,
+    config: &CheckConfig,
+    rate_limiter: Option<&HostRateLimiter>,
+) -> Result<FeedChanges, Error> {
+    let feed_name = feed_url.name.clone();
+    let options = FeedCheckOptions {
+        link_filter: feed_url.link_filter.clone(),
+        category_filter: feed_url.category_filter.clone(),
+        ..config.feed_check_options.clone()
+    };
+
+    let cache_lookup = if options.no_cache {
+        CacheLookup::Bypassed
+    } else {
+        let cache_path = feed_cache_path(config, feed_url).to_owned();
+        let cache_layout = config.cache_layout;
+        let cached_feed_name = feed_name.clone();
+        let check_only_changed = options.check_only_changed;
+        let maybe_cached_feed = tokio::task::spawn_blocking(move || {
+            let reader = load_cached_feed_from_disk(&cache_path, cache_layout);
+            if check_only_changed {
+                reader.read_cache_validators(&cached_feed_name)
+            } else {
+                reader.read_cache(&cached_feed_name)
+            }
+        })
+        .await
+        .map_err(|err| task_error_for_feed(&feed_url.name, err))?;
+
+        lookup_cache(&feed_name, maybe_cached_feed, &options)?
+    };
+    if let CacheLookup::Fresh = cache_lookup {
+        return Ok(FeedChanges::default());
+    }
+
+    let jitter = random_jitter(config.fetch_jitter);
+    if !jitter.is_zero() {
+        log::debug!("feed[{}]: delaying fetch by {:?}", feed_name, jitter);
+        tokio::time::sleep(jitter).await;
+    }
+
+    let fetched_feed = get_feed_with_mirrors(
+        client,
+        &config.http_client_options,
+        feed_url,
+        &cache_lookup.metadata(),
+        config.retry_policy,
+        rate_limiter,
+    )
+    .await?;
+    let fetch_status = fetched_feed.fetch_status();
+    let cache_lookup = match cache_lookup {
+        // only a changed feed is diffed, so only its cached items are read.
+        CacheLookup::Stale(_)
+            if options.check_only_changed && fetch_status == FetchStatus::Modified =>
+        {
+            let cache_path = feed_cache_path(config, feed_url).to_owned();
+            let cache_layout = config.cache_layout;
+            let cached_feed_name = feed_name.clone();
+            let cached_feed = tokio::task::spawn_blocking(move || {
+                load_cached_feed_from_disk(&cache_path, cache_layout).read_cache(&cached_feed_name)
+            })
+            .await
+            .map_err(|err| task_error_for_feed(&feed_url.name, err))??;
+
+            CacheLookup::Stale(cached_feed)
+        }
+        cache_lookup => cache_lookup,
+    };
+    let (mut changes, maybe_cache_update) =
+        diff_fetched_feed(&feed_name, cache_lookup, fetched_feed, &options);
+    changes.fetch_status = fetch_status;
+    truncate_new_items(
+        &feed_name,
+        &mut changes.new_items,
+        options.max_items_per_feed,
+    );
+
+    if let Some(cache_update) = maybe_cache_update {
+        let cache_path = feed_cache_path(config, feed_url).to_owned();
+        let cache_mode = config.cache_mode;
+        let cache_layout = config.cache_layout;
+        let dry_run = config.dry_run;
+        tokio::task::spawn_blocking(move || {
+            let feed_writer = cache_writer_with_dry_run(
+                cache_writer_for_mode(&cache_path, cache_mode, cache_layout),
+                dry_run,
+            );
+            cache_update
+                .apply(&feed_name, &feed_writer)
+                .map_err(|err| err.with_data("writing cache"))
+        })
+        .await
+        .map_err(|err| task_error_for_feed(&feed_url.name, err))??;
+    }
+
+    Ok(changes)
+}
+
+/// Checks every feed configured in `config.conf_path`, or given by
+/// `config.feeds`, for new links, caching each feed's latest contents in its
+/// own cache path, if overridden, or otherwise `config.cache_path`. At most
+/// `config.max_concurrency` feeds are fetched at once.
+pub async fn check_feeds(config: &CheckConfig) -> Result<FeedCheckResults, Error> {
+    if !config.feed_check_options.no_cache {
+        ensure_cache_dir(&config.cache_path)?;
+    }
+
+    let feed_mappings = feeds_to_check(config)?;
+    ensure_feed_cache_dirs(config, &feed_mappings)?;
+
+    // a single client is shared across all feed requests.
+    let client = &build_http_client(&config.http_client_options)?;
+
+    let max_concurrency = config
+        .max_concurrency
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map(NonZeroUsize::get)
+        .unwrap_or(1);
+
+    // shared by every feed, so that the limit applies across the whole run.
+    let rate_limiter = &config.per_host_rps.map(HostRateLimiter::new);
+
+    // feeds complete in any order, but are collected in order of name.
+    let fetch_feeds: FeedCheckResults = stream::iter(feed_mappings.into_values())
+        .map(|feed_url| async move {
+            if is_feed_backing_off(config, &feed_url) {
+                return (feed_url.name, Ok(FeedChanges::default()));
+            }
+
+            let res =
+                get_and_cache_new_items_from_feed(client, &feed_url, config, rate_limiter.as_ref())
+                    .await;
+            record_feed_check_outcome(config, &feed_url, &res);
+            (feed_url.name, res)
+        })
+        .buffer_unordered(max_concurrency)
+        .collect()
+        .await;
+
+    Ok(fetch_feeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{http_ok_response, serve_once, MOCK_LOCAL_GOOD_FEED};
+
+    #[tokio::test]
+    async fn should_check_all_configured_feeds() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (feed_url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        std::fs::write(conf_dir.path().join("test"), feed_url.as_str()).unwrap();
+
+        let results = check_feeds(&CheckConfig::new(conf_dir.path(), cache_dir.path()))
+            .await
+            .unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results.get("test"), Some(Ok(new_links)) if new_links.is_empty()));
+        assert!(crate::cache_file_path(cache_dir.path(), "test").exists());
+    }
+}
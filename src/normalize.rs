@@ -0,0 +1,112 @@
+//! Normalizes links so that superficially different urls for the same item,
+//! such as those differing only in tracking query parameters, match when
+//! detecting new items.
+
+use reqwest::Url;
+
+/// Configures how links are normalized prior to matching. Hosts are always
+/// lowercased and default ports stripped.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UrlNormalizer {
+    /// query parameters removed from every link. A trailing `*` matches any
+    /// parameter sharing its prefix, such as `utm_*`.
+    pub strip_query_params: Vec<String>,
+    /// treat http and https links as equivalent and drop trailing slashes.
+    pub normalize_urls: bool,
+}
+
+impl UrlNormalizer {
+    fn is_stripped_param(&self, param: &str) -> bool {
+        self.strip_query_params
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => param.starts_with(prefix),
+                None => param == pattern,
+            })
+    }
+
+    /// Returns the form of a link used for matching. Links that fail to parse
+    /// are returned unchanged.
+    pub fn normalize(&self, link: &str) -> String {
+        let Ok(mut url) = Url::parse(link) else {
+            return link.to_string();
+        };
+
+        if url.query().is_some() && !self.strip_query_params.is_empty() {
+            let retained_pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(param, _)| !self.is_stripped_param(param))
+                .map(|(param, value)| (param.into_owned(), value.into_owned()))
+                .collect();
+
+            if retained_pairs.is_empty() {
+                url.set_query(None);
+            } else {
+                url.query_pairs_mut().clear().extend_pairs(retained_pairs);
+            }
+        }
+
+        if self.normalize_urls {
+            if url.scheme() == "http" {
+                // only fails for schemes that can't be swapped, such as file.
+                let _ = url.set_scheme("https");
+            }
+
+            let trimmed_path = url.path().trim_end_matches('/').to_string();
+            if !trimmed_path.is_empty() {
+                url.set_path(&trimmed_path);
+            }
+        }
+
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_lowercase_hosts_and_strip_default_ports() {
+        let normalizer = UrlNormalizer::default();
+
+        assert_eq!(
+            normalizer.normalize("https://EXAMPLE.com:443/Post"),
+            "https://example.com/Post"
+        );
+    }
+
+    #[test]
+    fn should_strip_configured_query_params() {
+        let normalizer = UrlNormalizer {
+            strip_query_params: vec!["utm_*".to_string(), "ref".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            normalizer.normalize("https://example.com/post?id=1&utm_source=rss&ref=feed"),
+            "https://example.com/post?id=1"
+        );
+        assert_eq!(
+            normalizer.normalize("https://example.com/post?utm_medium=rss"),
+            "https://example.com/post"
+        );
+    }
+
+    #[test]
+    fn should_ignore_scheme_and_trailing_slashes_when_normalizing_urls() {
+        let normalizer = UrlNormalizer {
+            normalize_urls: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            normalizer.normalize("http://example.com/post/"),
+            "https://example.com/post"
+        );
+        assert_eq!(
+            normalizer.normalize("http://example.com/"),
+            "https://example.com/"
+        );
+    }
+}
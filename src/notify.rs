@@ -0,0 +1,182 @@
+//! Delivers the results of a run to a notification target, such as stdout, a
+//! webhook or a local file.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use reqwest::Url;
+
+use crate::output::{self, LinkChanges, NewItemsByFeed, OutputOptions};
+use crate::webhook::{self, WebhookFormat};
+use crate::{Error, HttpClientOptions};
+
+/// The target new links are delivered to.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyTarget {
+    /// written to stdout in the configured output format.
+    Stdout,
+    /// posted to a webhook as a single batched json request.
+    Webhook,
+    /// appended to a local file in the configured output format.
+    File,
+}
+
+/// The results of a run, as delivered to a [Notifier].
+#[derive(Debug, Clone, Copy)]
+pub struct RunReport<'a> {
+    pub new_links: &'a NewItemsByFeed,
+    /// previously seen items whose content changed, when detecting updates.
+    pub updated_links: Option<&'a NewItemsByFeed>,
+    /// items removed from each feed, when reporting removed items.
+    pub removed_links: Option<&'a NewItemsByFeed>,
+    pub failed_feeds: &'a [(String, Error)],
+}
+
+/// Delivers every new link found by a run in a single batch, so targets can
+/// group, sort or dedup links across feeds.
+pub trait Notifier {
+    fn notify(&self, report: &RunReport) -> Result<(), Error>;
+}
+
+impl<F> Notifier for F
+where
+    F: Fn(&RunReport) -> Result<(), Error>,
+{
+    fn notify(&self, report: &RunReport) -> Result<(), Error> {
+        (self)(report)
+    }
+}
+
+fn write_report<W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    report: &RunReport,
+) -> io::Result<()> {
+    output::write_new_links(
+        writer,
+        options,
+        LinkChanges {
+            new_links: report.new_links,
+            updated_links: report.updated_links,
+            removed_links: report.removed_links,
+        },
+        report.failed_feeds,
+    )
+}
+
+/// Writes new links to stdout.
+#[derive(Debug, Clone, Copy)]
+pub struct StdoutNotifier {
+    pub output_options: OutputOptions,
+}
+
+impl Notifier for StdoutNotifier {
+    fn notify(&self, report: &RunReport) -> Result<(), Error> {
+        write_report(&mut io::stdout().lock(), &self.output_options, report)
+            .map_err(|err| Error::from(err).with_data("stdout"))
+    }
+}
+
+/// Posts new links to a webhook. Nothing is sent when no feed has new links.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    pub http_client_options: HttpClientOptions,
+    pub url: Url,
+    pub format: WebhookFormat,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, report: &RunReport) -> Result<(), Error> {
+        webhook::post_new_links(
+            &self.http_client_options,
+            &self.url,
+            self.format,
+            report.new_links,
+        )
+    }
+}
+
+/// Writes new links to a file, creating it if it doesn't exist.
+#[derive(Debug, Clone)]
+pub struct FileNotifier {
+    pub path: PathBuf,
+    /// append each run to the file rather than truncating it.
+    pub append: bool,
+    pub output_options: OutputOptions,
+}
+
+fn io_error_for_notify_file(path: &Path, err: io::Error) -> Error {
+    Error::from(err).with_data(format!("notify-file[{}]", path.display()))
+}
+
+impl Notifier for FileNotifier {
+    fn notify(&self, report: &RunReport) -> Result<(), Error> {
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)
+            .map(io::BufWriter::new)
+            .map_err(|err| io_error_for_notify_file(&self.path, err))?;
+
+        write_report(&mut writer, &self.output_options, report)
+            .and_then(|_| writer.flush())
+            .map_err(|err| io_error_for_notify_file(&self.path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::FeedItem;
+
+    #[test]
+    fn should_append_or_truncate_each_run_to_the_notify_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |notifier: &FileNotifier, link: &str| {
+            let new_links = NewItemsByFeed::from([(
+                "a".to_string(),
+                BTreeSet::from([FeedItem {
+                    link: link.to_string(),
+                    title: None,
+                    published: None,
+                    categories: vec![],
+                }]),
+            )]);
+
+            notifier
+                .notify(&RunReport {
+                    new_links: &new_links,
+                    updated_links: None,
+                    removed_links: None,
+                    failed_feeds: &[],
+                })
+                .unwrap();
+        };
+
+        for append in [true, false] {
+            let notifier = FileNotifier {
+                path: dir.path().join(format!("links-{}.txt", append)),
+                append,
+                output_options: OutputOptions::default(),
+            };
+
+            run(&notifier, "http://example.com/1");
+            run(&notifier, "http://example.com/2");
+
+            assert_eq!(
+                std::fs::read_to_string(&notifier.path).unwrap(),
+                if append {
+                    "http://example.com/1\nhttp://example.com/2\n"
+                } else {
+                    "http://example.com/2\n"
+                }
+            );
+        }
+    }
+}
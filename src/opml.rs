@@ -0,0 +1,231 @@
+//! Reads and writes feeds as OPML documents, as exported by most feed
+//! readers.
+
+use std::io::{self, Write};
+
+use quick_xml::encoding::Decoder;
+use quick_xml::events::{BytesDecl, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+
+use crate::{Error, ErrorKind};
+
+/// The file extension signifying feeds are configured via an OPML document.
+pub const OPML_EXTENSION: &str = "opml";
+
+/// Joins the names of the categories an outline is nested within to its own.
+const CATEGORY_NAME_SEPARATOR: &str = "-";
+
+/// A feed outline read from an OPML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpmlOutline {
+    /// the outline's name, prefixed by the names of its enclosing categories.
+    pub name: String,
+    pub xml_url: String,
+}
+
+fn opml_error<E: std::fmt::Display>(err: E) -> Error {
+    Error::new(ErrorKind::InvalidOpml(err.to_string()))
+}
+
+fn outline_attribute(
+    decoder: Decoder,
+    outline: &BytesStart,
+    attribute: &str,
+) -> Result<Option<String>, Error> {
+    outline
+        .try_get_attribute(attribute)
+        .map_err(opml_error)?
+        .map(|attribute| {
+            attribute
+                .decode_and_unescape_value(decoder)
+                .map(|value| value.trim().to_string())
+        })
+        .transpose()
+        .map_err(opml_error)
+        .map(|value| value.filter(|value| !value.is_empty()))
+}
+
+/// Replaces anything in a component of an outline's name that could address
+/// a path, such as the `/` of a title like "News / World", so that exported
+/// titles remain valid feed names.
+fn sanitize_name_component(component: &str) -> String {
+    component
+        .replace("..", CATEGORY_NAME_SEPARATOR)
+        .replace(['/', '\\', '\0'], CATEGORY_NAME_SEPARATOR)
+}
+
+/// Returns an outline's name, preferring its `text` attribute over its
+/// `title`.
+fn outline_name(decoder: Decoder, outline: &BytesStart) -> Result<Option<String>, Error> {
+    match outline_attribute(decoder, outline, "text")? {
+        Some(text) => Ok(Some(text)),
+        None => outline_attribute(decoder, outline, "title"),
+    }
+}
+
+/// Parses every feed outline, those with an `xmlUrl` attribute, from an OPML
+/// document. Outlines nested within category outlines are named with the
+/// category's name as a prefix.
+pub fn read_opml(contents: &str) -> Result<Vec<OpmlOutline>, Error> {
+    let mut reader = Reader::from_str(contents);
+    let mut categories: Vec<Option<String>> = vec![];
+    let mut outlines = vec![];
+
+    loop {
+        let (outline, is_empty) = match reader.read_event().map_err(opml_error)? {
+            Event::Start(element) if element.name().as_ref() == b"outline" => (element, false),
+            Event::Empty(element) if element.name().as_ref() == b"outline" => (element, true),
+            Event::End(element) if element.name().as_ref() == b"outline" => {
+                categories.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let decoder = reader.decoder();
+        let name = outline_name(decoder, &outline)?;
+        if let Some(xml_url) = outline_attribute(decoder, &outline, "xmlUrl")? {
+            let name = name.clone().ok_or_else(|| {
+                opml_error(format!(
+                    "outline for {} has neither text nor title",
+                    xml_url
+                ))
+            })?;
+            let prefixed_name = categories
+                .iter()
+                .flatten()
+                .chain(std::iter::once(&name))
+                .map(|component| sanitize_name_component(component))
+                .collect::<Vec<_>>()
+                .join(CATEGORY_NAME_SEPARATOR);
+
+            outlines.push(OpmlOutline {
+                name: prefixed_name,
+                xml_url,
+            });
+        }
+
+        if !is_empty {
+            categories.push(name);
+        }
+    }
+
+    Ok(outlines)
+}
+
+/// Writes every outline as a `rss` typed outline of an OPML 2.0 document.
+pub fn write_opml<W: Write>(writer: W, outlines: &[OpmlOutline]) -> io::Result<()> {
+    let mut writer = Writer::new_with_indent(writer, b' ', 2);
+
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer
+        .create_element("opml")
+        .with_attribute(("version", "2.0"))
+        .write_inner_content(|writer| {
+            writer
+                .create_element("head")
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("title")
+                        .write_text_content(BytesText::new("rss_checker feeds"))
+                        .map(|_| ())
+                })?;
+            writer
+                .create_element("body")
+                .write_inner_content(|writer| {
+                    for outline in outlines {
+                        writer
+                            .create_element("outline")
+                            .with_attributes([
+                                ("text", outline.name.as_str()),
+                                ("type", "rss"),
+                                ("xmlUrl", outline.xml_url.as_str()),
+                            ])
+                            .write_empty()?;
+                    }
+
+                    Ok(())
+                })
+                .map(|_| ())
+        })?;
+
+    writeln!(writer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_feed_outlines_flattening_categories() {
+        let opml = r#"<?xml version="1.0"?>
+<opml version="2.0">
+  <head><title>subscriptions</title></head>
+  <body>
+    <outline text="news">
+      <outline text="world" type="rss" xmlUrl="http://example.com/world.xml"/>
+      <outline text="tech">
+        <outline title="rust" type="rss" xmlUrl="http://example.com/rust.xml"/>
+      </outline>
+    </outline>
+    <outline text="blog &amp; more" type="rss" xmlUrl="http://example.com/blog.xml"></outline>
+    <outline text="Ars Technica / All" type="rss" xmlUrl="http://example.com/ars.xml"/>
+    <outline text="Wait... what" type="rss" xmlUrl="http://example.com/wait.xml"/>
+  </body>
+</opml>"#;
+
+        assert_eq!(
+            read_opml(opml).unwrap(),
+            vec![
+                OpmlOutline {
+                    name: "news-world".to_string(),
+                    xml_url: "http://example.com/world.xml".to_string(),
+                },
+                OpmlOutline {
+                    name: "news-tech-rust".to_string(),
+                    xml_url: "http://example.com/rust.xml".to_string(),
+                },
+                OpmlOutline {
+                    name: "blog & more".to_string(),
+                    xml_url: "http://example.com/blog.xml".to_string(),
+                },
+                OpmlOutline {
+                    name: "Ars Technica - All".to_string(),
+                    xml_url: "http://example.com/ars.xml".to_string(),
+                },
+                OpmlOutline {
+                    name: "Wait-. what".to_string(),
+                    xml_url: "http://example.com/wait.xml".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_unnamed_feed_outlines() {
+        let opml = r#"<opml version="2.0"><body><outline xmlUrl="http://example.com/a.xml"/></body></opml>"#;
+
+        assert!(matches!(
+            read_opml(opml),
+            Err(Error {
+                kind: ErrorKind::InvalidOpml(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn should_round_trip_outlines_through_opml() {
+        let opml = r#"<opml version="2.0"><body><outline text="news"><outline text="a &lt;b&gt;" xmlUrl="http://example.com/a.xml?x=1&amp;y=2"/></outline><outline text="c" xmlUrl="http://example.com/c.xml"/></body></opml>"#;
+        let outlines = read_opml(opml).unwrap();
+
+        let mut exported = Vec::new();
+        write_opml(&mut exported, &outlines).unwrap();
+
+        assert_eq!(
+            read_opml(std::str::from_utf8(&exported).unwrap()).unwrap(),
+            outlines
+        );
+    }
+}
@@ -0,0 +1,844 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, FeedItem};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// one link per line.
+    Plain,
+    /// a json object listing each link, and its item's title and publication
+    /// date, with its feed, alongside an error for each feed that failed.
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortBy {
+    /// lexicographically by link.
+    Link,
+    /// by each item's publication date, newest first. Undated items follow
+    /// all dated items.
+    Date,
+}
+
+/// When plain output is colorized.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// only when writing to a terminal.
+    #[default]
+    Auto,
+    /// even when writing to a file or pipe.
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parses a color choice by name, or as a boolean where `true` or `1` is
+    /// taken as `always` and `false` or `0` as `never`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Self::Always),
+            "false" | "0" => Ok(Self::Never),
+            value => Self::from_str(value, true).map_err(|_| {
+                format!(
+                    "expected one of auto, always, never, true, false, 1 or 0: {}",
+                    value
+                )
+            }),
+        }
+    }
+
+    /// Returns whether output written to a terminal, or not, is colorized.
+    pub fn should_colorize(self, is_terminal: bool) -> bool {
+        match self {
+            Self::Auto => is_terminal,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// The ANSI escape styling feed names in colorized output.
+const FEED_NAME_STYLE: &str = "\x1b[1;34m";
+/// The ANSI escape styling links in colorized output.
+const LINK_STYLE: &str = "\x1b[32m";
+const RESET_STYLE: &str = "\x1b[0m";
+
+/// Text written wrapped in an ANSI style, when colorizing.
+struct Styled<'a> {
+    style: Option<&'static str>,
+    text: &'a str,
+}
+
+impl fmt::Display for Styled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.style {
+            Some(style) => write!(f, "{}{}{}", style, self.text, RESET_STYLE),
+            None => f.write_str(self.text),
+        }
+    }
+}
+
+/// Configures how new links are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    /// group links under the feed they were discovered in.
+    pub group_by_feed: bool,
+    /// include feeds without new links when grouping by feed.
+    pub show_empty: bool,
+    pub sort_by: SortBy,
+    /// reverse date ordering to oldest first.
+    pub oldest_first: bool,
+    /// colorize feed names and links in plain output with ANSI escapes.
+    pub color: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Plain,
+            group_by_feed: false,
+            show_empty: false,
+            sort_by: SortBy::Link,
+            oldest_first: false,
+            color: false,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Orders two items per the configured sort, falling back to their links
+    /// so undated items retain a stable order.
+    fn compare(&self, a: &FeedItem, b: &FeedItem) -> Ordering {
+        let by_date = match (self.sort_by, &a.published, &b.published) {
+            (SortBy::Link, _, _) | (SortBy::Date, None, None) => Ordering::Equal,
+            (SortBy::Date, Some(_), None) => Ordering::Less,
+            (SortBy::Date, None, Some(_)) => Ordering::Greater,
+            (SortBy::Date, Some(a), Some(b)) if self.oldest_first => a.cmp(b),
+            (SortBy::Date, Some(a), Some(b)) => b.cmp(a),
+        };
+
+        by_date.then_with(|| a.link.cmp(&b.link))
+    }
+
+    /// Sorts items in place per the configured sort.
+    fn sort<'a, T, F>(&self, items: &mut [T], item_of: F)
+    where
+        F: Fn(&T) -> &'a FeedItem,
+    {
+        items.sort_by(|a, b| self.compare(item_of(a), item_of(b)));
+    }
+
+    fn styled<'a>(&self, style: &'static str, text: &'a str) -> Styled<'a> {
+        Styled {
+            style: self.color.then_some(style),
+            text,
+        }
+    }
+}
+
+/// New items for each successfully checked feed, keyed by feed name.
+pub type NewItemsByFeed = BTreeMap<String, BTreeSet<FeedItem>>;
+
+/// Drops any link already produced by an earlier feed, so each link is
+/// attributed only to the first feed, by name, that produced it.
+pub fn dedup_across_feeds(new_links: &mut NewItemsByFeed) {
+    let mut seen_links = HashSet::new();
+
+    for items in new_links.values_mut() {
+        items.retain(|item| seen_links.insert(item.link.clone()));
+    }
+}
+
+/// A newly discovered item and the feed it was discovered in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct NewItem<'a> {
+    feed: &'a str,
+    #[serde(flatten)]
+    item: &'a FeedItem,
+}
+
+/// All new items discovered in a single feed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct FeedNewItems<'a> {
+    feed: &'a str,
+    links: Vec<&'a FeedItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<Vec<&'a FeedItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    removed: Option<Vec<&'a FeedItem>>,
+}
+
+/// A feed that could not be checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct FeedError<'a> {
+    feed: &'a str,
+    /// the stable code of the error's kind.
+    kind: &'static str,
+    message: String,
+}
+
+impl<'a> FeedError<'a> {
+    fn new(feed: &'a str, err: &Error) -> Self {
+        Self {
+            feed,
+            kind: err.kind.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct JsonOutput<'a> {
+    links: Vec<NewItem<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<Vec<NewItem<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    removed: Option<Vec<NewItem<'a>>>,
+    errors: Vec<FeedError<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct JsonGroupedOutput<'a> {
+    feeds: Vec<FeedNewItems<'a>>,
+    errors: Vec<FeedError<'a>>,
+}
+
+/// New links alongside the updated and removed links, when reported.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkChanges<'a> {
+    pub new_links: &'a NewItemsByFeed,
+    pub updated_links: Option<&'a NewItemsByFeed>,
+    pub removed_links: Option<&'a NewItemsByFeed>,
+}
+
+impl<'a> LinkChanges<'a> {
+    /// Only new links, reporting neither updated nor removed links.
+    pub fn new(new_links: &'a NewItemsByFeed) -> Self {
+        Self {
+            new_links,
+            updated_links: None,
+            removed_links: None,
+        }
+    }
+}
+
+/// Writes all new items to a writer in the requested format. When reporting
+/// updated or removed items, plain output prefixes new links with `+`,
+/// updated links with `~` and removed links with `-`, while json output lists
+/// updated and removed links separately. Failed feeds are only included in
+/// json output.
+pub fn write_new_links<W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    changes: LinkChanges,
+    failed_feeds: &[(String, Error)],
+) -> io::Result<()> {
+    let errors = || {
+        failed_feeds
+            .iter()
+            .map(|(feed, err)| FeedError::new(feed, err))
+            .collect()
+    };
+
+    match (options.format, options.group_by_feed) {
+        (OutputFormat::Plain, false) => write_plain(writer, options, changes),
+        (OutputFormat::Plain, true) => write_plain_grouped(writer, options, changes),
+        (OutputFormat::Json, false) => write_json(writer, options, changes, errors()),
+        (OutputFormat::Json, true) => write_json_grouped(writer, options, changes, errors()),
+    }
+}
+
+/// The prefixes distinguishing new, updated and removed links in plain
+/// output, only written when reporting updated or removed links.
+struct PlainPrefixes {
+    new: &'static str,
+    updated: &'static str,
+    removed: &'static str,
+}
+
+impl PlainPrefixes {
+    fn new(changes: &LinkChanges) -> Self {
+        match (changes.updated_links, changes.removed_links) {
+            (None, None) => Self {
+                new: "",
+                updated: "",
+                removed: "",
+            },
+            _ => Self {
+                new: "+ ",
+                updated: "~ ",
+                removed: "- ",
+            },
+        }
+    }
+}
+
+/// A feed's new items alongside its updated and removed items, when reported.
+struct FeedItemsToShow<'a> {
+    feed: &'a String,
+    links: &'a BTreeSet<FeedItem>,
+    updated: Option<&'a BTreeSet<FeedItem>>,
+    removed: Option<&'a BTreeSet<FeedItem>>,
+}
+
+/// Yields each feed with its new, updated and removed items, omitting feeds
+/// without any unless `show_empty` is set.
+fn feeds_to_show<'a>(
+    show_empty: bool,
+    changes: LinkChanges<'a>,
+) -> impl Iterator<Item = FeedItemsToShow<'a>> {
+    const NO_ITEMS: &BTreeSet<FeedItem> = &BTreeSet::new();
+    let feed_items = move |links: Option<&'a NewItemsByFeed>, feed: &String| {
+        links.map(|links| links.get(feed).unwrap_or(NO_ITEMS))
+    };
+    let has_items =
+        |items: Option<&BTreeSet<FeedItem>>| items.is_some_and(|items| !items.is_empty());
+
+    changes
+        .new_links
+        .iter()
+        .map(move |(feed, links)| FeedItemsToShow {
+            feed,
+            links,
+            updated: feed_items(changes.updated_links, feed),
+            removed: feed_items(changes.removed_links, feed),
+        })
+        .filter(move |feed| {
+            show_empty
+                || !feed.links.is_empty()
+                || has_items(feed.updated)
+                || has_items(feed.removed)
+        })
+}
+
+/// Pairs every item with its feed, in the configured order.
+fn sorted_new_items<'a>(options: &OutputOptions, links: &'a NewItemsByFeed) -> Vec<NewItem<'a>> {
+    let mut new_items: Vec<_> = links
+        .iter()
+        .flat_map(|(feed, items)| items.iter().map(move |item| NewItem { feed, item }))
+        .collect();
+    // the sort is stable, so items sharing a link remain ordered by feed.
+    options.sort(&mut new_items, |new_item| new_item.item);
+    new_items
+}
+
+/// Returns a feed's items in the configured order.
+fn sorted_items<'a>(options: &OutputOptions, items: &'a BTreeSet<FeedItem>) -> Vec<&'a FeedItem> {
+    let mut items: Vec<_> = items.iter().collect();
+    options.sort(&mut items, |item| item);
+    items
+}
+
+fn write_plain<'a, W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    changes: LinkChanges<'a>,
+) -> io::Result<()> {
+    let prefixes = PlainPrefixes::new(&changes);
+    let optional_items = |links: Option<&'a NewItemsByFeed>| {
+        links
+            .map(|links| sorted_new_items(options, links))
+            .unwrap_or_default()
+    };
+
+    for (prefix, new_items) in [
+        (prefixes.new, sorted_new_items(options, changes.new_links)),
+        (prefixes.updated, optional_items(changes.updated_links)),
+        (prefixes.removed, optional_items(changes.removed_links)),
+    ] {
+        for new_item in new_items {
+            let link = options.styled(LINK_STYLE, &new_item.item.link);
+            writeln!(writer, "{}{}", prefix, link)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_plain_grouped<'a, W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    changes: LinkChanges<'a>,
+) -> io::Result<()> {
+    let prefixes = PlainPrefixes::new(&changes);
+    let optional_items = |items: Option<&'a BTreeSet<FeedItem>>| {
+        items
+            .map(|items| sorted_items(options, items))
+            .unwrap_or_default()
+    };
+
+    for feed in feeds_to_show(options.show_empty, changes) {
+        writeln!(writer, "{}", options.styled(FEED_NAME_STYLE, feed.feed))?;
+
+        for (prefix, items) in [
+            (prefixes.new, sorted_items(options, feed.links)),
+            (prefixes.updated, optional_items(feed.updated)),
+            (prefixes.removed, optional_items(feed.removed)),
+        ] {
+            for item in items {
+                let link = options.styled(LINK_STYLE, &item.link);
+                writeln!(writer, "  {}{}", prefix, link)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_json<'a, W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    changes: LinkChanges<'a>,
+    errors: Vec<FeedError<'a>>,
+) -> io::Result<()> {
+    let optional_items =
+        |links: Option<&'a NewItemsByFeed>| links.map(|links| sorted_new_items(options, links));
+    let output = JsonOutput {
+        links: sorted_new_items(options, changes.new_links),
+        updated: optional_items(changes.updated_links),
+        removed: optional_items(changes.removed_links),
+        errors,
+    };
+    serde_json::to_writer(&mut *writer, &output)?;
+    writeln!(writer)
+}
+
+fn write_json_grouped<'a, W: Write>(
+    writer: &mut W,
+    options: &OutputOptions,
+    changes: LinkChanges<'a>,
+    errors: Vec<FeedError<'a>>,
+) -> io::Result<()> {
+    let optional_items =
+        |items: Option<&'a BTreeSet<FeedItem>>| items.map(|items| sorted_items(options, items));
+    let feeds: Vec<_> = feeds_to_show(options.show_empty, changes)
+        .map(|feed| FeedNewItems {
+            feed: feed.feed,
+            links: sorted_items(options, feed.links),
+            updated: optional_items(feed.updated),
+            removed: optional_items(feed.removed),
+        })
+        .collect();
+
+    let output = JsonGroupedOutput { feeds, errors };
+    serde_json::to_writer(&mut *writer, &output)?;
+    writeln!(writer)
+}
+
+/// A summary of a single run, displayed as a single line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    pub feeds_checked: usize,
+    pub feeds_failed: usize,
+    /// feeds fetched in full, rather than being unchanged or skipped.
+    pub feeds_fetched: usize,
+    /// feeds responding that they're unchanged since they were cached.
+    pub feeds_not_modified: usize,
+    pub new_links: usize,
+    /// the wall-clock time spent checking every feed.
+    pub fetch_duration: Duration,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checked {} feeds in {:.2}s: {} succeeded, {} failed, {} new links; {} fetched, {} not modified",
+            self.feeds_checked,
+            self.fetch_duration.as_secs_f64(),
+            self.feeds_checked.saturating_sub(self.feeds_failed),
+            self.feeds_failed,
+            self.new_links,
+            self.feeds_fetched,
+            self.feeds_not_modified
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_links_by_feed(feeds: &[(&str, &[&str])]) -> NewItemsByFeed {
+        feeds
+            .iter()
+            .map(|(feed, links)| {
+                (
+                    feed.to_string(),
+                    links
+                        .iter()
+                        .map(|link| FeedItem {
+                            link: link.to_string(),
+                            title: None,
+                            published: None,
+                            categories: vec![],
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn published(date: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        chrono::DateTime::parse_from_rfc3339(date).ok()
+    }
+
+    fn dated_items(items: &[(&str, Option<&str>)]) -> BTreeSet<FeedItem> {
+        items
+            .iter()
+            .map(|(link, date)| FeedItem {
+                link: link.to_string(),
+                title: None,
+                published: date.and_then(published),
+                categories: vec![],
+            })
+            .collect()
+    }
+
+    fn render(options: OutputOptions, new_links: &NewItemsByFeed) -> String {
+        let mut out = Vec::new();
+        write_new_links(&mut out, &options, LinkChanges::new(new_links), &[]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn should_deduplicate_links_across_feeds_in_plain_format() {
+        let mut new_links = new_links_by_feed(&[
+            ("a", &["http://example.com/1", "http://example.com/2"]),
+            ("b", &["http://example.com/2"]),
+        ]);
+
+        assert_eq!(
+            render(OutputOptions::default(), &new_links),
+            "http://example.com/1\nhttp://example.com/2\nhttp://example.com/2\n"
+        );
+
+        dedup_across_feeds(&mut new_links);
+        assert_eq!(
+            render(OutputOptions::default(), &new_links),
+            "http://example.com/1\nhttp://example.com/2\n"
+        );
+    }
+
+    #[test]
+    fn should_attribute_deduplicated_links_to_first_feed() {
+        let mut new_links = new_links_by_feed(&[
+            ("a", &["http://example.com/1"]),
+            ("b", &["http://example.com/1", "http://example.com/2"]),
+        ]);
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        dedup_across_feeds(&mut new_links);
+
+        assert_eq!(
+            render(options, &new_links),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/1\"},{\"feed\":\"b\",\"link\":\"http://example.com/2\"}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_associate_links_with_feeds_in_json_format() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/1"]), ("b", &[])]);
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/1\"}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_include_item_title_and_publication_date_in_json_format() {
+        let new_links = NewItemsByFeed::from([(
+            "a".to_string(),
+            BTreeSet::from([FeedItem {
+                link: "http://example.com/1".to_string(),
+                title: Some("first".to_string()),
+                published: published("2004-10-26T14:06:44Z"),
+                categories: vec![],
+            }]),
+        )]);
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/1\",\"title\":\"first\",\"published\":\"2004-10-26T14:06:44Z\"}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_group_links_under_feed_omitting_empty_feeds() {
+        let new_links = new_links_by_feed(&[
+            ("a", &["http://example.com/1", "http://example.com/2"]),
+            ("b", &[]),
+            ("c", &["http://example.com/2"]),
+        ]);
+        let options = OutputOptions {
+            group_by_feed: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "a\n  http://example.com/1\n  http://example.com/2\nc\n  http://example.com/2\n"
+        );
+    }
+
+    #[test]
+    fn should_group_links_under_feed_including_empty_feeds() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/1"]), ("b", &[])]);
+        let options = OutputOptions {
+            group_by_feed: true,
+            show_empty: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "a\n  http://example.com/1\nb\n"
+        );
+    }
+
+    #[test]
+    fn should_group_links_under_feed_in_json_format() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/1"]), ("b", &[])]);
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            group_by_feed: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "{\"feeds\":[{\"feed\":\"a\",\"links\":[{\"link\":\"http://example.com/1\"}]}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_sort_links_by_date_with_undated_links_last() {
+        let new_links = NewItemsByFeed::from([
+            (
+                "a".to_string(),
+                dated_items(&[
+                    ("http://example.com/old", Some("2004-10-26T14:06:44Z")),
+                    ("http://example.com/undated-b", None),
+                ]),
+            ),
+            (
+                "b".to_string(),
+                dated_items(&[
+                    ("http://example.com/new", Some("2004-10-27T14:06:44+02:00")),
+                    ("http://example.com/undated-a", None),
+                ]),
+            ),
+        ]);
+        let newest_first = OutputOptions {
+            sort_by: SortBy::Date,
+            ..Default::default()
+        };
+        let oldest_first = OutputOptions {
+            oldest_first: true,
+            ..newest_first
+        };
+
+        assert_eq!(
+            render(newest_first, &new_links),
+            "http://example.com/new\nhttp://example.com/old\nhttp://example.com/undated-a\nhttp://example.com/undated-b\n"
+        );
+        assert_eq!(
+            render(oldest_first, &new_links),
+            "http://example.com/old\nhttp://example.com/new\nhttp://example.com/undated-a\nhttp://example.com/undated-b\n"
+        );
+    }
+
+    #[test]
+    fn should_sort_links_by_date_within_each_feed_when_grouping() {
+        let new_links = NewItemsByFeed::from([(
+            "a".to_string(),
+            dated_items(&[
+                ("http://example.com/1", Some("2004-10-26T14:06:44Z")),
+                ("http://example.com/2", Some("2004-10-27T14:06:44Z")),
+            ]),
+        )]);
+        let options = OutputOptions {
+            group_by_feed: true,
+            sort_by: SortBy::Date,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(options, &new_links),
+            "a\n  http://example.com/2\n  http://example.com/1\n"
+        );
+    }
+
+    #[test]
+    fn should_report_failed_feeds_in_json_format() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/1"])]);
+        let failed_feeds = [(
+            "b".to_string(),
+            Error::new(crate::ErrorKind::Timeout("b".to_string())),
+        )];
+        let options = OutputOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_new_links(
+            &mut out,
+            &options,
+            LinkChanges::new(&new_links),
+            &failed_feeds,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/1\"}],\"errors\":[{\"feed\":\"b\",\"kind\":\"timeout\",\"message\":\"feed b timed out\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn should_distinguish_new_and_removed_links() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/new"]), ("b", &[])]);
+        let removed_links = new_links_by_feed(&[("b", &["http://example.com/removed"])]);
+        let render_with_removed = |options: OutputOptions| {
+            let mut out = Vec::new();
+            let changes = LinkChanges {
+                removed_links: Some(&removed_links),
+                ..LinkChanges::new(&new_links)
+            };
+            write_new_links(&mut out, &options, changes, &[]).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            render_with_removed(OutputOptions::default()),
+            "+ http://example.com/new\n- http://example.com/removed\n"
+        );
+        assert_eq!(
+            render_with_removed(OutputOptions {
+                group_by_feed: true,
+                ..Default::default()
+            }),
+            "a\n  + http://example.com/new\nb\n  - http://example.com/removed\n"
+        );
+        assert_eq!(
+            render_with_removed(OutputOptions {
+                format: OutputFormat::Json,
+                ..Default::default()
+            }),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/new\"}],\"removed\":[{\"feed\":\"b\",\"link\":\"http://example.com/removed\"}],\"errors\":[]}\n"
+        );
+        assert_eq!(
+            render_with_removed(OutputOptions {
+                format: OutputFormat::Json,
+                group_by_feed: true,
+                ..Default::default()
+            }),
+            "{\"feeds\":[{\"feed\":\"a\",\"links\":[{\"link\":\"http://example.com/new\"}],\"removed\":[]},{\"feed\":\"b\",\"links\":[],\"removed\":[{\"link\":\"http://example.com/removed\"}]}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_distinguish_updated_links() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/new"])]);
+        let updated_links = new_links_by_feed(&[("a", &["http://example.com/edited"])]);
+        let render_with_updated = |options: OutputOptions| {
+            let mut out = Vec::new();
+            let changes = LinkChanges {
+                updated_links: Some(&updated_links),
+                ..LinkChanges::new(&new_links)
+            };
+            write_new_links(&mut out, &options, changes, &[]).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            render_with_updated(OutputOptions::default()),
+            "+ http://example.com/new\n~ http://example.com/edited\n"
+        );
+        assert_eq!(
+            render_with_updated(OutputOptions {
+                format: OutputFormat::Json,
+                ..Default::default()
+            }),
+            "{\"links\":[{\"feed\":\"a\",\"link\":\"http://example.com/new\"}],\"updated\":[{\"feed\":\"a\",\"link\":\"http://example.com/edited\"}],\"errors\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn should_only_colorize_plain_output_when_enabled() {
+        let new_links = new_links_by_feed(&[("a", &["http://example.com/1"])]);
+        let colorized = OutputOptions {
+            group_by_feed: true,
+            color: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(colorized, &new_links),
+            "\x1b[1;34ma\x1b[0m\n  \x1b[32mhttp://example.com/1\x1b[0m\n"
+        );
+        assert_eq!(
+            render(
+                OutputOptions {
+                    format: OutputFormat::Json,
+                    ..colorized
+                },
+                &new_links
+            ),
+            "{\"feeds\":[{\"feed\":\"a\",\"links\":[{\"link\":\"http://example.com/1\"}]}],\"errors\":[]}\n"
+        );
+        assert!(ColorChoice::Auto.should_colorize(true));
+        assert!(!ColorChoice::Auto.should_colorize(false));
+    }
+
+    #[test]
+    fn should_parse_color_choices_by_name_or_as_booleans() {
+        assert_eq!(ColorChoice::parse("auto"), Ok(ColorChoice::Auto));
+        assert_eq!(ColorChoice::parse("Always"), Ok(ColorChoice::Always));
+        assert_eq!(ColorChoice::parse("true"), Ok(ColorChoice::Always));
+        assert_eq!(ColorChoice::parse("1"), Ok(ColorChoice::Always));
+        assert_eq!(ColorChoice::parse("false"), Ok(ColorChoice::Never));
+        assert_eq!(ColorChoice::parse("0"), Ok(ColorChoice::Never));
+        assert!(ColorChoice::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn should_display_run_summary_on_a_single_line() {
+        let summary = RunSummary {
+            feeds_checked: 3,
+            feeds_failed: 1,
+            feeds_fetched: 1,
+            feeds_not_modified: 1,
+            new_links: 5,
+            fetch_duration: Duration::from_millis(1234),
+        };
+
+        assert_eq!(
+            summary.to_string(),
+            "checked 3 feeds in 1.23s: 2 succeeded, 1 failed, 5 new links; 1 fetched, 1 not modified"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Limits the rate of requests made to each host across a run, so that
+//! following many feeds from the same publisher doesn't flood its server.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+
+/// Spaces requests to the same host evenly, allowing at most a configured
+/// number of requests per second to each. Requests to different hosts are
+/// never delayed by one another.
+#[derive(Debug)]
+pub struct HostRateLimiter {
+    min_interval: Duration,
+    /// the earliest instant the next request to each host may be made.
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    pub fn new(requests_per_second: NonZeroU32) -> Self {
+        Self {
+            min_interval: Duration::from_secs(1) / requests_per_second.get(),
+            next_request: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserves the next available slot for a request to `url`'s host,
+    /// returning how long the caller must wait before making it. Urls without
+    /// a host, such as `file` urls, are never delayed.
+    pub fn reserve(&self, url: &Url) -> Duration {
+        let Some(host) = url.host_str() else {
+            return Duration::ZERO;
+        };
+
+        let now = Instant::now();
+        // a poisoned lock only means another fetch panicked mid-reservation,
+        // which leaves the map consistent.
+        let mut next_request = self
+            .next_request
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let slot = next_request.get(host).map_or(now, |&next| next.max(now));
+        next_request.insert(host.to_string(), slot + self.min_interval);
+
+        slot - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_only_space_requests_to_the_same_host() {
+        let limiter = HostRateLimiter::new(NonZeroU32::new(10).unwrap());
+        let a = Url::parse("http://a.example.com/feed.xml").unwrap();
+        let b = Url::parse("http://b.example.com/feed.xml").unwrap();
+
+        assert_eq!(limiter.reserve(&a), Duration::ZERO);
+        assert_eq!(limiter.reserve(&b), Duration::ZERO);
+        assert!(limiter.reserve(&a) > Duration::from_millis(90));
+        assert!(limiter.reserve(&a) > Duration::from_millis(190));
+        assert_eq!(
+            limiter.reserve(&Url::parse("file:///feed.xml").unwrap()),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! A pre-flight diagnostic of a deployment, checking that its feeds are
+//! configured, its cache is writable and feeds can be fetched, such as for a
+//! container readiness probe.
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use reqwest::Url;
+
+use crate::validate::validate_feed;
+use crate::{cache_paths, ensure_cache_dir, feeds_to_check, CheckConfig, Error};
+
+/// The outcome of a single self-test check, describing what was verified or
+/// why it failed.
+#[derive(Debug)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub outcome: Result<String, Error>,
+}
+
+impl SelfTestCheck {
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+impl fmt::Display for SelfTestCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Ok(detail) => write!(f, "{}: ok, {}", self.name, detail),
+            Err(err) => write!(f, "{}: FAILED, {}", self.name, err),
+        }
+    }
+}
+
+/// Creates, writes and removes a probe file within a cache directory,
+/// creating the directory if it doesn't already exist.
+fn probe_cache_dir(cache_path: &Path) -> Result<(), Error> {
+    ensure_cache_dir(cache_path)?;
+
+    let probe_path = cache_path.join(format!(".selftest-{}", std::process::id()));
+    let io_error_for_cache = |err: std::io::Error| {
+        Error::from(err).with_data(format!("cache[{}]", cache_path.display()))
+    };
+    std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(&probe_path)
+        .and_then(|mut probe| probe.write_all(b"selftest"))
+        .map_err(io_error_for_cache)?;
+
+    std::fs::remove_file(&probe_path).map_err(io_error_for_cache)
+}
+
+/// Runs every check, so that all failures are reported together. Feeds are
+/// fetched from `url` or, without one, from the first configured feed
+/// fetched over http without credentials, as one requiring them would fail
+/// unauthenticated.
+pub fn run_selftest(config: &CheckConfig, url: Option<&Url>) -> Vec<SelfTestCheck> {
+    let feeds = feeds_to_check(config);
+
+    let cache_paths = cache_paths(config);
+    let cache_outcome = if config.feed_check_options.no_cache {
+        Ok("nothing written, as the cache is disabled".to_string())
+    } else {
+        cache_paths
+            .iter()
+            .try_for_each(|cache_path| probe_cache_dir(cache_path))
+            .map(|_| format!("{} cache directories are writable", cache_paths.len()))
+    };
+
+    let url = url.cloned().or_else(|| {
+        feeds.as_ref().ok()?.values().find_map(|feed_url| {
+            let unauthenticated = feed_url.credentials.is_none() && feed_url.token_env.is_none();
+            (unauthenticated && matches!(feed_url.url.scheme(), "http" | "https"))
+                .then(|| feed_url.url.clone())
+        })
+    });
+    let fetch_outcome = match url {
+        Some(url) => validate_feed(&config.http_client_options, &url)
+            .map(|health| format!("fetched {} with {} items", url, health.item_count)),
+        None => Ok("nothing fetched, as no http feed is configured".to_string()),
+    };
+
+    vec![
+        SelfTestCheck {
+            name: "config",
+            outcome: feeds.map(|feeds| format!("{} feeds configured", feeds.len())),
+        },
+        SelfTestCheck {
+            name: "cache",
+            outcome: cache_outcome,
+        },
+        SelfTestCheck {
+            name: "fetch",
+            outcome: fetch_outcome,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{http_ok_response, serve_once, MOCK_LOCAL_GOOD_FEED};
+    use crate::ErrorKind;
+
+    #[test]
+    fn should_pass_every_check_of_a_working_deployment() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (url, handle) = serve_once(http_ok_response(MOCK_LOCAL_GOOD_FEED));
+        std::fs::write(
+            conf_dir.path().join("blog.toml"),
+            format!("url = \"{}\"\n", url),
+        )
+        .unwrap();
+
+        let checks = run_selftest(
+            &CheckConfig::new(conf_dir.path(), cache_dir.path().join("cache")),
+            None,
+        );
+        handle.join().unwrap();
+
+        assert!(checks.iter().all(SelfTestCheck::passed), "{:?}", checks);
+        assert_eq!(checks[0].to_string(), "config: ok, 1 feeds configured");
+        assert!(checks[2].to_string().contains(url.as_str()));
+        // the probe file is removed once written.
+        assert_eq!(
+            std::fs::read_dir(cache_dir.path().join("cache"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn should_report_every_failing_check() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let cache_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(conf_dir.path().join("broken.toml"), "url = 1\n").unwrap();
+        let (url, handle) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+
+        let checks = run_selftest(
+            &CheckConfig::new(conf_dir.path(), cache_file.path()),
+            Some(&url),
+        );
+        handle.join().unwrap();
+
+        assert!(checks.iter().all(|check| !check.passed()), "{:?}", checks);
+        assert!(matches!(
+            &checks[1].outcome,
+            Err(Error {
+                kind: ErrorKind::IoErr(_),
+                ..
+            })
+        ));
+        assert!(checks[2].to_string().starts_with("fetch: FAILED"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Diagnoses a single feed, reporting how it parsed and why its items may
+//! yield no links, without reading or writing the cache.
+
+use std::fmt;
+
+use chrono::DateTime;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+
+use crate::{
+    atom_entry_feed_items, build_http_client, default_scheme_fetchers, get_feed_by_scheme,
+    get_feed_with_blocking_http_request, json_item_feed_items, rss_item_feed_items, CacheContents,
+    CacheMetadata, Error, ErrorKind, FeedFormat, FeedGettable, FetchedFeed, HttpClientOptions,
+    ItemsProduceable, RssOrAtomFeed,
+};
+
+/// What was learnt of a single item while diagnosing its feed.
+struct ItemDiagnosis {
+    has_link: bool,
+    has_id: bool,
+    /// the item declares a publication date that fails to parse.
+    has_invalid_date: bool,
+}
+
+fn diagnose_items(feed: &RssOrAtomFeed) -> Vec<ItemDiagnosis> {
+    match feed {
+        RssOrAtomFeed::Rss2(channel) => channel
+            .items()
+            .iter()
+            .map(|item| ItemDiagnosis {
+                has_link: !rss_item_feed_items(item).is_empty(),
+                has_id: item.guid().is_some(),
+                has_invalid_date: item
+                    .pub_date()
+                    .is_some_and(|published| DateTime::parse_from_rfc2822(published).is_err()),
+            })
+            .collect(),
+        // atom dates are validated while parsing, failing the whole feed.
+        RssOrAtomFeed::Atom(feed) => feed
+            .entries()
+            .iter()
+            .map(|entry| ItemDiagnosis {
+                has_link: !atom_entry_feed_items(entry).is_empty(),
+                has_id: !entry.id().is_empty(),
+                has_invalid_date: false,
+            })
+            .collect(),
+        RssOrAtomFeed::Json(feed) => feed
+            .items()
+            .iter()
+            .map(|item| ItemDiagnosis {
+                has_link: !json_item_feed_items(item).is_empty(),
+                has_id: !item.id.is_null(),
+                has_invalid_date: item
+                    .date_published()
+                    .is_some_and(|published| DateTime::parse_from_rfc3339(published).is_err()),
+            })
+            .collect(),
+    }
+}
+
+/// A diagnostic report of a single feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedHealth {
+    pub format: FeedFormat,
+    pub item_count: usize,
+    /// items yielding a valid, absolute, link.
+    pub linked_item_count: usize,
+    /// issues that may cause items to be missed or misreported.
+    pub warnings: Vec<String>,
+}
+
+impl From<&RssOrAtomFeed> for FeedHealth {
+    fn from(feed: &RssOrAtomFeed) -> Self {
+        let items = diagnose_items(feed);
+        let item_count = items.len();
+        let count = |predicate: fn(&ItemDiagnosis) -> bool| {
+            items.iter().filter(|item| predicate(item)).count()
+        };
+        let linked_item_count = count(|item| item.has_link);
+        let repeated_link_count = linked_item_count - feed.get_items().len();
+
+        let warnings = [
+            (
+                item_count - linked_item_count,
+                "have no valid link, so are never reported",
+            ),
+            (
+                repeated_link_count,
+                "repeat the link of an earlier item, so are never reported",
+            ),
+            (
+                count(|item| !item.has_id),
+                "have no guid or id, so are identified by their link",
+            ),
+            (
+                count(|item| item.has_invalid_date),
+                "have an unparseable publication date, so are treated as undated",
+            ),
+        ]
+        .into_iter()
+        .filter(|(affected, _)| *affected > 0)
+        .map(|(affected, issue)| format!("{} of {} items {}", affected, item_count, issue))
+        .chain((item_count == 0).then(|| "the feed has no items".to_string()))
+        .collect();
+
+        Self {
+            format: feed.format(),
+            item_count,
+            linked_item_count,
+            warnings,
+        }
+    }
+}
+
+impl fmt::Display for FeedHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "format: {}", self.format)?;
+        writeln!(f, "items: {}", self.item_count)?;
+        write!(f, "items with valid links: {}", self.linked_item_count)?;
+        for warning in &self.warnings {
+            write!(f, "\nwarning: {}", warning)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches the feed at `url`, unconditionally, and diagnoses it. Fails if
+/// the feed can't be fetched or parsed in any supported format.
+pub fn validate_feed(options: &HttpClientOptions, url: &Url) -> Result<FeedHealth, Error> {
+    let client = build_http_client(options)?;
+    let fetch_feed = get_feed_by_scheme(default_scheme_fetchers(
+        get_feed_with_blocking_http_request(
+            &client,
+            options.max_body_bytes,
+            None,
+            None,
+            None,
+            &HeaderMap::new(),
+        ),
+        options,
+    ));
+
+    match fetch_feed.get_feed(url.as_str(), url, &CacheMetadata::default())? {
+        FetchedFeed::Modified(fetched_feed) => match &fetched_feed.contents {
+            CacheContents::Feed(feed) => Ok(FeedHealth::from(feed)),
+            CacheContents::Keys(_) => unreachable!("fetched feeds are never reduced to keys"),
+        },
+        // only a misbehaving server responds so to an unconditional request.
+        FetchedFeed::NotModified(_) => Err(Error::new(ErrorKind::HttpStatus {
+            feed_name: url.to_string(),
+            status: reqwest::StatusCode::NOT_MODIFIED,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_report_the_health_of_a_fetched_feed() {
+        let feed_path = std::fs::canonicalize("dev/nginx/www/feed.xml").unwrap();
+        let url = Url::from_file_path(feed_path).unwrap();
+
+        let health = validate_feed(&HttpClientOptions::default(), &url).unwrap();
+
+        assert_eq!(health.format, FeedFormat::Rss);
+        assert_eq!(health.item_count, health.linked_item_count);
+        assert!(health.item_count > 0);
+    }
+
+    #[test]
+    fn should_warn_of_items_that_are_never_reported() {
+        let xml = "<rss version=\"2.0\"><channel><title>t</title><link>http://example.com</link><description>d</description>\
+<item><guid>1</guid><link>http://example.com/1</link><pubDate>yesterday</pubDate></item>\
+<item><guid>2</guid><link>http://example.com/1</link></item>\
+<item><title>no link</title></item>\
+</channel></rss>";
+        let feed = RssOrAtomFeed::Rss2(rss::Channel::read_from(xml.as_bytes()).unwrap());
+
+        assert_eq!(
+            FeedHealth::from(&feed).to_string(),
+            "format: rss\n\
+items: 3\n\
+items with valid links: 2\n\
+warning: 1 of 3 items have no valid link, so are never reported\n\
+warning: 1 of 3 items repeat the link of an earlier item, so are never reported\n\
+warning: 1 of 3 items have no guid or id, so are identified by their link\n\
+warning: 1 of 3 items have an unparseable publication date, so are treated as undated"
+        );
+    }
+}
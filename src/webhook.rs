@@ -0,0 +1,166 @@
+//! Delivers new links to a webhook as a single batched json request.
+
+use clap::ValueEnum;
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::output::NewItemsByFeed;
+use crate::{build_http_client, Error, HttpClientOptions};
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// a json array of objects associating each link, and its item's title,
+    /// with its feed.
+    #[default]
+    Generic,
+    /// a slack message, listing one link per line.
+    Slack,
+}
+
+/// A newly discovered link as delivered by a generic webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct WebhookItem<'a> {
+    feed: &'a str,
+    link: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+}
+
+/// A slack message payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct SlackMessage {
+    text: String,
+}
+
+/// Escapes the characters slack reserves for control sequences.
+fn escape_slack_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn generic_payload(new_links: &NewItemsByFeed) -> serde_json::Value {
+    let items: Vec<_> = new_links
+        .iter()
+        .flat_map(|(feed, items)| {
+            items.iter().map(move |item| WebhookItem {
+                feed,
+                link: &item.link,
+                title: item.title.as_deref(),
+            })
+        })
+        .collect();
+
+    serde_json::json!(items)
+}
+
+fn slack_payload(new_links: &NewItemsByFeed) -> serde_json::Value {
+    let lines: Vec<_> = new_links
+        .iter()
+        .flat_map(|(feed, items)| {
+            items.iter().map(move |item| {
+                let link = match &item.title {
+                    Some(title) => format!("<{}|{}>", item.link, escape_slack_text(title)),
+                    None => format!("<{}>", item.link),
+                };
+
+                format!("{}: {}", escape_slack_text(feed), link)
+            })
+        })
+        .collect();
+
+    serde_json::json!(SlackMessage {
+        text: lines.join("\n"),
+    })
+}
+
+/// Posts every new link to `url` in a single request. Nothing is sent when no
+/// feed has new links.
+pub fn post_new_links(
+    options: &HttpClientOptions,
+    url: &Url,
+    format: WebhookFormat,
+    new_links: &NewItemsByFeed,
+) -> Result<(), Error> {
+    if new_links.values().all(|items| items.is_empty()) {
+        return Ok(());
+    }
+
+    let payload = match format {
+        WebhookFormat::Generic => generic_payload(new_links),
+        WebhookFormat::Slack => slack_payload(new_links),
+    };
+
+    // the url is omitted from errors as webhook urls commonly embed a secret.
+    let webhook_error = |err| Error::from(err).with_data("webhook");
+    build_http_client(options)
+        .and_then(|client| client.post(url.as_str()).json(&payload).send())
+        .and_then(|resp| resp.error_for_status())
+        .map(|_| ())
+        .map_err(webhook_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::tests::{http_ok_response, serve_once};
+    use crate::FeedItem;
+
+    fn new_links() -> NewItemsByFeed {
+        NewItemsByFeed::from([
+            (
+                "a".to_string(),
+                BTreeSet::from([FeedItem {
+                    link: "http://example.com/1".to_string(),
+                    title: Some("first & <best>".to_string()),
+                    published: None,
+                    categories: vec![],
+                }]),
+            ),
+            (
+                "b".to_string(),
+                BTreeSet::from([FeedItem {
+                    link: "http://example.com/2".to_string(),
+                    title: None,
+                    published: None,
+                    categories: vec![],
+                }]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn should_build_generic_payload() {
+        assert_eq!(
+            generic_payload(&new_links()).to_string(),
+            "[{\"feed\":\"a\",\"link\":\"http://example.com/1\",\"title\":\"first & <best>\"},{\"feed\":\"b\",\"link\":\"http://example.com/2\"}]"
+        );
+    }
+
+    #[test]
+    fn should_build_slack_payload() {
+        assert_eq!(
+            slack_payload(&new_links()).to_string(),
+            "{\"text\":\"a: <http://example.com/1|first &amp; &lt;best&gt;>\\nb: <http://example.com/2>\"}"
+        );
+    }
+
+    #[test]
+    fn should_post_new_links_as_json() {
+        let (url, handle) = serve_once(http_ok_response(""));
+
+        post_new_links(
+            &HttpClientOptions::default(),
+            &url,
+            WebhookFormat::Generic,
+            &new_links(),
+        )
+        .unwrap();
+        let request = handle.join().unwrap().to_lowercase();
+
+        assert!(request.starts_with("post /feed.xml"));
+        assert!(request.contains("content-type: application/json"));
+    }
+}